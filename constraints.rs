@@ -0,0 +1,331 @@
+use crate::error::GameError;
+use crate::instruction::GameInstruction;
+use crate::state::{AUCTION_SEED, COMMIT_SEED, PLAYER_SEED, RENT_SEED, USER_SEED, TEAM_SIZE};
+use solana_program::{
+  account_info::AccountInfo,
+  entrypoint::ProgramResult,
+  program_error::ProgramError,
+  bpf_loader_upgradeable,
+  pubkey::Pubkey,
+  system_program,
+  sysvar,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Key {
+  Any,
+  SystemProgram,
+  TokenProgram,
+  AssociatedTokenProgram,
+  SlotHashes,
+  //programdata account of this program under the upgradeable loader
+  ProgramData,
+  Pda(&'static [Seed]),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Seed {
+  Bytes(&'static [u8]),
+  //key of the account at this index of the instruction
+  Account(usize),
+}
+
+//checked in Processor::process before the handler runs
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AccountConstraint {
+  pub signer: bool,
+  pub writable: bool,
+  pub program_owned: bool,
+  pub key: Key,
+}
+
+//no requirements, the other constraints are built from it
+pub const ANY: AccountConstraint = AccountConstraint {
+  signer: false,
+  writable: false,
+  program_owned: false,
+  key: Key::Any,
+};
+
+const SIGNER: AccountConstraint = ANY.signer();
+const PAYER: AccountConstraint = ANY.signer().writable();
+const MUT: AccountConstraint = ANY.writable();
+const STATE: AccountConstraint = ANY.owned().writable();
+const SYSTEM: AccountConstraint = ANY.key(Key::SystemProgram);
+const TOKEN: AccountConstraint = ANY.key(Key::TokenProgram);
+const ASSOCIATED_TOKEN: AccountConstraint = ANY.key(Key::AssociatedTokenProgram);
+const SLOT_HASHES: AccountConstraint = ANY.key(Key::SlotHashes);
+const PROGRAM_DATA: AccountConstraint = ANY.key(Key::ProgramData);
+
+impl AccountConstraint {
+  pub const fn signer(self) -> Self {
+    AccountConstraint { signer: true, ..self }
+  }
+
+  pub const fn writable(self) -> Self {
+    AccountConstraint { writable: true, ..self }
+  }
+
+  pub const fn owned(self) -> Self {
+    AccountConstraint { program_owned: true, ..self }
+  }
+
+  pub const fn key(self, key: Key) -> Self {
+    AccountConstraint { key, ..self }
+  }
+
+  pub fn check(&self, account: &AccountInfo, accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    if self.signer && !account.is_signer{return Err(GameError::MissingSignature.into())}
+    if self.writable && !account.is_writable{return Err(GameError::NotWritable.into())}
+    if self.program_owned && account.owner != program_id{return Err(GameError::IncorrectProgramOwner.into())}
+
+    let expected: Pubkey = match self.key {
+      Key::Any => return Ok(()),
+      Key::SystemProgram => system_program::id(),
+      Key::TokenProgram => spl_token::id(),
+      Key::AssociatedTokenProgram => spl_associated_token_account::id(),
+      Key::SlotHashes => sysvar::slot_hashes::id(),
+      Key::ProgramData => Pubkey::find_program_address(&[program_id.as_ref()], &bpf_loader_upgradeable::id()).0,
+      Key::Pda(seeds) => {
+        let seeds: Vec<&[u8]> = seeds.iter().map(|seed| match seed {
+          Seed::Bytes(bytes) => *bytes,
+          Seed::Account(index) => accounts[*index].key.as_ref(),
+        }).collect();
+
+        let (pda, _) = Pubkey::find_program_address(&seeds, program_id);
+
+        if account.key != &pda{return Err(GameError::InvalidPda.into())}
+
+        return Ok(())
+      }
+    };
+
+    if account.key != &expected{return Err(GameError::UnexpectedAccount.into())}
+
+    Ok(())
+  }
+}
+
+const REGISTER_USER: &[AccountConstraint] = &[PAYER, MUT, SYSTEM, MUT];
+
+const GENERATE_PLAYER: &[AccountConstraint] = &[
+  PAYER,
+  ANY,
+  MUT,
+  ANY,
+  MUT.key(Key::Pda(&[Seed::Bytes(COMMIT_SEED), Seed::Account(0), Seed::Account(3)])),
+  SLOT_HASHES,
+  SYSTEM,
+];
+
+const BUY_PLAYER: &[AccountConstraint] = &[
+  PAYER,
+  STATE.key(Key::Pda(&[Seed::Bytes(PLAYER_SEED), Seed::Account(2)])),
+  ANY,
+  MUT,
+  SYSTEM,
+  TOKEN,
+  ANY,
+  MUT,
+  MUT,
+  ANY,
+  MUT,
+  MUT,
+];
+
+const RENT_ANOTHER_PLAYER: &[AccountConstraint] = &[
+  PAYER,
+  STATE,
+  MUT,
+  ANY,
+  MUT,
+  MUT.key(Key::Pda(&[Seed::Bytes(RENT_SEED), Seed::Account(1)])),
+  SYSTEM,
+  MUT,
+];
+
+const SELL_PLAYER: &[AccountConstraint] = &[
+  PAYER,
+  STATE.key(Key::Pda(&[Seed::Bytes(PLAYER_SEED), Seed::Account(2)])),
+  ANY,
+  MUT,
+  ANY,
+  MUT,
+  TOKEN,
+  SYSTEM,
+  ASSOCIATED_TOKEN,
+  MUT,
+];
+
+const RENT_YOUR_PLAYER: &[AccountConstraint] = &[PAYER, STATE, MUT, SYSTEM];
+
+const SET_TEAM: &[AccountConstraint] = &[SIGNER, STATE, STATE, STATE, STATE, STATE, STATE];
+
+const CHALLENGE: &[AccountConstraint] = &[
+  PAYER,
+  STATE,
+  STATE,
+  MUT,
+  SYSTEM,
+  MUT,
+  SLOT_HASHES,
+  ANY,
+  ANY,
+  ANY,
+  ANY,
+  ANY,
+  ANY,
+  ANY,
+  ANY,
+  ANY,
+  ANY,
+];
+
+const ABORT_RENT: &[AccountConstraint] = &[SIGNER, STATE, MUT];
+
+const ABORT_SELL: &[AccountConstraint] = &[PAYER, STATE, TOKEN, ANY, MUT, MUT, MUT];
+
+const CLAIM_RENT_OVER_PLAYER: &[AccountConstraint] = &[
+  PAYER,
+  STATE,
+  MUT.key(Key::Pda(&[Seed::Bytes(RENT_SEED), Seed::Account(1)])),
+  MUT,
+];
+
+const CLAIM_NFT_PLAYER: &[AccountConstraint] = &[SIGNER, ANY, STATE, ANY];
+
+const CREATE_PDA: &[AccountConstraint] = &[MUT, PAYER, SYSTEM];
+
+const UPGRADE_USER: AccountConstraint = STATE.key(Key::Pda(&[Seed::Bytes(USER_SEED), Seed::Account(0)]));
+
+//only the player of the upgraded slot is written
+const fn upgrade_constraints(slot: usize) -> [AccountConstraint; 2 + TEAM_SIZE] {
+  let mut constraints: [AccountConstraint; 2 + TEAM_SIZE] = [SIGNER, UPGRADE_USER, ANY.owned(), ANY.owned(), ANY.owned(), ANY.owned(), ANY.owned()];
+  constraints[2 + slot] = STATE;
+  constraints
+}
+
+static UPGRADE: [[AccountConstraint; 2 + TEAM_SIZE]; TEAM_SIZE] = [
+  upgrade_constraints(0),
+  upgrade_constraints(1),
+  upgrade_constraints(2),
+  upgrade_constraints(3),
+  upgrade_constraints(4),
+];
+
+const INIT_CONFIG: &[AccountConstraint] = &[PAYER, MUT, SYSTEM, PROGRAM_DATA];
+
+const UPDATE_CONFIG: &[AccountConstraint] = &[SIGNER, STATE];
+
+const CREATE_AUCTION: &[AccountConstraint] = &[
+  PAYER,
+  STATE,
+  MUT.key(Key::Pda(&[Seed::Bytes(AUCTION_SEED), Seed::Account(1)])),
+  ANY,
+  MUT,
+  ANY,
+  MUT,
+  TOKEN,
+  SYSTEM,
+  ASSOCIATED_TOKEN,
+];
+
+const PLACE_BID: &[AccountConstraint] = &[PAYER, STATE, MUT, SYSTEM];
+
+const SETTLE_AUCTION: &[AccountConstraint] = &[
+  PAYER,
+  STATE,
+  STATE,
+  ANY,
+  MUT,
+  ANY,
+  MUT,
+  ANY,
+  MUT,
+  ANY,
+  MUT,
+  TOKEN,
+  SYSTEM,
+  ASSOCIATED_TOKEN,
+];
+
+const CANCEL_AUCTION: &[AccountConstraint] = &[PAYER, STATE, STATE, ANY, MUT, MUT, TOKEN];
+
+const COMMIT_RANDOMNESS: &[AccountConstraint] = &[PAYER, MUT, SYSTEM];
+
+const MIGRATE_ACCOUNT: &[AccountConstraint] = &[PAYER, STATE, SYSTEM];
+
+//users registered before the name registry may have no record at the old name
+const RENAME_USER: &[AccountConstraint] = &[PAYER, STATE, MUT, MUT, SYSTEM];
+
+//nothing to close at the record address for users registered before the registry
+const CLOSE_USER: &[AccountConstraint] = &[SIGNER, STATE, MUT, MUT];
+
+const CLOSE_PLAYER: &[AccountConstraint] = &[SIGNER, STATE, MUT];
+
+const RELEASE_RENT: &[AccountConstraint] = &[ANY.owned(), MUT, MUT];
+
+const TERMINATE_RENT: &[AccountConstraint] = &[PAYER, STATE, MUT, MUT, MUT];
+
+const RENEW_RENT: &[AccountConstraint] = &[STATE, MUT, MUT, MUT];
+
+//closed players stay in the team slots, so they are not required to be program owned
+const REFRESH_TEAM: &[AccountConstraint] = &[STATE, ANY, ANY, ANY, ANY, ANY];
+
+const CANCEL_COMMIT: &[AccountConstraint] = &[PAYER, STATE, ANY, MUT];
+
+const TRANSFER_ADMIN: &[AccountConstraint] = &[SIGNER, STATE, SIGNER];
+
+//in the order the handler reads them, trailing accounts that depend on
+//the instruction data like token accounts or team players are checked by the handler
+pub fn account_constraints(instruction: &GameInstruction) -> &'static [AccountConstraint] {
+  match instruction {
+    GameInstruction::RegisterUser{..} => REGISTER_USER,
+    GameInstruction::GeneratePlayer{..} => GENERATE_PLAYER,
+    GameInstruction::BuyPlayer{..} => BUY_PLAYER,
+    GameInstruction::RentAnotherPlayer{..} => RENT_ANOTHER_PLAYER,
+    GameInstruction::SellPlayer{..} => SELL_PLAYER,
+    GameInstruction::RentYourPlayer{..} => RENT_YOUR_PLAYER,
+    GameInstruction::SetTeam{..} => SET_TEAM,
+    GameInstruction::Challenge{..} => CHALLENGE,
+    GameInstruction::AbortRent => ABORT_RENT,
+    GameInstruction::AbortSell => ABORT_SELL,
+    GameInstruction::ClaimRentOverPlayer => CLAIM_RENT_OVER_PLAYER,
+    GameInstruction::ClaimNFTPlayer => CLAIM_NFT_PLAYER,
+    GameInstruction::CreatePDA => CREATE_PDA,
+    //the handler rejects slots outside the team, only the fixed accounts are checked for them
+    GameInstruction::Upgrade{upgrade} => match UPGRADE.get(upgrade.slot as usize) {
+      Some(constraints) => constraints,
+      None => &UPGRADE[0][..2],
+    },
+    GameInstruction::InitConfig{..} => INIT_CONFIG,
+    GameInstruction::UpdateConfig{..} => UPDATE_CONFIG,
+    GameInstruction::CreateAuction{..} => CREATE_AUCTION,
+    GameInstruction::PlaceBid{..} => PLACE_BID,
+    GameInstruction::SettleAuction => SETTLE_AUCTION,
+    GameInstruction::CancelAuction => CANCEL_AUCTION,
+    GameInstruction::CommitRandomness{..} => COMMIT_RANDOMNESS,
+    GameInstruction::MigrateAccount{..} => MIGRATE_ACCOUNT,
+    GameInstruction::RenameUser{..} => RENAME_USER,
+    GameInstruction::CloseUser => CLOSE_USER,
+    GameInstruction::ClosePlayer => CLOSE_PLAYER,
+    GameInstruction::ReleaseRent => RELEASE_RENT,
+    GameInstruction::TerminateRent => TERMINATE_RENT,
+    GameInstruction::RenewRent => RENEW_RENT,
+    GameInstruction::RefreshTeam => REFRESH_TEAM,
+    GameInstruction::CancelCommit => CANCEL_COMMIT,
+    GameInstruction::TransferAdmin => TRANSFER_ADMIN,
+  }
+}
+
+pub fn check_accounts(instruction: &GameInstruction, accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+  let constraints: &[AccountConstraint] = account_constraints(instruction);
+
+  if accounts.len() < constraints.len(){return Err(ProgramError::NotEnoughAccountKeys)}
+
+  for (account, constraint) in accounts.iter().zip(constraints) {
+    constraint.check(account, accounts, program_id)?;
+  }
+
+  Ok(())
+}
@@ -1,6 +1,8 @@
+use crate::error::GameError;
 use crate::processor::Processor;
 use solana_program::{
-  account_info::AccountInfo, entrypoint, entrypoint::ProgramResult, pubkey::Pubkey,
+  account_info::AccountInfo, entrypoint, entrypoint::ProgramResult,
+  program_error::PrintProgramError, pubkey::Pubkey,
 };
 
 entrypoint!(process_instruction);
@@ -9,5 +11,9 @@ fn process_instruction(
   accounts: &[AccountInfo],
   instruction_data: &[u8],
 ) -> ProgramResult {
-  Processor::process(program_id, accounts, instruction_data)
+  if let Err(error) = Processor::process(program_id, accounts, instruction_data) {
+    error.print::<GameError>();
+    return Err(error);
+  }
+  Ok(())
 }
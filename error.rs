@@ -1,18 +1,252 @@
-use solana_program::program_error::ProgramError;
+//the derived impls still match on deprecated variants
+#![allow(deprecated)]
+
+use num_derive::FromPrimitive;
+use num_traits::FromPrimitive;
+use solana_program::{
+  decode_error::DecodeError,
+  msg,
+  program_error::{PrintProgramError, ProgramError},
+};
 use thiserror::Error;
 
-#[derive(Error, Debug, Copy, Clone)]
-pub enum MailError {
+#[derive(Error, Debug, Copy, Clone, PartialEq, Eq, FromPrimitive)]
+pub enum GameError {
   /// Invalid Instruction
   #[error("Invalid Instruction")]
-  InvalidInstruction,
+  InvalidInstruction = 0,
   /// Account Is Not Writable
   #[error("Account Is Not Writable")]
-  NotWritable,
+  NotWritable = 1,
+  /// Account Is Not A Signer
+  #[error("Account Is Not A Signer")]
+  MissingSignature = 2,
+  /// Account Is Not Owned By The Program
+  #[error("Account Is Not Owned By The Program")]
+  IncorrectProgramOwner = 3,
+  /// User Account Does Not Match The Derived Address
+  #[error("User Account Does Not Match The Derived Address")]
+  InvalidUserAccount = 4,
+  /// User Name Is Longer Than 10 Characters
+  #[error("User Name Is Longer Than 10 Characters")]
+  UserNameTooLong = 5,
+  /// Token Account Is Not Owned By The Token Program
+  #[error("Token Account Is Not Owned By The Token Program")]
+  InvalidTokenAccount = 6,
+  /// Token Account Is Not Owned By The User
+  #[error("Token Account Is Not Owned By The User")]
+  TokenAccountOwnerMismatch = 7,
+  /// Token Account Does Not Hold The NFT
+  #[error("Token Account Does Not Hold The NFT")]
+  NftNotHeld = 8,
+  /// NFT Mint Does Not Match
+  #[error("NFT Mint Does Not Match")]
+  NftMintMismatch = 9,
+  /// Signer Is Not The Owner Of The Player
+  #[error("Signer Is Not The Owner Of The Player")]
+  NotPlayerOwner = 10,
+  /// Not Enough Lamports To Pay The Price
+  #[error("Not Enough Lamports To Pay The Price")]
+  InsufficientFunds = 11,
+  /// Player Is Not For Sale
+  #[error("Player Is Not For Sale")]
+  PlayerNotForSale = 12,
+  /// Player Is Not For Rent
+  #[error("Player Is Not For Rent")]
+  PlayerNotForRent = 13,
+  /// Player Is Rented
+  #[error("Player Is Rented")]
+  PlayerRented = 14,
+  /// Signer Is Not The Owner Of The User Account
+  #[error("Signer Is Not The Owner Of The User Account")]
+  NotAccountOwner = 15,
+  /// Player Is Not Controlled By The User
+  #[error("Player Is Not Controlled By The User")]
+  PlayerNotControlled = 16,
+  /// Player Is Not Available For The Team
+  ///
+  /// No longer emitted, `PlayerStatus` transitions return `IllegalStatusTransition`.
+  #[deprecated(note = "no longer emitted, kept so the error codes stay stable")]
+  #[error("Player Is Not Available For The Team")]
+  PlayerNotAvailable = 17,
+  /// Rent Period Is Not Over
+  #[error("Rent Period Is Not Over")]
+  RentNotOver = 18,
+  /// Not Enough Experience
+  #[error("Not Enough Experience")]
+  NotEnoughExperience = 19,
+  /// Player Is Not In The Team Slot
+  #[error("Player Is Not In The Team Slot")]
+  PlayerNotInTeam = 20,
+  /// Config Account Does Not Match The Derived Address
+  #[error("Config Account Does Not Match The Derived Address")]
+  InvalidConfig = 21,
+  /// Fee Is Above 10000 Basis Points
+  #[error("Fee Is Above 10000 Basis Points")]
+  InvalidFee = 22,
+  /// Signer Is Not The Admin
+  #[error("Signer Is Not The Admin")]
+  NotAdmin = 23,
+  /// Treasury Does Not Match The Config
+  #[error("Treasury Does Not Match The Config")]
+  TreasuryMismatch = 24,
+  /// PDA Does Not Match The Derived Address
+  #[error("PDA Does Not Match The Derived Address")]
+  InvalidPda = 25,
+  /// Escrow Account Is Not The PDA Associated Token Account
+  #[error("Escrow Account Is Not The PDA Associated Token Account")]
+  InvalidEscrowAccount = 26,
+  /// Player Is In An Auction
+  #[error("Player Is In An Auction")]
+  PlayerInAuction = 27,
+  /// Auction Has Ended
+  #[error("Auction Has Ended")]
+  AuctionEnded = 28,
+  /// Auction Has Not Ended
+  #[error("Auction Has Not Ended")]
+  AuctionNotEnded = 29,
+  /// Bid Is Below The Reserve Or Highest Bid
+  #[error("Bid Is Below The Reserve Or Highest Bid")]
+  BidTooLow = 30,
+  /// Auction Already Has Bids
+  #[error("Auction Already Has Bids")]
+  AuctionHasBids = 31,
+  /// Auction Has No Bids
+  #[error("Auction Has No Bids")]
+  AuctionHasNoBids = 32,
+  /// Auction Account Does Not Match The Derived Address
+  #[error("Auction Account Does Not Match The Derived Address")]
+  InvalidAuction = 33,
+  /// Account Is Not The Highest Bidder
+  #[error("Account Is Not The Highest Bidder")]
+  BidderMismatch = 34,
+  /// Payment Mint Does Not Match The Listing
+  #[error("Payment Mint Does Not Match The Listing")]
+  PaymentMintMismatch = 35,
+  /// Payment Token Account Is Invalid
+  #[error("Payment Token Account Is Invalid")]
+  InvalidPaymentAccount = 36,
+  /// Match Record Does Not Match The Derived Address
+  #[error("Match Record Does Not Match The Derived Address")]
+  InvalidMatchRecord = 37,
+  /// Randomness Commit Is Invalid
+  #[error("Randomness Commit Is Invalid")]
+  InvalidCommit = 38,
+  /// Revealed Secret Does Not Match The Commitment
+  #[error("Revealed Secret Does Not Match The Commitment")]
+  CommitmentMismatch = 39,
+  /// No Slot Hash After The Commit Yet
+  #[error("No Slot Hash After The Commit Yet")]
+  RevealTooEarly = 40,
+  /// Commit Is Older Than The Slot Hash History
+  #[error("Commit Is Older Than The Slot Hash History")]
+  CommitExpired = 41,
+  /// Account Data Is Not The Expected Type
+  #[error("Account Data Is Not The Expected Type")]
+  InvalidAccountType = 42,
+  /// Account Layout Version Is Not Supported
+  #[error("Account Layout Version Is Not Supported")]
+  UnsupportedAccountVersion = 43,
+  /// Player Account Does Not Match The Derived Address
+  #[error("Player Account Does Not Match The Derived Address")]
+  InvalidPlayerAccount = 44,
+  /// User Name Is Shorter Than 3 Characters
+  #[error("User Name Is Shorter Than 3 Characters")]
+  UserNameTooShort = 45,
+  /// User Name May Only Contain Letters, Digits And Underscores
+  #[error("User Name May Only Contain Letters, Digits And Underscores")]
+  InvalidUserNameCharacter = 46,
+  /// User Name Is Already Taken
+  #[error("User Name Is Already Taken")]
+  UserNameTaken = 47,
+  /// Username Record Does Not Match The Derived Address
+  #[error("Username Record Does Not Match The Derived Address")]
+  InvalidUsernameRecord = 48,
+  /// Player Is Listed For Sale, Rent Or Auction
+  #[error("Player Is Listed For Sale, Rent Or Auction")]
+  PlayerListed = 49,
+  /// Player Is Part Of A Team
+  #[error("Player Is Part Of A Team")]
+  PlayerInTeam = 50,
+  /// Rent Cannot Be Refunded To The Closed Account
+  #[error("Rent Cannot Be Refunded To The Closed Account")]
+  InvalidDestination = 51,
+  /// Rent Escrow Does Not Match The Rented Player
+  #[error("Rent Escrow Does Not Match The Rented Player")]
+  InvalidRentEscrow = 52,
+  /// Player Is Not Rented
+  #[error("Player Is Not Rented")]
+  PlayerNotRented = 53,
+  /// Signer Is Neither The Owner Nor The Renter
+  #[error("Signer Is Neither The Owner Nor The Renter")]
+  NotRentParty = 54,
+  /// Rent Must Cover One Period Plus At Most The Allowed Renewals
+  #[error("Rent Must Cover One Period Plus At Most The Allowed Renewals")]
+  InvalidRentPeriods = 55,
+  /// Listing Book Does Not Match The Listing
+  #[error("Listing Book Does Not Match The Listing")]
+  InvalidListingBook = 56,
+  /// Listing Page Is Full
+  #[error("Listing Page Is Full")]
+  ListingPageFull = 57,
+  /// Player Is Not In The Listing Book
+  #[error("Player Is Not In The Listing Book")]
+  ListingNotFound = 58,
+  /// Player Can Not Take This Action In Its Current Status
+  #[error("Player Can Not Take This Action In Its Current Status")]
+  IllegalStatusTransition = 59,
+  /// A Player Of The Team Was Not Passed
+  #[error("A Player Of The Team Was Not Passed")]
+  TeamPlayerMissing = 60,
+  /// Account Is Not The Expected Program Or Sysvar
+  #[error("Account Is Not The Expected Program Or Sysvar")]
+  UnexpectedAccount = 61,
+  /// Listing Costs More Than The Maximum Price
+  #[error("Listing Costs More Than The Maximum Price")]
+  PriceAboveMaximum = 62,
+  /// Player Is In The Team More Than Once
+  #[error("Player Is In The Team More Than Once")]
+  DuplicatePlayer = 63,
+  /// Team Slot Does Not Exist In The Formation
+  #[error("Team Slot Does Not Exist In The Formation")]
+  InvalidTeamSlot = 64,
+  /// Team Is Not Ready
+  #[error("Team Is Not Ready")]
+  TeamNotReady = 65,
+  /// User No Longer Controls A Player Of The Team
+  #[error("User No Longer Controls A Player Of The Team")]
+  TeamNotEligible = 66,
+  /// User Can Not Challenge Itself
+  #[error("User Can Not Challenge Itself")]
+  SelfChallenge = 67,
+  /// Program Data Account Is Invalid Or Has No Upgrade Authority
+  #[error("Program Data Account Is Invalid Or Has No Upgrade Authority")]
+  InvalidProgramData = 68,
+  /// Auction Duration Is Too Long
+  #[error("Auction Duration Is Too Long")]
+  InvalidAuctionDuration = 69,
+  /// Player Stat Would Overflow
+  #[error("Player Stat Would Overflow")]
+  StatOverflow = 70,
 }
 
-impl From<MailError> for ProgramError {
-  fn from(e: MailError) -> Self {
+impl From<GameError> for ProgramError {
+  fn from(e: GameError) -> Self {
     ProgramError::Custom(e as u32)
   }
 }
+
+impl<T> DecodeError<T> for GameError {
+  fn type_of() -> &'static str {
+    "GameError"
+  }
+}
+
+impl PrintProgramError for GameError {
+  fn print<E>(&self)
+  where
+    E: 'static + std::error::Error + DecodeError<E> + PrintProgramError + FromPrimitive,
+  {
+    msg!("Error: {}", &self.to_string());
+  }
+}
@@ -1,25 +1,51 @@
-use crate::error::MailError::InvalidInstruction;
-use crate::state::{UserName,SellOffer,RentOffer,UpgradePlayer};
-use borsh::BorshDeserialize;
-use solana_program::program_error::ProgramError;
+use crate::error::GameError::InvalidInstruction;
+use crate::state::{UserName,SellOffer,RentOffer,BuyRequest,RentRequest,UpgradePlayer,ConfigData,USER_SEED,PLAYER_SEED,CONFIG_SEED,VAULT_SEED,
+  AuctionOffer,Bid,ChallengeData,CommitData,Reveal,AccountType,AUCTION_SEED,MATCH_SEED,COMMIT_SEED,USERNAME_SEED,RENT_SEED,
+  ListingBook,ListingKind,LISTING_SEED,Formation,TEAM_SIZE};
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+  instruction::{AccountMeta, Instruction},
+  program_error::ProgramError,
+  pubkey::Pubkey,
+};
+use spl_associated_token_account::get_associated_token_address;
 
+//accounts of every instruction in the order the handler reads them, the builders below
+//pass them this way and constraints.rs checks the leading ones
 #[derive(Debug, PartialEq)]
 pub enum GameInstruction {
 
-  RegisterUser{username: UserName},//
-  GeneratePlayer,//user account
-  BuyPlayer,//player account, user account, seller
-  RentAnotherPlayer,//player account, user account, seller
-  SellPlayer{selloffer : SellOffer},//player account, user account, 
-  RentYourPlayer{rentoffer : RentOffer},//player account, user account, owner
-  SetTeam,//user account, player address, nft adresses, //team formation
-  Challenge,//player account opponent account
-  AbortRent,//player account,user account
-  AbortSell,//player account, user account
-  ClaimRentOverPlayer,
-  ClaimNFTPlayer,
-  CreatePDA,
-  Upgrade{upgrade:UpgradePlayer},
+  RegisterUser{username: UserName},//user, user account pda, system program, username record
+  GeneratePlayer{reveal:Reveal},//user, user ata, player pda of the nft, nft, commit for the nft, slot hashes, system program
+  BuyPlayer{buy:BuyRequest},//buyer, player, nft, seller, system program, token program, vault pda, vault ata, buyer ata, config, treasury, listing book, token payment accounts
+  RentAnotherPlayer{rent:RentRequest},//renter, player, owner, config, treasury, rent escrow, system program, listing book, token payment accounts
+  SellPlayer{selloffer : SellOffer},//owner, player, nft, owner ata, vault pda, vault ata, token program, system program, associated token program, listing book
+  RentYourPlayer{rentoffer : RentOffer},//owner, player, listing book, system program
+  SetTeam{formation:Formation},//user, user account, players of the formation slots, players leaving the team
+  Challenge{challengedata:ChallengeData},//user, user account, opponent account, match record, system program, commit for the opponent, slot hashes, players of the team slots, players of the opponent team slots
+  AbortRent,//owner, player, listing book
+  AbortSell,//owner, player, token program, vault pda, vault ata, owner ata, listing book
+  ClaimRentOverPlayer,//owner, player, rent escrow, renter, token payout accounts
+  ClaimNFTPlayer,//user, user ata, player, nft
+  CreatePDA,//vault pda, payer, system program
+  Upgrade{upgrade:UpgradePlayer},//user, user account pda, players of the team slots
+  InitConfig{config:ConfigData},//admin, config pda, system program, program data
+  UpdateConfig{config:ConfigData},//admin, config pda
+  CreateAuction{auctionoffer:AuctionOffer},//seller, player, auction pda, nft, seller ata, vault pda, vault ata, token program, system program, associated token program
+  PlaceBid{bid:Bid},//bidder, auction pda, previous bidder, system program
+  SettleAuction,//payer, auction pda, player, nft, seller, winner, winner ata, vault pda, vault ata, config, treasury, token program, system program, associated token program
+  CancelAuction,//seller, auction pda, player, vault pda, vault ata, seller ata, token program
+  CommitRandomness{commit:CommitData},//user, commit pda, system program
+  MigrateAccount{account_type:AccountType},//payer, user account or player, system program
+  RenameUser{username: UserName},//user, user account pda, old username record, new username record, system program
+  CloseUser,//user, user account pda, username record, destination, team players
+  ClosePlayer,//owner, player, destination
+  ReleaseRent,//player, rent escrow, owner, token payout accounts of the owner
+  TerminateRent,//owner or renter, player, rent escrow, owner, renter, token payout accounts
+  RenewRent,//player, rent escrow, owner, renter, token payout accounts
+  RefreshTeam,//user account, players of the team slots
+  CancelCommit,//user, commit pda, config, treasury
+  TransferAdmin,//admin, config pda, new admin
 
 }
 
@@ -29,28 +55,1089 @@ impl GameInstruction {
     
     Ok(match tag {
       0 => Self::RegisterUser{
-        username: UserName::try_from_slice(&rest)?,
+        username: UserName::try_from_slice(rest)?,
+      },
+      1 => Self::GeneratePlayer{
+        reveal: Reveal::try_from_slice(rest)?,
+      },
+      3 => Self::BuyPlayer{
+        buy: BuyRequest::try_from_slice(rest)?,
+      },
+      4 => Self::RentAnotherPlayer{
+        rent: RentRequest::try_from_slice(rest)?,
       },
-      1 => Self::GeneratePlayer,
-      3 => Self::BuyPlayer,
-      4 => Self::RentAnotherPlayer,
       5 => Self::SellPlayer{
-        selloffer: SellOffer::try_from_slice(&rest)?,
+        selloffer: SellOffer::try_from_slice(rest)?,
       },
       6 => Self::RentYourPlayer{
-        rentoffer: RentOffer::try_from_slice(&rest)?,
+        rentoffer: RentOffer::try_from_slice(rest)?,
+      },
+      7 => Self::SetTeam{
+        formation: Formation::try_from_slice(rest)?,
+      },
+      8 => Self::Challenge{
+        challengedata: ChallengeData::try_from_slice(rest)?,
       },
-      7 => Self::SetTeam,
-      8 => Self::Challenge,
       9 => Self::AbortRent,
       10 => Self::AbortSell,
       11 => Self::ClaimRentOverPlayer,
       12 => Self::ClaimNFTPlayer,
       13 => Self::CreatePDA,
       14 => Self::Upgrade{
-        upgrade: UpgradePlayer::try_from_slice(&rest)?,
+        upgrade: UpgradePlayer::try_from_slice(rest)?,
+      },
+      15 => Self::InitConfig{
+        config: ConfigData::try_from_slice(rest)?,
+      },
+      16 => Self::UpdateConfig{
+        config: ConfigData::try_from_slice(rest)?,
+      },
+      17 => Self::CreateAuction{
+        auctionoffer: AuctionOffer::try_from_slice(rest)?,
+      },
+      18 => Self::PlaceBid{
+        bid: Bid::try_from_slice(rest)?,
+      },
+      19 => Self::SettleAuction,
+      20 => Self::CancelAuction,
+      21 => Self::CommitRandomness{
+        commit: CommitData::try_from_slice(rest)?,
       },
+      22 => Self::MigrateAccount{
+        account_type: AccountType::try_from_slice(rest)?,
+      },
+      23 => Self::RenameUser{
+        username: UserName::try_from_slice(rest)?,
+      },
+      24 => Self::CloseUser,
+      25 => Self::ClosePlayer,
+      26 => Self::ReleaseRent,
+      27 => Self::TerminateRent,
+      28 => Self::RenewRent,
+      29 => Self::RefreshTeam,
+      30 => Self::CancelCommit,
+      31 => Self::TransferAdmin,
       _ => return Err(InvalidInstruction.into()),
     })
   }
+
+  //borsh only fails when the writer does and writing to a Vec cannot fail, so pack returns
+  //the bytes directly instead of a Result every builder would have to pass on
+  fn pack_data<T: BorshSerialize>(buf: &mut Vec<u8>, data: &T) {
+    data.serialize(buf).expect("writing to a Vec cannot fail");
+  }
+
+  pub fn pack(&self) -> Vec<u8> {
+    let mut buf: Vec<u8> = Vec::new();
+
+    match self {
+      Self::RegisterUser{username} => {
+        buf.push(0);
+        Self::pack_data(&mut buf, username);
+      }
+      Self::GeneratePlayer{reveal} => {
+        buf.push(1);
+        Self::pack_data(&mut buf, reveal);
+      }
+      Self::BuyPlayer{buy} => {
+        buf.push(3);
+        Self::pack_data(&mut buf, buy);
+      }
+      Self::RentAnotherPlayer{rent} => {
+        buf.push(4);
+        Self::pack_data(&mut buf, rent);
+      }
+      Self::SellPlayer{selloffer} => {
+        buf.push(5);
+        Self::pack_data(&mut buf, selloffer);
+      }
+      Self::RentYourPlayer{rentoffer} => {
+        buf.push(6);
+        Self::pack_data(&mut buf, rentoffer);
+      }
+      Self::SetTeam{formation} => {
+        buf.push(7);
+        Self::pack_data(&mut buf, formation);
+      }
+      Self::Challenge{challengedata} => {
+        buf.push(8);
+        Self::pack_data(&mut buf, challengedata);
+      }
+      Self::AbortRent => buf.push(9),
+      Self::AbortSell => buf.push(10),
+      Self::ClaimRentOverPlayer => buf.push(11),
+      Self::ClaimNFTPlayer => buf.push(12),
+      Self::CreatePDA => buf.push(13),
+      Self::Upgrade{upgrade} => {
+        buf.push(14);
+        Self::pack_data(&mut buf, upgrade);
+      }
+      Self::InitConfig{config} => {
+        buf.push(15);
+        Self::pack_data(&mut buf, config);
+      }
+      Self::UpdateConfig{config} => {
+        buf.push(16);
+        Self::pack_data(&mut buf, config);
+      }
+      Self::CreateAuction{auctionoffer} => {
+        buf.push(17);
+        Self::pack_data(&mut buf, auctionoffer);
+      }
+      Self::PlaceBid{bid} => {
+        buf.push(18);
+        Self::pack_data(&mut buf, bid);
+      }
+      Self::SettleAuction => buf.push(19),
+      Self::CancelAuction => buf.push(20),
+      Self::CommitRandomness{commit} => {
+        buf.push(21);
+        Self::pack_data(&mut buf, commit);
+      }
+      Self::MigrateAccount{account_type} => {
+        buf.push(22);
+        Self::pack_data(&mut buf, account_type);
+      }
+      Self::RenameUser{username} => {
+        buf.push(23);
+        Self::pack_data(&mut buf, username);
+      }
+      Self::CloseUser => buf.push(24),
+      Self::ClosePlayer => buf.push(25),
+      Self::ReleaseRent => buf.push(26),
+      Self::TerminateRent => buf.push(27),
+      Self::RenewRent => buf.push(28),
+      Self::RefreshTeam => buf.push(29),
+      Self::CancelCommit => buf.push(30),
+      Self::TransferAdmin => buf.push(31),
+    }
+    buf
+  }
+}
+
+/// Token accounts used when a listing is priced in an SPL token.
+/// Each account must hold the listing's payment mint.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TokenPayment {
+  pub payer_token_account: Pubkey,
+  pub seller_token_account: Pubkey,
+  pub treasury_token_account: Pubkey,
+}
+
+impl TokenPayment {
+  fn account_metas(&self) -> Vec<AccountMeta> {
+    vec![
+      AccountMeta::new(self.payer_token_account, false),
+      AccountMeta::new(self.seller_token_account, false),
+      AccountMeta::new(self.treasury_token_account, false),
+    ]
+  }
+}
+
+/// Token accounts used when a rental is priced in an SPL token. The rent is
+/// held in the associated token account of the player's `RentEscrow`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RentTokenPayment {
+  pub payment_mint: Pubkey,
+  pub payer_token_account: Pubkey,
+  pub treasury_token_account: Pubkey,
+}
+
+/// Token accounts that receive escrowed rent priced in an SPL token.
+/// `ReleaseRent` only pays the owner.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RentPayout {
+  pub payment_mint: Pubkey,
+  pub owner_token_account: Pubkey,
+  pub renter_token_account: Pubkey,
+}
+
+impl RentPayout {
+  fn account_metas(&self, escrow: &Pubkey) -> Vec<AccountMeta> {
+    vec![
+      AccountMeta::new_readonly(spl_token::id(), false),
+      AccountMeta::new(get_associated_token_address(escrow, &self.payment_mint), false),
+      AccountMeta::new(self.owner_token_account, false),
+      AccountMeta::new(self.renter_token_account, false),
+    ]
+  }
+}
+
+/// Returns the address of the `UserAccount` of a wallet.
+pub fn find_user_address(program_id: &Pubkey, user: &Pubkey) -> Pubkey {
+  Pubkey::find_program_address(&[USER_SEED, &user.to_bytes()], program_id).0
+}
+
+/// Returns the address of the `UsernameRecord` of a name. Names are
+/// compared lowercase, so "Keeper" and "keeper" share one record.
+pub fn find_username_address(program_id: &Pubkey, user_name: &str) -> Pubkey {
+  Pubkey::find_program_address(&[USERNAME_SEED, user_name.to_ascii_lowercase().as_bytes()], program_id).0
+}
+
+/// Returns the address of the `Player` generated from an NFT mint.
+pub fn find_player_address(program_id: &Pubkey, nft: &Pubkey) -> Pubkey {
+  Pubkey::find_program_address(&[PLAYER_SEED, &nft.to_bytes()], program_id).0
+}
+
+/// Returns the address of the global `GameConfig` account.
+pub fn find_config_address(program_id: &Pubkey) -> Pubkey {
+  Pubkey::find_program_address(&[CONFIG_SEED], program_id).0
+}
+
+/// Returns the address of the `ProgramData` account of a program deployed
+/// with the upgradeable loader.
+pub fn find_program_data_address(program_id: &Pubkey) -> Pubkey {
+  Pubkey::find_program_address(&[program_id.as_ref()], &solana_program::bpf_loader_upgradeable::id()).0
+}
+
+/// Returns the address of the "battleballs" PDA that holds escrowed NFTs.
+pub fn find_vault_address(program_id: &Pubkey) -> Pubkey {
+  Pubkey::find_program_address(&[VAULT_SEED], program_id).0
+}
+
+/// Returns the address of the `RentEscrow` holding the rent of a player.
+pub fn find_rent_escrow_address(program_id: &Pubkey, player: &Pubkey) -> Pubkey {
+  Pubkey::find_program_address(&[RENT_SEED, &player.to_bytes()], program_id).0
+}
+
+/// Returns the address of the `ListingBook` page a listing of `price` goes
+/// into. Pages of a bucket are numbered from 0; a full page rejects new
+/// listings, so list into the next one.
+pub fn find_listing_book_address(program_id: &Pubkey, kind: ListingKind, price: u64, page: u16) -> Pubkey {
+  let bucket: u8 = ListingBook::bucket_of(price);
+  Pubkey::find_program_address(&[LISTING_SEED, &[kind as u8], &[bucket], &page.to_le_bytes()], program_id).0
+}
+
+/// Returns the address of the `Auction` account of a player.
+pub fn find_auction_address(program_id: &Pubkey, player: &Pubkey) -> Pubkey {
+  Pubkey::find_program_address(&[AUCTION_SEED, &player.to_bytes()], program_id).0
+}
+
+/// Returns the address of the `MatchRecord` written by a challenge.
+pub fn find_match_address(program_id: &Pubkey, user: &Pubkey, match_id: u64) -> Pubkey {
+  Pubkey::find_program_address(&[MATCH_SEED, &user.to_bytes(), &match_id.to_le_bytes()], program_id).0
+}
+
+/// Returns the address of the `RandomnessCommit` of `user` for `target`.
+pub fn find_commit_address(program_id: &Pubkey, user: &Pubkey, target: &Pubkey) -> Pubkey {
+  Pubkey::find_program_address(&[COMMIT_SEED, &user.to_bytes(), &target.to_bytes()], program_id).0
+}
+
+/// Creates a `RegisterUser` instruction. The `UserAccount` is created at
+/// `find_user_address` and the `UsernameRecord` at `find_username_address`,
+/// both funded by `user`.
+pub fn register_user(
+  program_id: &Pubkey,
+  user: &Pubkey,
+  user_name: String,
+) -> Instruction {
+  Instruction {
+    program_id: *program_id,
+    accounts: vec![
+      AccountMeta::new(*user, true),
+      AccountMeta::new(find_user_address(program_id, user), false),
+      AccountMeta::new_readonly(solana_program::system_program::id(), false),
+      AccountMeta::new(find_username_address(program_id, &user_name), false),
+    ],
+    data: GameInstruction::RegisterUser{username: UserName{user_name}}.pack(),
+  }
+}
+
+/// Creates a `GeneratePlayer` instruction. `secret` must match a commit
+/// made with `commit_randomness` for `nft` in an earlier slot. The `Player`
+/// is created at `find_player_address` and funded by `user`.
+pub fn generate_player(
+  program_id: &Pubkey,
+  user: &Pubkey,
+  user_ata: &Pubkey,
+  nft: &Pubkey,
+  secret: [u8; 32],
+) -> Instruction {
+  Instruction {
+    program_id: *program_id,
+    accounts: vec![
+      AccountMeta::new(*user, true),
+      AccountMeta::new_readonly(*user_ata, false),
+      AccountMeta::new(find_player_address(program_id, nft), false),
+      AccountMeta::new_readonly(*nft, false),
+      AccountMeta::new(find_commit_address(program_id, user, nft), false),
+      AccountMeta::new_readonly(solana_program::sysvar::slot_hashes::id(), false),
+      AccountMeta::new_readonly(solana_program::system_program::id(), false),
+    ],
+    data: GameInstruction::GeneratePlayer{reveal: Reveal{secret}}.pack(),
+  }
+}
+
+/// Creates a `BuyPlayer` instruction. `buyer` pays the listing price; the
+/// purchase fails if it is above `max_price`. `listing_book` is the page
+/// holding the sale listing. `payment` must be set when the listing is
+/// priced in an SPL token.
+#[allow(clippy::too_many_arguments)]
+pub fn buy_player(
+  program_id: &Pubkey,
+  buyer: &Pubkey,
+  player: &Pubkey,
+  player_nft: &Pubkey,
+  seller: &Pubkey,
+  pda: &Pubkey,
+  pda_ata: &Pubkey,
+  buyer_ata: &Pubkey,
+  treasury: &Pubkey,
+  listing_book: &Pubkey,
+  max_price: Option<u64>,
+  payment: Option<TokenPayment>,
+) -> Instruction {
+  let mut accounts: Vec<AccountMeta> = vec![
+    AccountMeta::new(*buyer, true),
+    AccountMeta::new(*player, false),
+    AccountMeta::new_readonly(*player_nft, false),
+    AccountMeta::new(*seller, false),
+    AccountMeta::new_readonly(solana_program::system_program::id(), false),
+    AccountMeta::new_readonly(spl_token::id(), false),
+    AccountMeta::new_readonly(*pda, false),
+    AccountMeta::new(*pda_ata, false),
+    AccountMeta::new(*buyer_ata, false),
+    AccountMeta::new_readonly(find_config_address(program_id), false),
+    AccountMeta::new(*treasury, false),
+    AccountMeta::new(*listing_book, false),
+  ];
+  if let Some(payment) = payment {
+    accounts.extend(payment.account_metas());
+  }
+  Instruction {
+    program_id: *program_id,
+    accounts,
+    data: GameInstruction::BuyPlayer{buy: BuyRequest{max_price}}.pack(),
+  }
+}
+
+/// Creates a `RentAnotherPlayer` instruction paying for `periods` periods
+/// up front. The owner's share is held in the `RentEscrow` at
+/// `find_rent_escrow_address`, which `renter` funds. The rental fails if
+/// all periods cost more than `max_price`. `listing_book` is the page
+/// holding the rent listing.
+/// `payment` must be set when the listing is priced in an SPL token.
+#[allow(clippy::too_many_arguments)]
+pub fn rent_another_player(
+  program_id: &Pubkey,
+  renter: &Pubkey,
+  player: &Pubkey,
+  owner: &Pubkey,
+  treasury: &Pubkey,
+  listing_book: &Pubkey,
+  periods: u16,
+  max_price: Option<u64>,
+  payment: Option<RentTokenPayment>,
+) -> Instruction {
+  let escrow: Pubkey = find_rent_escrow_address(program_id, player);
+  let mut accounts: Vec<AccountMeta> = vec![
+    AccountMeta::new(*renter, true),
+    AccountMeta::new(*player, false),
+    AccountMeta::new(*owner, false),
+    AccountMeta::new_readonly(find_config_address(program_id), false),
+    AccountMeta::new(*treasury, false),
+    AccountMeta::new(escrow, false),
+    AccountMeta::new_readonly(solana_program::system_program::id(), false),
+    AccountMeta::new(*listing_book, false),
+  ];
+  if let Some(payment) = payment {
+    accounts.extend([
+      AccountMeta::new_readonly(spl_token::id(), false),
+      AccountMeta::new(payment.payer_token_account, false),
+      AccountMeta::new(get_associated_token_address(&escrow, &payment.payment_mint), false),
+      AccountMeta::new(payment.treasury_token_account, false),
+      AccountMeta::new_readonly(payment.payment_mint, false),
+      AccountMeta::new_readonly(spl_associated_token_account::id(), false),
+    ]);
+  }
+  Instruction {
+    program_id: *program_id,
+    accounts,
+    data: GameInstruction::RentAnotherPlayer{rent: RentRequest{periods, max_price}}.pack(),
+  }
+}
+
+/// Creates a `SellPlayer` instruction. The NFT is moved from `owner_ata`
+/// into the associated token account of `pda`, which is created if missing.
+/// A `payment_mint` prices the listing in that SPL token instead of lamports.
+/// The listing is added to page `listing_page` of its price bucket.
+#[allow(clippy::too_many_arguments)]
+pub fn sell_player(
+  program_id: &Pubkey,
+  owner: &Pubkey,
+  player: &Pubkey,
+  nft: &Pubkey,
+  owner_ata: &Pubkey,
+  pda: &Pubkey,
+  sell_required_price: u64,
+  payment_mint: Option<Pubkey>,
+  listing_page: u16,
+) -> Instruction {
+  Instruction {
+    program_id: *program_id,
+    accounts: vec![
+      AccountMeta::new(*owner, true),
+      AccountMeta::new(*player, false),
+      AccountMeta::new_readonly(*nft, false),
+      AccountMeta::new(*owner_ata, false),
+      AccountMeta::new_readonly(*pda, false),
+      AccountMeta::new(get_associated_token_address(pda, nft), false),
+      AccountMeta::new_readonly(spl_token::id(), false),
+      AccountMeta::new_readonly(solana_program::system_program::id(), false),
+      AccountMeta::new_readonly(spl_associated_token_account::id(), false),
+      AccountMeta::new(find_listing_book_address(program_id, ListingKind::Sale, sell_required_price, listing_page), false),
+    ],
+    data: GameInstruction::SellPlayer{selloffer: SellOffer{
+      sell_required_price,
+      payment_mint: payment_mint.unwrap_or_default().to_bytes(),
+      listing_page,
+    }}.pack(),
+  }
+}
+
+/// Creates a `RentYourPlayer` instruction. `rent_required_price` buys one
+/// period of `rented_for_time` seconds; renters may prepay up to
+/// `max_renewals` more. A `payment_mint` prices the rent in that SPL token
+/// instead of lamports. The listing is added to page `listing_page` of its
+/// price bucket.
+#[allow(clippy::too_many_arguments)]
+pub fn rent_your_player(
+  program_id: &Pubkey,
+  owner: &Pubkey,
+  player: &Pubkey,
+  rent_required_price: u64,
+  rented_for_time: u64,
+  max_renewals: u16,
+  payment_mint: Option<Pubkey>,
+  listing_page: u16,
+) -> Instruction {
+  Instruction {
+    program_id: *program_id,
+    accounts: vec![
+      AccountMeta::new(*owner, true),
+      AccountMeta::new(*player, false),
+      AccountMeta::new(find_listing_book_address(program_id, ListingKind::Rent, rent_required_price, listing_page), false),
+      AccountMeta::new_readonly(solana_program::system_program::id(), false),
+    ],
+    data: GameInstruction::RentYourPlayer{
+      rentoffer: RentOffer{
+        rent_required_price,
+        rented_for_time,
+        payment_mint: payment_mint.unwrap_or_default().to_bytes(),
+        max_renewals,
+        listing_page,
+      },
+    }.pack(),
+  }
+}
+
+/// Creates a `SetTeam` instruction. `team` holds the player accounts in
+/// the slot order of `formation`, defence slots first.
+/// `old_team` holds the NFT mints of the current team, whose players
+/// leave it unless picked again.
+pub fn set_team(
+  program_id: &Pubkey,
+  user: &Pubkey,
+  user_account: &Pubkey,
+  formation: Formation,
+  team: &[Pubkey; TEAM_SIZE],
+  old_team: &[Pubkey],
+) -> Instruction {
+  let mut accounts: Vec<AccountMeta> = vec![
+    AccountMeta::new_readonly(*user, true),
+    AccountMeta::new(*user_account, false),
+  ];
+  accounts.extend(team.iter().map(|player| AccountMeta::new(*player, false)));
+  accounts.extend(team_account_metas(program_id, old_team));
+  Instruction {
+    program_id: *program_id,
+    accounts,
+    data: GameInstruction::SetTeam{formation}.pack(),
+  }
+}
+
+fn team_account_metas(program_id: &Pubkey, team: &[Pubkey]) -> Vec<AccountMeta> {
+  team
+    .iter()
+    .filter(|nft| **nft != Pubkey::default())
+    .map(|nft| AccountMeta::new(find_player_address(program_id, nft), false))
+    .collect()
+}
+
+/// Creates a `Challenge` instruction. `match_id` must not have been used
+/// by `user` before; the result is stored at `find_match_address`.
+/// `secret` must match a commit made for `opponent_account` in an earlier slot,
+/// with the formation and players the team has now.
+/// `team` and `opponent_team` hold the NFT mints of the team slots of each
+/// side, whose players are checked to still be controlled by their user and
+/// play the match.
+#[allow(clippy::too_many_arguments)]
+pub fn challenge(
+  program_id: &Pubkey,
+  user: &Pubkey,
+  user_account: &Pubkey,
+  opponent_account: &Pubkey,
+  team: &[Pubkey; TEAM_SIZE],
+  opponent_team: &[Pubkey; TEAM_SIZE],
+  match_id: u64,
+  secret: [u8; 32],
+) -> Instruction {
+  let mut accounts: Vec<AccountMeta> = vec![
+    AccountMeta::new(*user, true),
+    AccountMeta::new(*user_account, false),
+    AccountMeta::new(*opponent_account, false),
+    AccountMeta::new(find_match_address(program_id, user, match_id), false),
+    AccountMeta::new_readonly(solana_program::system_program::id(), false),
+    AccountMeta::new(find_commit_address(program_id, user, opponent_account), false),
+    AccountMeta::new_readonly(solana_program::sysvar::slot_hashes::id(), false),
+  ];
+  accounts.extend(team_slot_metas(program_id, team));
+  accounts.extend(team_slot_metas(program_id, opponent_team));
+  Instruction {
+    program_id: *program_id,
+    accounts,
+    data: GameInstruction::Challenge{challengedata: ChallengeData{match_id, secret}}.pack(),
+  }
+}
+
+fn team_slot_metas(program_id: &Pubkey, team: &[Pubkey; TEAM_SIZE]) -> Vec<AccountMeta> {
+  team
+    .iter()
+    .map(|nft| AccountMeta::new_readonly(find_player_address(program_id, nft), false))
+    .collect()
+}
+
+/// Creates an `AbortRent` instruction. `listing_book` is the page holding
+/// the rent listing.
+pub fn abort_rent(
+  program_id: &Pubkey,
+  owner: &Pubkey,
+  player: &Pubkey,
+  listing_book: &Pubkey,
+) -> Instruction {
+  Instruction {
+    program_id: *program_id,
+    accounts: vec![
+      AccountMeta::new_readonly(*owner, true),
+      AccountMeta::new(*player, false),
+      AccountMeta::new(*listing_book, false),
+    ],
+    data: GameInstruction::AbortRent.pack(),
+  }
+}
+
+/// Creates an `AbortSell` instruction. `listing_book` is the page holding
+/// the sale listing.
+pub fn abort_sell(
+  program_id: &Pubkey,
+  owner: &Pubkey,
+  player: &Pubkey,
+  pda: &Pubkey,
+  pda_ata: &Pubkey,
+  owner_ata: &Pubkey,
+  listing_book: &Pubkey,
+) -> Instruction {
+  Instruction {
+    program_id: *program_id,
+    accounts: vec![
+      AccountMeta::new(*owner, true),
+      AccountMeta::new(*player, false),
+      AccountMeta::new_readonly(spl_token::id(), false),
+      AccountMeta::new_readonly(*pda, false),
+      AccountMeta::new(*pda_ata, false),
+      AccountMeta::new(*owner_ata, false),
+      AccountMeta::new(*listing_book, false),
+    ],
+    data: GameInstruction::AbortSell.pack(),
+  }
+}
+
+/// Creates a `ClaimRentOverPlayer` instruction. Once the rental is over the
+/// escrowed rent goes to `owner`; `payout` must be set for token rentals.
+pub fn claim_rent_over_player(
+  program_id: &Pubkey,
+  owner: &Pubkey,
+  player: &Pubkey,
+  renter: &Pubkey,
+  payout: Option<RentPayout>,
+) -> Instruction {
+  let escrow: Pubkey = find_rent_escrow_address(program_id, player);
+  let mut accounts: Vec<AccountMeta> = vec![
+    AccountMeta::new(*owner, true),
+    AccountMeta::new(*player, false),
+    AccountMeta::new(escrow, false),
+    AccountMeta::new(*renter, false),
+  ];
+  if let Some(payout) = payout {
+    accounts.extend(payout.account_metas(&escrow));
+  }
+  Instruction {
+    program_id: *program_id,
+    accounts,
+    data: GameInstruction::ClaimRentOverPlayer.pack(),
+  }
+}
+
+/// Creates a `ReleaseRent` instruction. Pays `owner` the part of the
+/// escrowed rent earned so far; anyone may send it.
+pub fn release_rent(
+  program_id: &Pubkey,
+  player: &Pubkey,
+  owner: &Pubkey,
+  payout: Option<RentPayout>,
+) -> Instruction {
+  let escrow: Pubkey = find_rent_escrow_address(program_id, player);
+  let mut accounts: Vec<AccountMeta> = vec![
+    AccountMeta::new_readonly(*player, false),
+    AccountMeta::new(escrow, false),
+    AccountMeta::new(*owner, false),
+  ];
+  if let Some(payout) = payout {
+    accounts.extend(payout.account_metas(&escrow).into_iter().take(3));
+  }
+  Instruction {
+    program_id: *program_id,
+    accounts,
+    data: GameInstruction::ReleaseRent.pack(),
+  }
+}
+
+/// Creates a `TerminateRent` instruction. `signer` is the owner or the
+/// renter; the owner keeps the rent earned so far and the rest is refunded.
+/// The renter may end the rental at any time, the owner only once the
+/// current period is over, which refunds the prepaid periods.
+pub fn terminate_rent(
+  program_id: &Pubkey,
+  signer: &Pubkey,
+  player: &Pubkey,
+  owner: &Pubkey,
+  renter: &Pubkey,
+  payout: Option<RentPayout>,
+) -> Instruction {
+  let escrow: Pubkey = find_rent_escrow_address(program_id, player);
+  let mut accounts: Vec<AccountMeta> = vec![
+    AccountMeta::new(*signer, true),
+    AccountMeta::new(*player, false),
+    AccountMeta::new(escrow, false),
+    AccountMeta::new(*owner, false),
+    AccountMeta::new(*renter, false),
+  ];
+  if let Some(payout) = payout {
+    accounts.extend(payout.account_metas(&escrow));
+  }
+  Instruction {
+    program_id: *program_id,
+    accounts,
+    data: GameInstruction::TerminateRent.pack(),
+  }
+}
+
+/// Creates a `ClaimNFTPlayer` instruction.
+pub fn claim_nft_player(
+  program_id: &Pubkey,
+  user: &Pubkey,
+  user_ata: &Pubkey,
+  player: &Pubkey,
+  nft: &Pubkey,
+) -> Instruction {
+  Instruction {
+    program_id: *program_id,
+    accounts: vec![
+      AccountMeta::new_readonly(*user, true),
+      AccountMeta::new_readonly(*user_ata, false),
+      AccountMeta::new(*player, false),
+      AccountMeta::new_readonly(*nft, false),
+    ],
+    data: GameInstruction::ClaimNFTPlayer.pack(),
+  }
+}
+
+/// Creates a `CreatePDA` instruction.
+pub fn create_pda(
+  program_id: &Pubkey,
+  pda: &Pubkey,
+  payer: &Pubkey,
+) -> Instruction {
+  Instruction {
+    program_id: *program_id,
+    accounts: vec![
+      AccountMeta::new(*pda, false),
+      AccountMeta::new(*payer, true),
+      AccountMeta::new_readonly(solana_program::system_program::id(), false),
+    ],
+    data: GameInstruction::CreatePDA.pack(),
+  }
+}
+
+/// Creates an `Upgrade` instruction spending `exp` experience of `user`
+/// on the team player at index `slot` of the team, defence first. `team`
+/// holds the player accounts of every slot, the team power is recomputed
+/// from them. See `UpgradePlayer` for how this differs from `player_no`.
+pub fn upgrade(
+  program_id: &Pubkey,
+  user: &Pubkey,
+  team: &[Pubkey; TEAM_SIZE],
+  slot: u8,
+  exp: u64,
+) -> Instruction {
+  let mut accounts: Vec<AccountMeta> = vec![
+    AccountMeta::new_readonly(*user, true),
+    AccountMeta::new(find_user_address(program_id, user), false),
+  ];
+  accounts.extend(team.iter().enumerate().map(|(index, player)| {
+    if index == slot as usize {
+      AccountMeta::new(*player, false)
+    } else {
+      AccountMeta::new_readonly(*player, false)
+    }
+  }));
+  Instruction {
+    program_id: *program_id,
+    accounts,
+    data: GameInstruction::Upgrade{upgrade: UpgradePlayer{slot, exp}}.pack(),
+  }
+}
+
+/// Creates an `InitConfig` instruction. The signer becomes the admin and
+/// must be the upgrade authority of the program.
+pub fn init_config(
+  program_id: &Pubkey,
+  admin: &Pubkey,
+  treasury: &Pubkey,
+  fee_basis_points: u16,
+) -> Instruction {
+  Instruction {
+    program_id: *program_id,
+    accounts: vec![
+      AccountMeta::new(*admin, true),
+      AccountMeta::new(find_config_address(program_id), false),
+      AccountMeta::new_readonly(solana_program::system_program::id(), false),
+      AccountMeta::new_readonly(find_program_data_address(program_id), false),
+    ],
+    data: GameInstruction::InitConfig{
+      config: ConfigData{treasury: treasury.to_bytes(), fee_basis_points},
+    }.pack(),
+  }
+}
+
+/// Creates an `UpdateConfig` instruction.
+pub fn update_config(
+  program_id: &Pubkey,
+  admin: &Pubkey,
+  treasury: &Pubkey,
+  fee_basis_points: u16,
+) -> Instruction {
+  Instruction {
+    program_id: *program_id,
+    accounts: vec![
+      AccountMeta::new_readonly(*admin, true),
+      AccountMeta::new(find_config_address(program_id), false),
+    ],
+    data: GameInstruction::UpdateConfig{
+      config: ConfigData{treasury: treasury.to_bytes(), fee_basis_points},
+    }.pack(),
+  }
+}
+
+/// Creates a `TransferAdmin` instruction. Both the current and the new
+/// admin sign, so the config can not be handed to a mistyped key.
+pub fn transfer_admin(
+  program_id: &Pubkey,
+  admin: &Pubkey,
+  new_admin: &Pubkey,
+) -> Instruction {
+  Instruction {
+    program_id: *program_id,
+    accounts: vec![
+      AccountMeta::new_readonly(*admin, true),
+      AccountMeta::new(find_config_address(program_id), false),
+      AccountMeta::new_readonly(*new_admin, true),
+    ],
+    data: GameInstruction::TransferAdmin.pack(),
+  }
+}
+
+/// Creates a `CreateAuction` instruction. The NFT is escrowed like `SellPlayer`.
+pub fn create_auction(
+  program_id: &Pubkey,
+  seller: &Pubkey,
+  player: &Pubkey,
+  nft: &Pubkey,
+  seller_ata: &Pubkey,
+  reserve_price: u64,
+  duration: u64,
+) -> Instruction {
+  let pda: Pubkey = find_vault_address(program_id);
+  Instruction {
+    program_id: *program_id,
+    accounts: vec![
+      AccountMeta::new(*seller, true),
+      AccountMeta::new(*player, false),
+      AccountMeta::new(find_auction_address(program_id, player), false),
+      AccountMeta::new_readonly(*nft, false),
+      AccountMeta::new(*seller_ata, false),
+      AccountMeta::new_readonly(pda, false),
+      AccountMeta::new(get_associated_token_address(&pda, nft), false),
+      AccountMeta::new_readonly(spl_token::id(), false),
+      AccountMeta::new_readonly(solana_program::system_program::id(), false),
+      AccountMeta::new_readonly(spl_associated_token_account::id(), false),
+    ],
+    data: GameInstruction::CreateAuction{
+      auctionoffer: AuctionOffer{reserve_price, duration},
+    }.pack(),
+  }
+}
+
+/// Creates a `PlaceBid` instruction. `previous_bidder` is the current
+/// highest bidder, or any account when there are no bids yet.
+pub fn place_bid(
+  program_id: &Pubkey,
+  bidder: &Pubkey,
+  player: &Pubkey,
+  previous_bidder: &Pubkey,
+  amount: u64,
+) -> Instruction {
+  Instruction {
+    program_id: *program_id,
+    accounts: vec![
+      AccountMeta::new(*bidder, true),
+      AccountMeta::new(find_auction_address(program_id, player), false),
+      AccountMeta::new(*previous_bidder, false),
+      AccountMeta::new_readonly(solana_program::system_program::id(), false),
+    ],
+    data: GameInstruction::PlaceBid{bid: Bid{amount}}.pack(),
+  }
+}
+
+/// Creates a `SettleAuction` instruction. Anyone can settle once the
+/// auction has ended; `payer` funds the winner's token account if missing.
+#[allow(clippy::too_many_arguments)]
+pub fn settle_auction(
+  program_id: &Pubkey,
+  payer: &Pubkey,
+  player: &Pubkey,
+  nft: &Pubkey,
+  seller: &Pubkey,
+  winner: &Pubkey,
+  treasury: &Pubkey,
+) -> Instruction {
+  let pda: Pubkey = find_vault_address(program_id);
+  Instruction {
+    program_id: *program_id,
+    accounts: vec![
+      AccountMeta::new(*payer, true),
+      AccountMeta::new(find_auction_address(program_id, player), false),
+      AccountMeta::new(*player, false),
+      AccountMeta::new_readonly(*nft, false),
+      AccountMeta::new(*seller, false),
+      AccountMeta::new_readonly(*winner, false),
+      AccountMeta::new(get_associated_token_address(winner, nft), false),
+      AccountMeta::new_readonly(pda, false),
+      AccountMeta::new(get_associated_token_address(&pda, nft), false),
+      AccountMeta::new_readonly(find_config_address(program_id), false),
+      AccountMeta::new(*treasury, false),
+      AccountMeta::new_readonly(spl_token::id(), false),
+      AccountMeta::new_readonly(solana_program::system_program::id(), false),
+      AccountMeta::new_readonly(spl_associated_token_account::id(), false),
+    ],
+    data: GameInstruction::SettleAuction.pack(),
+  }
+}
+
+/// Creates a `CancelAuction` instruction.
+pub fn cancel_auction(
+  program_id: &Pubkey,
+  seller: &Pubkey,
+  player: &Pubkey,
+  nft: &Pubkey,
+  seller_ata: &Pubkey,
+) -> Instruction {
+  let pda: Pubkey = find_vault_address(program_id);
+  Instruction {
+    program_id: *program_id,
+    accounts: vec![
+      AccountMeta::new(*seller, true),
+      AccountMeta::new(find_auction_address(program_id, player), false),
+      AccountMeta::new(*player, false),
+      AccountMeta::new_readonly(pda, false),
+      AccountMeta::new(get_associated_token_address(&pda, nft), false),
+      AccountMeta::new(*seller_ata, false),
+      AccountMeta::new_readonly(spl_token::id(), false),
+    ],
+    data: GameInstruction::CancelAuction.pack(),
+  }
+}
+
+/// Creates a `CommitRandomness` instruction. `target` is the opponent user
+/// account for `Challenge` or the NFT mint for `GeneratePlayer`. `commitment`
+/// is `randomness::challenge_commitment(secret, lineup)` with the lineup the
+/// challenge will be played with, or `randomness::commitment(secret)` for
+/// `GeneratePlayer`. `user` also pays
+/// `COMMIT_BOND`, which the reveal refunds.
+pub fn commit_randomness(
+  program_id: &Pubkey,
+  user: &Pubkey,
+  target: &Pubkey,
+  commitment: [u8; 32],
+) -> Instruction {
+  Instruction {
+    program_id: *program_id,
+    accounts: vec![
+      AccountMeta::new(*user, true),
+      AccountMeta::new(find_commit_address(program_id, user, target), false),
+      AccountMeta::new_readonly(solana_program::system_program::id(), false),
+    ],
+    data: GameInstruction::CommitRandomness{
+      commit: CommitData{commitment, target: target.to_bytes()},
+    }.pack(),
+  }
+}
+
+/// Creates a `MigrateAccount` instruction. Rewrites `account` in the newest
+/// layout of `account_type`; `payer` covers the rent for the extra space.
+pub fn migrate_account(
+  program_id: &Pubkey,
+  payer: &Pubkey,
+  account: &Pubkey,
+  account_type: AccountType,
+) -> Instruction {
+  Instruction {
+    program_id: *program_id,
+    accounts: vec![
+      AccountMeta::new(*payer, true),
+      AccountMeta::new(*account, false),
+      AccountMeta::new_readonly(solana_program::system_program::id(), false),
+    ],
+    data: GameInstruction::MigrateAccount{account_type}.pack(),
+  }
+}
+
+/// Creates a `RenameUser` instruction. `old_name` is the name currently
+/// stored for `user`; its `UsernameRecord` is closed and the rent returned.
+pub fn rename_user(
+  program_id: &Pubkey,
+  user: &Pubkey,
+  old_name: &str,
+  user_name: String,
+) -> Instruction {
+  Instruction {
+    program_id: *program_id,
+    accounts: vec![
+      AccountMeta::new(*user, true),
+      AccountMeta::new(find_user_address(program_id, user), false),
+      AccountMeta::new(find_username_address(program_id, old_name), false),
+      AccountMeta::new(find_username_address(program_id, &user_name), false),
+      AccountMeta::new_readonly(solana_program::system_program::id(), false),
+    ],
+    data: GameInstruction::RenameUser{username: UserName{user_name}}.pack(),
+  }
+}
+
+/// Creates a `CloseUser` instruction. Closes the `UserAccount` of `user`
+/// and the `UsernameRecord` of `user_name`, refunding both to `destination`.
+/// `team` holds the NFT mints of the current team, whose players leave it.
+pub fn close_user(
+  program_id: &Pubkey,
+  user: &Pubkey,
+  user_name: &str,
+  destination: &Pubkey,
+  team: &[Pubkey],
+) -> Instruction {
+  let mut accounts: Vec<AccountMeta> = vec![
+    AccountMeta::new_readonly(*user, true),
+    AccountMeta::new(find_user_address(program_id, user), false),
+    AccountMeta::new(find_username_address(program_id, user_name), false),
+    AccountMeta::new(*destination, false),
+  ];
+  accounts.extend(team_account_metas(program_id, team));
+  Instruction {
+    program_id: *program_id,
+    accounts,
+    data: GameInstruction::CloseUser.pack(),
+  }
+}
+
+/// Creates a `ClosePlayer` instruction. The player must be idle: not
+/// listed, rented, auctioned or in a team; its rent goes to `destination`.
+pub fn close_player(
+  program_id: &Pubkey,
+  user: &Pubkey,
+  nft: &Pubkey,
+  destination: &Pubkey,
+) -> Instruction {
+  Instruction {
+    program_id: *program_id,
+    accounts: vec![
+      AccountMeta::new_readonly(*user, true),
+      AccountMeta::new(find_player_address(program_id, nft), false),
+      AccountMeta::new(*destination, false),
+    ],
+    data: GameInstruction::ClosePlayer.pack(),
+  }
+}
+
+/// Creates a `RenewRent` instruction. Once the current period is over it
+/// pays `owner` for it and starts the next prepaid period, or ends the
+/// rental when none is left. Anyone may send it.
+pub fn renew_rent(
+  program_id: &Pubkey,
+  player: &Pubkey,
+  owner: &Pubkey,
+  renter: &Pubkey,
+  payout: Option<RentPayout>,
+) -> Instruction {
+  let escrow: Pubkey = find_rent_escrow_address(program_id, player);
+  let mut accounts: Vec<AccountMeta> = vec![
+    AccountMeta::new(*player, false),
+    AccountMeta::new(escrow, false),
+    AccountMeta::new(*owner, false),
+    AccountMeta::new(*renter, false),
+  ];
+  if let Some(payout) = payout {
+    accounts.extend(payout.account_metas(&escrow));
+  }
+  Instruction {
+    program_id: *program_id,
+    accounts,
+    data: GameInstruction::RenewRent.pack(),
+  }
+}
+
+/// Creates a `RefreshTeam` instruction. `team` holds the NFT mints of the
+/// team slots of `user_account`. The team power is recomputed, or the team
+/// is dropped when the user no longer controls one of its players.
+/// Anyone may send it.
+pub fn refresh_team(
+  program_id: &Pubkey,
+  user_account: &Pubkey,
+  team: &[Pubkey; TEAM_SIZE],
+) -> Instruction {
+  let mut accounts: Vec<AccountMeta> = vec![AccountMeta::new(*user_account, false)];
+  accounts.extend(team_slot_metas(program_id, team));
+  Instruction {
+    program_id: *program_id,
+    accounts,
+    data: GameInstruction::RefreshTeam.pack(),
+  }
+}
+
+/// Creates a `CancelCommit` instruction. Closes the commit of `user` for
+/// `target` so a new one can be made; the rent goes back to `user` and the
+/// bond to the treasury.
+pub fn cancel_commit(
+  program_id: &Pubkey,
+  user: &Pubkey,
+  target: &Pubkey,
+  treasury: &Pubkey,
+) -> Instruction {
+  Instruction {
+    program_id: *program_id,
+    accounts: vec![
+      AccountMeta::new(*user, true),
+      AccountMeta::new(find_commit_address(program_id, user, target), false),
+      AccountMeta::new_readonly(find_config_address(program_id), false),
+      AccountMeta::new(*treasury, false),
+    ],
+    data: GameInstruction::CancelCommit.pack(),
+  }
 }
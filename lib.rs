@@ -1,5 +1,8 @@
+pub mod constraints;
 pub mod entrypoint;
 pub mod error;
 pub mod instruction;
 pub mod processor;
+pub mod randomness;
+pub mod simulation;
 pub mod state;
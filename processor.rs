@@ -1,15 +1,25 @@
+use crate::error::GameError;
 use crate::instruction::GameInstruction;
-use crate::state::{UserAccount,Player,UserName,SellOffer,RentOffer,UpgradePlayer
+use crate::state::{ProgramAccount,Migrate,AccountType,UserAccount,TeamStatus,Formation,TEAM_SIZE,compute_team_power,Player,PlayerStatus,PlayerAction,INITIAL_RATING,UserName,SellOffer,RentOffer,UpgradePlayer,
+  GameConfig,ConfigData,Vault,Auction,AuctionOffer,Bid,ChallengeData,MatchRecord,
+  RandomnessCommit,CommitData,Reveal,UsernameRecord,RentEscrow,BuyRequest,RentRequest,
+  ListingBook,Listing,ListingKind,UNINDEXED_LISTING,
+  USER_SEED,PLAYER_SEED,CONFIG_SEED,VAULT_SEED,AUCTION_SEED,MATCH_SEED,COMMIT_SEED,USERNAME_SEED,RENT_SEED,LISTING_SEED,MAX_COMMIT_AGE,COMMIT_BOND,
 };
-use borsh::{BorshDeserialize, BorshSerialize};
+use crate::randomness;
+use crate::simulation::{self, Lineup, MatchResult};
+use crate::constraints;
 
 use solana_program::{
   account_info::{next_account_info, AccountInfo},
   entrypoint::ProgramResult,
   pubkey::Pubkey,
-  sysvar::{clock::Clock, Sysvar,},
+  sysvar::{self, clock::Clock, rent::Rent, Sysvar,},
+  bpf_loader_upgradeable::{self, UpgradeableLoaderState},
   system_instruction,
-  program::invoke_signed,
+  program::{invoke, invoke_signed},
+  program_error::ProgramError,
+  msg,
   program_pack::Pack,
   keccak,
 };
@@ -17,6 +27,8 @@ use solana_program::{
 use spl_token::instruction::transfer;
 use spl_token::instruction::close_account;
 use spl_token::state::Account;
+use spl_associated_token_account::get_associated_token_address;
+use spl_associated_token_account::instruction::create_associated_token_account_idempotent;
 
 
 pub struct Processor;
@@ -28,49 +40,102 @@ impl Processor {
   ) -> ProgramResult {
     let instruction: GameInstruction = GameInstruction::unpack(instruction_data)?;
 
+    constraints::check_accounts(&instruction, accounts, program_id)?;
+
     match instruction {
 
       GameInstruction::RegisterUser {username} => {
         Self::register_user(accounts,username, program_id)
       }
-      GameInstruction::GeneratePlayer {} => {
-        Self::generate_player(accounts)
+      GameInstruction::GeneratePlayer {reveal} => {
+        Self::generate_player(accounts,reveal,program_id)
       }
-      GameInstruction::BuyPlayer {} => {
-        Self::buy_player(accounts, program_id)
+      GameInstruction::BuyPlayer {buy} => {
+        Self::buy_player(accounts, buy, program_id)
       }      
-      GameInstruction::RentAnotherPlayer {} => {
-        Self::rent_another_player(accounts, program_id)
+      GameInstruction::RentAnotherPlayer {rent} => {
+        Self::rent_another_player(accounts, rent, program_id)
       }
       GameInstruction::SellPlayer {selloffer} => {
-        Self::sell_player(accounts,selloffer)
+        Self::sell_player(accounts,selloffer,program_id)
       }
       GameInstruction::RentYourPlayer {rentoffer} => {
-        Self::rent_your_player(accounts,rentoffer)
+        Self::rent_your_player(accounts,rentoffer,program_id)
       }
-      GameInstruction::SetTeam {} => {
-        Self::set_team(accounts)
+      GameInstruction::SetTeam {formation} => {
+        Self::set_team(accounts,formation,program_id)
       }
-      GameInstruction::Challenge {} => {
-        Self::challenge(accounts, program_id)
+      GameInstruction::Challenge {challengedata} => {
+        Self::challenge(accounts, challengedata, program_id)
       }
-      GameInstruction::AbortRent {} => {
-        Self::abort_rent(accounts)
+      GameInstruction::AbortRent => {
+        Self::abort_rent(accounts,program_id)
       }
-      GameInstruction::AbortSell {} => {
-        Self::abort_sell(accounts)
+      GameInstruction::AbortSell => {
+        Self::abort_sell(accounts,program_id)
       }
-      GameInstruction::ClaimRentOverPlayer {} => {
-        Self::claim_player_back(accounts)
+      GameInstruction::ClaimRentOverPlayer => {
+        Self::claim_player_back(accounts,program_id)
       }
-      GameInstruction::ClaimNFTPlayer {} => {
+      GameInstruction::ClaimNFTPlayer => {
         Self::claim_nft_player(accounts)
       }
-      GameInstruction::CreatePDA {} => {
+      GameInstruction::CreatePDA => {
         Self::create_pda(accounts,program_id)
       }
       GameInstruction::Upgrade {upgrade} => {
-        Self::upgrade_player(accounts,upgrade)
+        Self::upgrade_player(accounts,upgrade,program_id)
+      }
+      GameInstruction::InitConfig {config} => {
+        Self::init_config(accounts,config,program_id)
+      }
+      GameInstruction::UpdateConfig {config} => {
+        Self::update_config(accounts,config,program_id)
+      }
+      GameInstruction::CreateAuction {auctionoffer} => {
+        Self::create_auction(accounts,auctionoffer,program_id)
+      }
+      GameInstruction::PlaceBid {bid} => {
+        Self::place_bid(accounts,bid,program_id)
+      }
+      GameInstruction::SettleAuction => {
+        Self::settle_auction(accounts,program_id)
+      }
+      GameInstruction::CancelAuction => {
+        Self::cancel_auction(accounts,program_id)
+      }
+      GameInstruction::CommitRandomness {commit} => {
+        Self::commit_randomness(accounts,commit,program_id)
+      }
+      GameInstruction::MigrateAccount {account_type} => {
+        Self::migrate_account(accounts,account_type,program_id)
+      }
+      GameInstruction::RenameUser {username} => {
+        Self::rename_user(accounts,username,program_id)
+      }
+      GameInstruction::CloseUser => {
+        Self::close_user(accounts,program_id)
+      }
+      GameInstruction::ClosePlayer => {
+        Self::close_player(accounts,program_id)
+      }
+      GameInstruction::ReleaseRent => {
+        Self::release_rent(accounts,program_id)
+      }
+      GameInstruction::TerminateRent => {
+        Self::terminate_rent(accounts,program_id)
+      }
+      GameInstruction::RenewRent => {
+        Self::renew_rent(accounts,program_id)
+      }
+      GameInstruction::RefreshTeam => {
+        Self::refresh_team(accounts,program_id)
+      }
+      GameInstruction::CancelCommit => {
+        Self::cancel_commit(accounts,program_id)
+      }
+      GameInstruction::TransferAdmin => {
+        Self::transfer_admin(accounts,program_id)
       }
     }
   }
@@ -85,47 +150,53 @@ impl Processor {
 
     let user: &AccountInfo<'_> = next_account_info(accounts_iter)?;
     let user_derived_account: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+    let system_program: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+    let username_record: &AccountInfo<'_> = next_account_info(accounts_iter)?;
 
-    let seed: String = String::from("acc");
-    let derived_pubkey: Pubkey = Pubkey::create_with_seed(&user.key, &seed, &program_id)?;
+    if !user.is_signer{return Err(GameError::MissingSignature.into())}
 
+    let (derived_pubkey, bump) = Pubkey::find_program_address(&[USER_SEED, &user.key.to_bytes()], program_id);
 
-    if user_derived_account.key != &derived_pubkey{panic!()}
-    if user_derived_account.owner != program_id{panic!()}
+    if user_derived_account.key != &derived_pubkey{return Err(GameError::InvalidUserAccount.into())}
 
-    if username.user_name.len() > 10 {panic!();}
+    let normalized_name: String = username.normalized()?;
 
     let username_len: u8 = username.user_name.len() as u8;
 
     //user name can be 10 character long
-    let mut user_name_holder: String = String::from("XXXXXXXXXX");
-    let offset: &usize = &username.user_name.len();
-    user_name_holder.replace_range(..offset, &username.user_name);
+    let user_name_holder: String = username.padded();
 
+    Self::claim_username(username_record, user, system_program, &normalized_name, program_id)?;
 
 
     let user_account: UserAccount = UserAccount{
       user_address:user.key.to_bytes(),
       user_name:user_name_holder,
       user_name_length:username_len,
-      team_is_ready:0,
+      team_status:TeamStatus::NoTeam,
       team_power:0,
-      team_class:"aa".to_string(),
-      defence_1:[0;32],
-      defence_2:[0;32],
-      middle:[0;32],
-      offence_1:[0;32],
-      offence_2:[0;32],
-      experienced:0
+      formation:Formation::TwoOneTwo,
+      team:[[0;32];TEAM_SIZE],
+      experienced:0,
+      wins:0,
+      losses:0,
+      draws:0,
+      streak:0,
+      rating:INITIAL_RATING,
     };
 
-    
-    user_account.serialize(&mut &mut user_derived_account.data.borrow_mut()[..])?;
+    //fails if the user is already registered
+    Self::create_pda_account(user, user_derived_account, system_program, user_account.packed_len()?,
+      &[USER_SEED, &user.key.to_bytes(), &[bump]], program_id)?;
+
+    user_account.pack_into(&mut user_derived_account.data.borrow_mut())?;
    
     Ok(())
   }
   fn generate_player(
-    accounts: &[AccountInfo]) -> ProgramResult {
+    accounts: &[AccountInfo],
+    reveal: Reveal,
+    program_id:&Pubkey) -> ProgramResult {
 
       let accounts_iter: &mut std::slice::Iter<'_, AccountInfo<'_>> = &mut accounts.iter();
 
@@ -133,62 +204,72 @@ impl Processor {
       let user_ata: &AccountInfo<'_> = next_account_info(accounts_iter)?;
       let player: &AccountInfo<'_> = next_account_info(accounts_iter)?;
       let nft: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let commit: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let slot_hashes: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let system_program: &AccountInfo<'_> = next_account_info(accounts_iter)?;
 
+      if !user.is_signer{return Err(GameError::MissingSignature.into())}
 
-      if user_ata.owner!=&spl_token::id(){panic!()}
-      let user_ata_unpacked: Account = Account::unpack_from_slice(&user_ata.data.borrow())?;//
-      if user.key != &user_ata_unpacked.owner{panic!()}//is the owner of ata user?
-      if user_ata_unpacked.amount != 1{panic!()} //has user the nft?
-      if  nft.key != &user_ata_unpacked.mint {panic!()}//is ata and nft(toekn mint) related?
+      //one player per nft, a second GeneratePlayer fails when creating the account
+      let (player_key, bump) = Pubkey::find_program_address(&[PLAYER_SEED, &nft.key.to_bytes()], program_id);
 
+      if player.key != &player_key{return Err(GameError::InvalidPlayerAccount.into())}
+
+      if user_ata.owner!=&spl_token::id(){return Err(GameError::InvalidTokenAccount.into())}
+      let user_ata_unpacked: Account = Account::unpack_from_slice(&user_ata.data.borrow())?;//
+      if user.key != &user_ata_unpacked.owner{return Err(GameError::TokenAccountOwnerMismatch.into())}//is the owner of ata user?
+      if user_ata_unpacked.amount != 1{return Err(GameError::NftNotHeld.into())} //has user the nft?
+      if  nft.key != &user_ata_unpacked.mint {return Err(GameError::NftMintMismatch.into())}//is ata and nft(toekn mint) related?
 
-      let nft_seed:&[u8] = &nft.key.to_bytes();
-      let user_seed:&[u8] = &user.key.to_bytes();
 
-      let rand: keccak::Hash = keccak::hashv(&[nft_seed,user_seed]);
+      //stats come from a secret committed before the nft's slot hash existed
+      let rand: keccak::Hash = Self::reveal_randomness(user, commit, slot_hashes, nft.key, &reveal.secret, None, program_id)?;
       let rand_str: String = rand.to_string();
       let rand_bytes: &[u8] = rand_str.as_bytes();
 
-      let offence:u64;
-      let defence:u64;
-
-      if rand_bytes[0] > 20 {
-        offence = (rand_bytes[0] - 10) as u64;
+      let offence:u64 = if rand_bytes[0] > 20 {
+        (rand_bytes[0] - 10) as u64
       }else{
-        offence = rand_bytes[0] as u64;
-      }
+        rand_bytes[0] as u64
+      };
 
-      if rand_bytes[1] > 20 {
-        defence = (rand_bytes[1] - 10) as u64;
+      let defence:u64 = if rand_bytes[1] > 20 {
+        (rand_bytes[1] - 10) as u64
       }else{
-        defence = rand_bytes[1] as u64;
-      }
+        rand_bytes[1] as u64
+      };
 
 
       let player_data: Player = Player{
     
         nft_adress:nft.key.to_bytes(),
         owner:user.key.to_bytes(),
-        for_sale:"aa".to_string(),
+        status:PlayerStatus::Idle,
         sale_required_price:0,
-        for_rent:"aa".to_string(),
         rent_required_price:0,
         who_rents:[0;32],//address
-        rented:0,
         rented_for_time:0,
         rent_end_on:0,
-        offence:offence,
-        defence:defence,
+        offence,
+        defence,
+        payment_mint:[0;32],
+        max_renewals:0,
+        listing_page:0,
     };
 
+      Self::create_pda_account(user, player, system_program, player_data.packed_len()?,
+        &[PLAYER_SEED, &nft.key.to_bytes(), &[bump]], program_id)?;
 
-    player_data.serialize(&mut &mut player.data.borrow_mut()[..])?;
-    
+    player_data.pack_into(&mut player.data.borrow_mut())?;
+
+    //commit rent goes back to user
+    Self::close_program_account(commit, user)?;
    
     Ok(())
   }//////////
   fn buy_player(
     accounts: &[AccountInfo],
+    buy: BuyRequest,
     program_id:&Pubkey) -> ProgramResult {
   
       let accounts_iter: &mut std::slice::Iter<'_, AccountInfo<'_>> = &mut accounts.iter();
@@ -197,98 +278,84 @@ impl Processor {
       let player: &AccountInfo<'_> = next_account_info(accounts_iter)?;
       let player_nft: &AccountInfo<'_> = next_account_info(accounts_iter)?;
       let seller: &AccountInfo<'_> = next_account_info(accounts_iter)?;
-      let transfer_lamports: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let system_program: &AccountInfo<'_> = next_account_info(accounts_iter)?;
       let token_program: &AccountInfo<'_> = next_account_info(accounts_iter)?;
       let pda: &AccountInfo<'_> = next_account_info(accounts_iter)?;
       let pda_ata: &AccountInfo<'_> = next_account_info(accounts_iter)?;
       let user_ata: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let config: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let treasury: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let listing_book: &AccountInfo<'_> = next_account_info(accounts_iter)?;
 
+      let config_data: GameConfig = Self::load_config(config, treasury, program_id)?;
+      let bump: u8 = Self::load_vault_bump(pda, program_id)?;
 
-      let player_acc_data: Player = Player::try_from_slice(&player.data.borrow())?; 
+      let player_acc_data: Player = Player::unpack(&player.data.borrow())?; 
 
       let owner_key: Pubkey = Pubkey::new_from_array(player_acc_data.owner);
 
-      if seller.key != &owner_key{panic!()}
+      if seller.key != &owner_key{return Err(GameError::NotPlayerOwner.into())}
 
 
       let nft_key: Pubkey = Pubkey::new_from_array(player_acc_data.nft_adress);
 
-      if player_nft.key != &nft_key{panic!()}//if nft and player not related panic
+      if player_nft.key != &nft_key{return Err(GameError::NftMintMismatch.into())}//if nft and player not related
+
+      if pda_ata.key != &get_associated_token_address(pda.key, &nft_key){return Err(GameError::InvalidEscrowAccount.into())}
 
-      if player.owner != program_id{panic!()} //if not a correct account panic
+      if player.owner != program_id{return Err(GameError::IncorrectProgramOwner.into())} //if not a correct account
 
-      let account_value: u64 = **transfer_lamports.lamports.borrow();
+      let payment_mint: Pubkey = Pubkey::new_from_array(player_acc_data.payment_mint);
 
-      if account_value < player_acc_data.sale_required_price {panic!()} //if not enough lamports to buy panic
+      let status: PlayerStatus = player_acc_data.status.transition(PlayerAction::Buy)?; //if not for sale
 
-      if player_acc_data.for_sale == "aa".to_string(){panic!()} //if not for sale panic
+      let (price, seller_amount, fee) = Self::quote(&player_acc_data, ListingKind::Sale, 1, buy.max_price, &config_data)?;
+
+      Self::remove_listing(listing_book, ListingKind::Sale, player, &player_acc_data, program_id)?;
 
 
       let new_owner: Player = Player{
         nft_adress:player_acc_data.nft_adress,
         owner:user.key.to_bytes(),
-        for_sale:"aa".to_string(),
+        status,
         sale_required_price:0,
-        for_rent:"aa".to_string(),
         rent_required_price:0,
-        who_rents:player_acc_data.who_rents,
-        rented:0,
+        who_rents:[0;32],
         rented_for_time:0,
         rent_end_on:0,
         offence:player_acc_data.offence,
         defence:player_acc_data.defence,
+        payment_mint:[0;32],
+        max_renewals:0,
+        listing_page:0,
       };
 
       //transfer token from pda_ATA to user_ata
       //close pda_ata & return value to seller
+      Self::withdraw_nft(token_program, pda, pda_ata, user_ata, seller, bump)?;
 
-      let amount:u64 = 1;
-
-      let trans_ix: solana_program::instruction::Instruction = transfer( &token_program.key,
-          &pda_ata.key, 
-          &user_ata.key, 
-          &pda.key, 
-          &[&pda.key], 
-          amount)?;
-
-      invoke_signed(
-        &trans_ix,
-        &[
-          token_program.clone(),
-          pda_ata.clone(),
-          user_ata.clone(),
-          pda.clone(),
-        ],
-        &[&[b"battleballs", &[254]]],
-      )?;
-
-      let close_ix: solana_program::instruction::Instruction = close_account(&token_program.key, 
-        &pda_ata.key,
-         &seller.key, 
-         &pda.key,
-         &[&pda.key])?;
+      if payment_mint == Pubkey::default() {
+        Self::pay_with_lamports(system_program, user, seller, treasury, seller_amount, fee)?;
+      }else{
+        let user_payment: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+        let seller_payment: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+        let treasury_payment: &AccountInfo<'_> = next_account_info(accounts_iter)?;
 
-      invoke_signed(
-          &close_ix,
-          &[
-            token_program.clone(),
-            pda_ata.clone(),
-            seller.clone(),
-            pda.clone(),
-          ],
-          &[&[b"battleballs", &[254]]],
-        )?;
+        Self::pay_with_token(token_program, user, user_payment, seller_payment, treasury_payment,
+          &payment_mint, seller.key, treasury.key, seller_amount, fee)?;
+      }
 
-      **transfer_lamports.lamports.borrow_mut() -= player_acc_data.sale_required_price;
-      **seller.lamports.borrow_mut() += player_acc_data.sale_required_price;
+      //the split is not stored on chain, this log is its record
+      msg!("sale: price {} seller {} fee {}", price, seller_amount, fee);
 
-      new_owner.serialize(&mut &mut player.data.borrow_mut()[..])?;
+      new_owner.pack_into(&mut player.data.borrow_mut())?;
 
 
     Ok(())
   }
   fn rent_another_player(
     accounts: &[AccountInfo],
+    rent: RentRequest,
     program_id:&Pubkey) -> ProgramResult {
 
       let accounts_iter: &mut std::slice::Iter<'_, AccountInfo<'_>> = &mut accounts.iter();
@@ -296,249 +363,327 @@ impl Processor {
       let user: &AccountInfo<'_> = next_account_info(accounts_iter)?;
       let player: &AccountInfo<'_> = next_account_info(accounts_iter)?;
       let seller: &AccountInfo<'_> = next_account_info(accounts_iter)?;
-      let transfer_lamports: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let config: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let treasury: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let rent_escrow: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let system_program: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let listing_book: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+
+      if !user.is_signer{return Err(GameError::MissingSignature.into())}
 
+      let config_data: GameConfig = Self::load_config(config, treasury, program_id)?;
 
-      let player_acc_data: Player = Player::try_from_slice(&player.data.borrow())?; 
+      let player_acc_data: Player = Player::unpack(&player.data.borrow())?; 
 
 
       let owner_key: Pubkey = Pubkey::new_from_array(player_acc_data.owner);
 
-      if seller.key != &owner_key{panic!()}
+      if seller.key != &owner_key{return Err(GameError::NotPlayerOwner.into())}
 
-      if player.owner != program_id{panic!()} //if not a correct account panic
+      if player.owner != program_id{return Err(GameError::IncorrectProgramOwner.into())} //if not a correct account
 
-      let account_value: u64 = **transfer_lamports.lamports.borrow();
+      let payment_mint: Pubkey = Pubkey::new_from_array(player_acc_data.payment_mint);
 
-      if account_value < player_acc_data.sale_required_price {panic!()} //if not enough lamports to buy panic
+      let status: PlayerStatus = player_acc_data.status.transition(PlayerAction::Rent)?; //if not for rent
 
-      if player_acc_data.for_sale == "aa".to_string(){panic!()} //if not for rent panic
+      if rent.periods == 0 || rent.periods - 1 > player_acc_data.max_renewals{return Err(GameError::InvalidRentPeriods.into())}
 
+      let periods: u64 = rent.periods as u64;
+      let (price, seller_amount, fee) = Self::quote(&player_acc_data, ListingKind::Rent, periods, rent.max_price, &config_data)?;
+      let period_amount: u64 = seller_amount / periods;
+
+      Self::remove_listing(listing_book, ListingKind::Rent, player, &player_acc_data, program_id)?;
 
       let clock: Clock= Clock::get()?;
       let current_time: u64 = clock.unix_timestamp as u64;
 
-      let rent_ends_on: u64 = &current_time + &player_acc_data.rented_for_time;
+      let rent_ends_on: u64 = current_time + player_acc_data.rented_for_time;
 
       let new_owner: Player = Player{
         nft_adress:player_acc_data.nft_adress,
         owner:player_acc_data.owner,
-        for_sale:"aa".to_string(),
+        status,
         sale_required_price:0,
-        for_rent:"aa".to_string(),
         rent_required_price:0,
         who_rents:user.key.to_bytes(),
-        rented:1,
         rented_for_time:player_acc_data.rented_for_time,
         rent_end_on:rent_ends_on,
         offence:player_acc_data.offence,
         defence:player_acc_data.defence,
+        payment_mint:[0;32],
+        max_renewals:0,
+        listing_page:0,
+      };
+
+      let (escrow_key, escrow_bump) = Pubkey::find_program_address(&[RENT_SEED, &player.key.to_bytes()], program_id);
+
+      if rent_escrow.key != &escrow_key{return Err(GameError::InvalidRentEscrow.into())}
+
+      //the owner's share is held here and released as the rental runs, the fee goes to the treasury now
+      let escrow_data: RentEscrow = RentEscrow{
+        owner:player_acc_data.owner,
+        renter:user.key.to_bytes(),
+        player:player.key.to_bytes(),
+        payment_mint:player_acc_data.payment_mint,
+        amount:period_amount,
+        released:0,
+        start_time:current_time,
+        end_time:rent_ends_on,
+        bump:escrow_bump,
+        period:player_acc_data.rented_for_time,
+        renewals_left:rent.periods - 1,
+        prepaid:seller_amount - period_amount,
       };
 
-      **transfer_lamports.lamports.borrow_mut() -= player_acc_data.rent_required_price;
-      **seller.lamports.borrow_mut() += player_acc_data.rent_required_price;
+      Self::create_pda_account(user, rent_escrow, system_program, escrow_data.packed_len()?,
+        &[RENT_SEED, &player.key.to_bytes(), &[escrow_bump]], program_id)?;
+
+      if payment_mint == Pubkey::default() {
+        Self::pay_with_lamports(system_program, user, rent_escrow, treasury, seller_amount, fee)?;
+      }else{
+        let token_program: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+        let user_payment: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+        let escrow_payment: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+        let treasury_payment: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+        let mint: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+        let ata_program: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+
+        if token_program.key != &spl_token::id(){return Err(GameError::InvalidTokenAccount.into())}
+        if mint.key != &payment_mint{return Err(GameError::PaymentMintMismatch.into())}
+        if escrow_payment.key != &get_associated_token_address(rent_escrow.key, &payment_mint){return Err(GameError::InvalidEscrowAccount.into())}
+
+        invoke(
+          &create_associated_token_account_idempotent(
+            user.key,
+            rent_escrow.key,
+            mint.key,
+            token_program.key,
+          ),
+          &[
+            user.clone(),
+            escrow_payment.clone(),
+            rent_escrow.clone(),
+            mint.clone(),
+            system_program.clone(),
+            token_program.clone(),
+            ata_program.clone(),
+          ],
+        )?;
+
+        Self::pay_with_token(token_program, user, user_payment, escrow_payment, treasury_payment,
+          &payment_mint, rent_escrow.key, treasury.key, seller_amount, fee)?;
+      }
+
+      escrow_data.pack_into(&mut rent_escrow.data.borrow_mut())?;
 
-      new_owner.serialize(&mut &mut player.data.borrow_mut()[..])?;
+      msg!("rent: price {} periods {} escrowed {} fee {}", price, periods, seller_amount, fee);
+
+      new_owner.pack_into(&mut player.data.borrow_mut())?;
 
 
     Ok(())
   }
   fn sell_player(
     accounts: &[AccountInfo],
-    selloffer : SellOffer) -> ProgramResult {
+    selloffer : SellOffer,
+    program_id:&Pubkey) -> ProgramResult {
 
       let accounts_iter: &mut std::slice::Iter<'_, AccountInfo<'_>> = &mut accounts.iter();
 
       let user: &AccountInfo<'_> = next_account_info(accounts_iter)?;
       let player: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let nft: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let user_ata: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let pda: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let pda_ata: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let token_program: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let system_program: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let ata_program: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let listing_book: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+
 
+      if !user.is_signer{return Err(GameError::MissingSignature.into())}
 
-      if !user.is_signer{panic!()}
+      if player.owner != program_id{return Err(GameError::IncorrectProgramOwner.into())}
 
-      let player_acc_data: Player = Player::try_from_slice(&player.data.borrow())?;
+      let player_acc_data: Player = Player::unpack(&player.data.borrow())?;
 
 
       let owner_key: Pubkey = Pubkey::new_from_array(player_acc_data.owner);
 
-      if user.key != &owner_key{panic!()} // if you are not the owner you cant sell
+      if user.key != &owner_key{return Err(GameError::NotPlayerOwner.into())} // if you are not the owner you cant sell
+
+      let status: PlayerStatus = player_acc_data.status.transition(PlayerAction::ListForSale)?; //if rented or listed you cant sell
+
+      let nft_key: Pubkey = Pubkey::new_from_array(player_acc_data.nft_adress);
+
+      if nft.key != &nft_key{return Err(GameError::NftMintMismatch.into())}
 
-      if player_acc_data.rented != 0{panic!()} //if rented you cant sell
+      Self::load_vault_bump(pda, program_id)?;
 
-      //add price filter by changing the for sale_string according to price
-      //for now it is just XX
+      Self::deposit_nft(user, nft, user_ata, pda, pda_ata, token_program, system_program, ata_program)?;
+
+      let listing: Listing = Listing{
+        player:player.key.to_bytes(),
+        price:selloffer.sell_required_price,
+        offence:player_acc_data.offence,
+        defence:player_acc_data.defence,
+        payment_mint:selloffer.payment_mint,
+      };
+
+      Self::insert_listing(listing_book, user, system_program, ListingKind::Sale, selloffer.listing_page, listing, program_id)?;
 
       let forsale: Player = Player{
         nft_adress:player_acc_data.nft_adress,
         owner:player_acc_data.owner,
-        for_sale:"XX".to_string(),
+        status,
         sale_required_price:selloffer.sell_required_price,
-        for_rent:"aa".to_string(),
         rent_required_price:0,
         who_rents:player_acc_data.who_rents,
-        rented:0,
         rented_for_time:0,
         rent_end_on:0,
         offence:player_acc_data.offence,
         defence:player_acc_data.defence,
+        payment_mint:selloffer.payment_mint,
+        max_renewals:0,
+        listing_page:selloffer.listing_page,
       };
 
-      forsale.serialize(&mut &mut player.data.borrow_mut()[..])?;
+      forsale.pack_into(&mut player.data.borrow_mut())?;
 
    
     Ok(())
   }
   fn rent_your_player(
     accounts: &[AccountInfo],
-    rentoffer: RentOffer) -> ProgramResult {
+    rentoffer: RentOffer,
+    program_id:&Pubkey) -> ProgramResult {
 
       let accounts_iter: &mut std::slice::Iter<'_, AccountInfo<'_>> = &mut accounts.iter();
 
       let user: &AccountInfo<'_> = next_account_info(accounts_iter)?;
       let player: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let listing_book: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let system_program: &AccountInfo<'_> = next_account_info(accounts_iter)?;
 
-      if !user.is_signer{panic!()}
+      if !user.is_signer{return Err(GameError::MissingSignature.into())}
 
-      let player_acc_data: Player = Player::try_from_slice(&player.data.borrow())?;
+      if player.owner != program_id{return Err(GameError::IncorrectProgramOwner.into())}
+
+      let player_acc_data: Player = Player::unpack(&player.data.borrow())?;
 
       let owner_key: Pubkey = Pubkey::new_from_array(player_acc_data.owner);
 
-      if user.key != &owner_key{panic!()} // if you are not the owner you cant rent
+      if user.key != &owner_key{return Err(GameError::NotPlayerOwner.into())} // if you are not the owner you cant rent
+
+      let status: PlayerStatus = player_acc_data.status.transition(PlayerAction::ListForRent)?; //if rented, listed or in auction you cant rent
 
-      if player_acc_data.rented != 0{panic!()} //if already rented you cant rent
+      let listing: Listing = Listing{
+        player:player.key.to_bytes(),
+        price:rentoffer.rent_required_price,
+        offence:player_acc_data.offence,
+        defence:player_acc_data.defence,
+        payment_mint:rentoffer.payment_mint,
+      };
 
-      //add price filter by changing the for_rent string according to price
-      //for now it is just XX
+      Self::insert_listing(listing_book, user, system_program, ListingKind::Rent, rentoffer.listing_page, listing, program_id)?;
 
       let forsale: Player = Player{
         nft_adress:player_acc_data.nft_adress,
         owner:player_acc_data.owner,
-        for_sale:"aa".to_string(),
+        status,
         sale_required_price:0,
-        for_rent:"XX".to_string(),
         rent_required_price:rentoffer.rent_required_price,
         who_rents:player_acc_data.who_rents,
-        rented:0,
         rented_for_time:rentoffer.rented_for_time,
         rent_end_on:0,
         offence:player_acc_data.offence,
         defence:player_acc_data.defence,
+        payment_mint:rentoffer.payment_mint,
+        max_renewals:rentoffer.max_renewals,
+        listing_page:rentoffer.listing_page,
       };
 
-      forsale.serialize(&mut &mut player.data.borrow_mut()[..])?;
+      forsale.pack_into(&mut player.data.borrow_mut())?;
     
    
     Ok(())
   }
   fn set_team(
-    accounts: &[AccountInfo]) -> ProgramResult {
+    accounts: &[AccountInfo],
+    formation: Formation,
+    program_id:&Pubkey) -> ProgramResult {
 
       let accounts_iter: &mut std::slice::Iter<'_, AccountInfo<'_>> = &mut accounts.iter();
 
       let user: &AccountInfo<'_> = next_account_info(accounts_iter)?;
       let user_account: &AccountInfo<'_> = next_account_info(accounts_iter)?;
-      let offence_1: &AccountInfo<'_> = next_account_info(accounts_iter)?;
-      let offence_2: &AccountInfo<'_> = next_account_info(accounts_iter)?;
-      let middle: &AccountInfo<'_> = next_account_info(accounts_iter)?;
-      let defence_1: &AccountInfo<'_> = next_account_info(accounts_iter)?;
-      let defence_2: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let team: [&AccountInfo<'_>;TEAM_SIZE] = [
+        next_account_info(accounts_iter)?,
+        next_account_info(accounts_iter)?,
+        next_account_info(accounts_iter)?,
+        next_account_info(accounts_iter)?,
+        next_account_info(accounts_iter)?,
+      ];
 
-      let offence_1_data: Player = Player::try_from_slice(&offence_1.data.borrow())?;
-      let offence_2_data: Player = Player::try_from_slice(&offence_2.data.borrow())?;
-      let middle_data: Player = Player::try_from_slice(&middle.data.borrow())?;
-      let defence_1_data: Player = Player::try_from_slice(&defence_1.data.borrow())?;
-      let defence_2_data: Player = Player::try_from_slice(&defence_2.data.borrow())?;
-      let user_account_data: UserAccount = UserAccount::try_from_slice(&user_account.data.borrow())?;
+      let user_account_data: UserAccount = UserAccount::unpack(&user_account.data.borrow())?;
 
 
       let user_key: Pubkey = Pubkey::new_from_array(user_account_data.user_address);
 
-      if user.key != &user_key{panic!()}
+      if user.key != &user_key{return Err(GameError::NotAccountOwner.into())}
 
-
-      if offence_1_data.rented == 1 {
-        let owner_address: Pubkey = Pubkey::new_from_array(offence_1_data.who_rents);
-        if &owner_address != user.key{panic!()}
-      }else{
-        let owner_address: Pubkey = Pubkey::new_from_array(offence_1_data.owner);
-        if &owner_address != user.key{panic!()}
+      //a player fills one slot only
+      for (slot, player) in team.iter().enumerate() {
+        if player.owner != program_id{return Err(GameError::IncorrectProgramOwner.into())}
+        if team[..slot].iter().any(|other| other.key == player.key){return Err(GameError::DuplicatePlayer.into())}
       }
-      if offence_2_data.rented == 1 {
-        let owner_address: Pubkey = Pubkey::new_from_array(offence_2_data.who_rents);
-        if &owner_address != user.key{panic!()}
-      }else{
-        let owner_address: Pubkey = Pubkey::new_from_array(offence_2_data.owner);
-        if &owner_address != user.key{panic!()}
-      }
-      if middle_data.rented == 1 {
-        let owner_address: Pubkey = Pubkey::new_from_array(middle_data.who_rents);
-        if &owner_address != user.key{panic!()}
-      }else{
-        let owner_address: Pubkey = Pubkey::new_from_array(middle_data.owner);
-        if &owner_address != user.key{panic!()}
-      }
-      if defence_1_data.rented == 1 {
-        let owner_address: Pubkey = Pubkey::new_from_array(defence_1_data.who_rents);
-        if &owner_address != user.key{panic!()}
-      }else{
-        let owner_address: Pubkey = Pubkey::new_from_array(defence_1_data.owner);
-        if &owner_address != user.key{panic!()}
+
+      let players: [Player;TEAM_SIZE] = Self::unpack_team(&team)?;
+
+      let new_team: [[u8;32];TEAM_SIZE] = std::array::from_fn(|slot| players[slot].nft_adress);
+
+      //players of the old team that are not picked again are passed after the new ones
+      Self::release_team(user, &user_account_data.team, &new_team, accounts_iter.as_slice(), program_id)?;
+
+      //rented players are fielded by their renter, the others by their owner
+      for player in team {
+        let mut player_data: Player = Player::unpack(&player.data.borrow())?;
+
+        let controller: [u8;32] = if player_data.status == PlayerStatus::Rented {player_data.who_rents} else {player_data.owner};
+
+        if user.key.to_bytes() != controller{return Err(GameError::PlayerNotControlled.into())}
+
+        player_data.status = player_data.status.transition(PlayerAction::JoinTeam)?;
+        player_data.pack_into(&mut player.data.borrow_mut())?;
       }
-      if defence_2_data.rented == 1 {
-        let owner_address: Pubkey = Pubkey::new_from_array(defence_2_data.who_rents);
-        if &owner_address != user.key{panic!()}
-      }else{
-        let owner_address: Pubkey = Pubkey::new_from_array(defence_2_data.owner);
-        if &owner_address != user.key{panic!()}
-      }
-
-      if offence_1_data.for_sale == "aa"{panic!()}
-      if offence_2_data.for_sale == "aa"{panic!()}
-      if middle_data.for_sale == "aa"{panic!()}
-      if defence_1_data.for_sale == "aa"{panic!()}
-      if defence_1_data.for_sale == "aa"{panic!()}
-      if offence_1_data.for_rent == "aa"{panic!()}
-      if offence_2_data.for_rent == "aa"{panic!()}
-      if middle_data.for_rent == "aa"{panic!()}
-      if defence_1_data.for_rent == "aa"{panic!()}
-      if defence_1_data.for_rent == "aa"{panic!()}
-
-      let d1: u64 = defence_1_data.defence as u64;
-      let d2: u64 = defence_2_data.defence as u64;
-      let d1o: u64 = defence_1_data.defence as u64;
-      let d2o: u64 = defence_2_data.defence as u64;
-      let o1: u64 = offence_1_data.offence as u64;
-      let o2: u64 = offence_2_data.offence as u64;
-      let o1d: u64 = offence_1_data.offence as u64;
-      let o2d: u64 = offence_2_data.offence as u64;
-      let m1: u64 = middle_data.offence as u64;
-      let m2: u64 = middle_data.defence as u64;
-
-      let defence_power: u64 = ((d1+d2)*2)+((m1)*3);
-      let offence_power: u64 = ((o1+o2)*2)+((m2)*3);
-      let team_power: u64  = &defence_power + &offence_power+(d1o+d2o+o1d+o2d);
-
-      let formation: UserAccount = UserAccount{
+
+      let team_power: u64 = compute_team_power(formation, &players);
+
+      let user_team: UserAccount = UserAccount{
         user_address:user_account_data.user_address,
         user_name:user_account_data.user_name,
         user_name_length:user_account_data.user_name_length,
-        team_is_ready:1,
-        team_power:team_power,
-        team_class:"XX".to_string(),
-        defence_1:defence_1_data.nft_adress,
-        defence_2:defence_2_data.nft_adress,
-        middle:middle_data.nft_adress,
-        offence_1:offence_1_data.nft_adress,
-        offence_2:offence_2_data.nft_adress,
-        experienced:0
+        team_status:TeamStatus::Ready,
+        team_power,
+        formation,
+        team:new_team,
+        experienced:user_account_data.experienced,
+        wins:user_account_data.wins,
+        losses:user_account_data.losses,
+        draws:user_account_data.draws,
+        streak:user_account_data.streak,
+        rating:user_account_data.rating,
       };
 
-      formation.serialize(&mut &mut user_account.data.borrow_mut()[..])?;
+      user_team.pack_into(&mut user_account.data.borrow_mut())?;
 
 
     Ok(())
   }
   fn challenge(
     accounts: &[AccountInfo],
+    challengedata: ChallengeData,
     program_id:&Pubkey) -> ProgramResult {
 
       let accounts_iter: &mut std::slice::Iter<'_, AccountInfo<'_>> = &mut accounts.iter();
@@ -546,17 +691,54 @@ impl Processor {
       let user: &AccountInfo<'_> = next_account_info(accounts_iter)?; 
       let user_account: &AccountInfo<'_> = next_account_info(accounts_iter)?; 
       let opponent_account: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let match_record: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let system_program: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let commit: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let slot_hashes: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let team: [&AccountInfo<'_>;TEAM_SIZE] = [
+        next_account_info(accounts_iter)?,
+        next_account_info(accounts_iter)?,
+        next_account_info(accounts_iter)?,
+        next_account_info(accounts_iter)?,
+        next_account_info(accounts_iter)?,
+      ];
+      let opponent_team: [&AccountInfo<'_>;TEAM_SIZE] = [
+        next_account_info(accounts_iter)?,
+        next_account_info(accounts_iter)?,
+        next_account_info(accounts_iter)?,
+        next_account_info(accounts_iter)?,
+        next_account_info(accounts_iter)?,
+      ];
+
+      if user_account.key == opponent_account.key{return Err(GameError::SelfChallenge.into())}
+
+      let mut user_account_data: UserAccount = UserAccount::unpack(&user_account.data.borrow())?;
+      let mut opponent_account_data: UserAccount = UserAccount::unpack(&opponent_account.data.borrow())?;
+
+      if user_account.owner != program_id{return Err(GameError::IncorrectProgramOwner.into())}
+      if opponent_account.owner != program_id{return Err(GameError::IncorrectProgramOwner.into())}
+
 
-      let user_account_data: UserAccount = UserAccount::try_from_slice(&user_account.data.borrow())?;
-      let opponent_account_data: UserAccount = UserAccount::try_from_slice(&opponent_account.data.borrow())?;
+      let user_key: Pubkey = Pubkey::new_from_array(user_account_data.user_address);
 
-      if user_account.owner != program_id{panic!()}
-      if opponent_account.owner != program_id{panic!()}
+      if user.key != &user_key {return Err(GameError::NotAccountOwner.into())}
 
+      if user_account_data.team_status != TeamStatus::Ready{return Err(GameError::TeamNotReady.into())}
+      if opponent_account_data.team_status != TeamStatus::Ready{return Err(GameError::TeamNotReady.into())}
 
-      let user_key: Pubkey = Pubkey::new_from_array(user_account_data.user_address);
+      //the cached powers may be stale, the match is played with the players both sides control now
+      let user_players: [Player;TEAM_SIZE] = Self::verify_team(&user_account_data, &team, program_id)?
+        .ok_or(GameError::TeamNotEligible)?;
+      let opponent_players: [Player;TEAM_SIZE] = Self::verify_team(&opponent_account_data, &opponent_team, program_id)?
+        .ok_or(GameError::TeamNotEligible)?;
+
+      user_account_data.team_power = compute_team_power(user_account_data.formation, &user_players);
+      opponent_account_data.team_power = compute_team_power(opponent_account_data.formation, &opponent_players);
+
+      let match_id: [u8;8] = challengedata.match_id.to_le_bytes();
+      let (match_key, match_bump) = Pubkey::find_program_address(&[MATCH_SEED, &user.key.to_bytes(), &match_id], program_id);
 
-      if user.key != &user_key {panic!()}
+      if match_record.key != &match_key{return Err(GameError::InvalidMatchRecord.into())}
 
       let  mut exp: u64 = user_account_data.experienced;
 
@@ -589,7 +771,7 @@ impl Processor {
 
       }else{
 
-        let difference: u64 = opponent_account_data.team_power - user_account_data.team_power;
+        let difference: u64 = user_account_data.team_power - opponent_account_data.team_power;
 
         if difference < 5{
           exp += 7;
@@ -615,114 +797,118 @@ impl Processor {
 
       }
 
-      let seed:&[u8] = &user.key.to_bytes();
+      let user_lineup: Lineup = Lineup{formation:user_account_data.formation, players:&user_players};
 
+      //the commit is bound to the opponent so the reveal cannot be spent on another match,
+      //and to the lineup so the team cannot be changed once the slot hash is known
+      let rand: keccak::Hash = Self::reveal_randomness(user, commit, slot_hashes, opponent_account.key, &challengedata.secret, Some(&user_lineup), program_id)?;
 
-      let rand: keccak::Hash = keccak::hashv(&[seed,
-      &user_account_data.user_name.to_string().as_bytes(),&opponent_account_data.user_name.as_bytes()]);
+      let seed: [u8;32] = rand.to_bytes();
 
-      let ran_str: String = rand.to_string();
+      let result: MatchResult = simulation::simulate(
+        &user_lineup,
+        &Lineup{formation:opponent_account_data.formation, players:&opponent_players},
+        &seed,
+        simulation::POSSESSIONS,
+      );
 
-      let ran_bytes: &[u8] = ran_str.as_bytes();
+      let user_score: i32 = result.user_score as i32;
+      let opponent_score: i32 = result.opponent_score as i32;
 
-      let mut index: usize = 0;
-      let mut opponent_score: i32 = 0;
-      let mut user_score: i32 = 0;
+      let mut user_result: UserAccount = user_account_data.clone();
+      let mut opponent_result: UserAccount = opponent_account_data.clone();
 
-      loop {
-          
-        let  opponent_team_power: u64 = opponent_account_data.team_power + ran_bytes[index] as u64;
-        if opponent_team_power > user_account_data.team_power{
-          opponent_score+=1;
-        }else{
-          user_score+=1;
-        }
-        index+=1;
-        if index == 3{break}
-      };
+      if user_score > opponent_score {
+        user_result.experienced = exp;
+      }
 
-      loop {
-  
-        index+=1;
+      Self::update_stats(&mut user_result, &mut opponent_result, user_score, opponent_score);
 
-        let user_team_power: u64 = user_account_data.team_power + ran_bytes[index] as u64;
-        if user_team_power > opponent_account_data.team_power{
-          user_score+=1;
-        }else{
-          opponent_score+=1;
-        }
-        index+=1;
-        if index == 4{break}
-      };
+      user_result.pack_into(&mut user_account.data.borrow_mut())?;
+      opponent_result.pack_into(&mut opponent_account.data.borrow_mut())?;
 
-      let experienced: UserAccount = UserAccount{
-        user_address:user_account_data.user_address,
-        user_name:user_account_data.user_name,
-        user_name_length:user_account_data.user_name_length,
-        team_is_ready:user_account_data.team_is_ready,
-        team_power:user_account_data.team_power,
-        team_class:user_account_data.team_class,
-        defence_1:user_account_data.defence_1,
-        defence_2:user_account_data.defence_2,
-        middle:user_account_data.middle,
-        offence_1:user_account_data.offence_1,
-        offence_2:user_account_data.offence_2,
-        experienced:exp,
+      let winner: [u8;32] = if user_score > opponent_score {
+        user_account_data.user_address
+      }else if opponent_score > user_score {
+        opponent_account_data.user_address
+      }else{
+        [0;32]
       };
 
+      let clock: Clock= Clock::get()?;
 
-      if user_score > opponent_score {
+      let record: MatchRecord = MatchRecord{
+        match_id:challengedata.match_id,
+        user:user_account_data.user_address,
+        opponent:opponent_account_data.user_address,
+        user_team_power:user_account_data.team_power,
+        opponent_team_power:opponent_account_data.team_power,
+        seed,
+        possessions:simulation::POSSESSIONS,
+        user_score:result.user_score,
+        opponent_score:result.opponent_score,
+        goals:result.goals,
+        winner,
+        slot:clock.slot,
+        played_on:clock.unix_timestamp as u64,
+      };
 
-        experienced.serialize(&mut &mut user_account.data.borrow_mut()[..])?;
+      Self::create_pda_account(user, match_record, system_program, record.packed_len()?,
+        &[MATCH_SEED, &user.key.to_bytes(), &match_id, &[match_bump]], program_id)?;
 
-      }
+      record.pack_into(&mut match_record.data.borrow_mut())?;
 
+      //closed after the cpis above so the refund is not part of their balance check
+      Self::close_program_account(commit, user)?;
 
     
         Ok(())
   }  
   fn abort_rent(
-    accounts: &[AccountInfo]) -> ProgramResult {
+    accounts: &[AccountInfo],
+    program_id:&Pubkey) -> ProgramResult {
 
       let accounts_iter: &mut std::slice::Iter<'_, AccountInfo<'_>> = &mut accounts.iter();
 
       let user: &AccountInfo<'_> = next_account_info(accounts_iter)?;
       let player: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let listing_book: &AccountInfo<'_> = next_account_info(accounts_iter)?;
 
-      if !user.is_signer{panic!()}
+      if !user.is_signer{return Err(GameError::MissingSignature.into())}
 
-      let player_acc_data: Player = Player::try_from_slice(&player.data.borrow())?;
+      let player_acc_data: Player = Player::unpack(&player.data.borrow())?;
 
       let owner_key: Pubkey = Pubkey::new_from_array(player_acc_data.owner);
 
-      if user.key != &owner_key{panic!()} // if you are not the owner you cant cancel rent
+      if user.key != &owner_key{return Err(GameError::NotPlayerOwner.into())} // if you are not the owner you cant cancel rent
 
-      if player_acc_data.rented != 0{panic!()} //if already rented you cant cancel rent
+      let status: PlayerStatus = player_acc_data.status.transition(PlayerAction::AbortRent)?; //if already rented you cant cancel rent
 
-      //add price filter by changing the for_rent string according to price
-      //for now it is just XX
+      Self::remove_listing(listing_book, ListingKind::Rent, player, &player_acc_data, program_id)?;
 
       let abortrent: Player = Player{
         nft_adress:player_acc_data.nft_adress,
         owner:player_acc_data.owner,
-        for_sale:"aa".to_string(),
+        status,
         sale_required_price:0,
-        for_rent:"aa".to_string(),
         rent_required_price:0,
         who_rents:player_acc_data.who_rents,
-        rented:0,
         rented_for_time:0,
         rent_end_on:0,
         offence:player_acc_data.offence,
         defence:player_acc_data.defence,
+        payment_mint:[0;32],
+        max_renewals:0,
+        listing_page:0,
       };
 
-      abortrent.serialize(&mut &mut player.data.borrow_mut()[..])?;
+      abortrent.pack_into(&mut player.data.borrow_mut())?;
    
     Ok(())
   }  
   fn abort_sell(
-    accounts: &[AccountInfo]) -> ProgramResult {
+    accounts: &[AccountInfo],
+    program_id:&Pubkey) -> ProgramResult {
 
       let accounts_iter: &mut std::slice::Iter<'_, AccountInfo<'_>> = &mut accounts.iter();
 
@@ -732,116 +918,156 @@ impl Processor {
       let pda: &AccountInfo<'_> = next_account_info(accounts_iter)?;
       let pda_ata: &AccountInfo<'_> = next_account_info(accounts_iter)?;
       let user_ata: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let listing_book: &AccountInfo<'_> = next_account_info(accounts_iter)?;
 
 
-      if !user.is_signer{panic!()}
+      if !user.is_signer{return Err(GameError::MissingSignature.into())}
 
-      let player_acc_data: Player = Player::try_from_slice(&player.data.borrow())?;
+      let player_acc_data: Player = Player::unpack(&player.data.borrow())?;
 
       let owner_key: Pubkey = Pubkey::new_from_array(player_acc_data.owner);
 
-      if user.key != &owner_key{panic!()} // if you are not the owner you cant cancel rent
+      if user.key != &owner_key{return Err(GameError::NotPlayerOwner.into())} // if you are not the owner you cant cancel rent
+
+      let status: PlayerStatus = player_acc_data.status.transition(PlayerAction::AbortSale)?; //auctions are closed with CancelAuction
+
+      let bump: u8 = Self::load_vault_bump(pda, program_id)?;
+
+      let nft_key: Pubkey = Pubkey::new_from_array(player_acc_data.nft_adress);
 
-      if player_acc_data.rented != 0{panic!()} //if already rented you cant cancel rent
+      if pda_ata.key != &get_associated_token_address(pda.key, &nft_key){return Err(GameError::InvalidEscrowAccount.into())}
 
 
       let abortsale: Player = Player{
         nft_adress:player_acc_data.nft_adress,
         owner:player_acc_data.owner,
-        for_sale:"aa".to_string(),
+        status,
         sale_required_price:0,
-        for_rent:"aa".to_string(),
         rent_required_price:0,
         who_rents:player_acc_data.who_rents,
-        rented:0,
         rented_for_time:0,
         rent_end_on:0,
         offence:player_acc_data.offence,
         defence:player_acc_data.defence,
+        payment_mint:[0;32],
+        max_renewals:0,
+        listing_page:0,
       };
 
-      abortsale.serialize(&mut &mut player.data.borrow_mut()[..])?;
+      Self::remove_listing(listing_book, ListingKind::Sale, player, &player_acc_data, program_id)?;
 
-      let amount:u64 = 1;
+      abortsale.pack_into(&mut player.data.borrow_mut())?;
 
     //transfer from pda_ata to user
-      let trans_ix = transfer( &token_program.key,
-        &pda_ata.key, 
-        &user_ata.key, 
-        &pda.key, 
-        &[&pda.key], 
-        amount)?;
-
-      invoke_signed(
-        &trans_ix,
-        &[
-          token_program.clone(),
-          pda_ata.clone(),
-          user_ata.clone(),
-          pda.clone(),
-        ],
-        &[&[b"battleballs", &[254]]],
-      )?;
-
-      let close_ix = close_account(&token_program.key, 
-        &pda_ata.key,
-         &user.key, 
-         &pda.key,
-         &[&pda.key])?;
-
-      invoke_signed(
-          &close_ix,
-          &[
-            token_program.clone(),
-            pda_ata.clone(),
-            user.clone(),
-            pda.clone(),
-          ],
-          &[&[b"battleballs", &[254]]],
-        )?;
-
+      Self::withdraw_nft(token_program, pda, pda_ata, user_ata, user, bump)?;
 
    
     Ok(())
   }////////////
   fn claim_player_back(
-    accounts: &[AccountInfo]) -> ProgramResult {
+    accounts: &[AccountInfo],
+    program_id:&Pubkey) -> ProgramResult {
 
       let accounts_iter: &mut std::slice::Iter<'_, AccountInfo<'_>> = &mut accounts.iter();
 
       let user: &AccountInfo<'_> = next_account_info(accounts_iter)?;
       let player: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let rent_escrow: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let renter: &AccountInfo<'_> = next_account_info(accounts_iter)?;
 
-      let player_acc_data: Player = Player::try_from_slice(&player.data.borrow())?;
+      let player_acc_data: Player = Player::unpack(&player.data.borrow())?;
 
 
       let owner_key: Pubkey = Pubkey::new_from_array(player_acc_data.owner);
 
-      if user.key != &owner_key{panic!()} // if you are not the owner you cant claim back
+      if user.key != &owner_key{return Err(GameError::NotPlayerOwner.into())} // if you are not the owner you cant claim back
 
       let clock: Clock= Clock::get()?;
       let current_time: u64 = clock.unix_timestamp as u64;
 
-      if current_time < player_acc_data.rent_end_on{panic!()} // rent is no over yet
+      if current_time < player_acc_data.rent_end_on{return Err(GameError::RentNotOver.into())} // rent is no over yet
 
+      Self::end_rental(player, &player_acc_data, rent_escrow, user, renter, accounts_iter.as_slice(), current_time, program_id)?;
+   
+    Ok(())
+  }
+  fn release_rent(
+    accounts: &[AccountInfo],
+    program_id:&Pubkey) -> ProgramResult {
 
-      let rentover: Player = Player{
-        nft_adress:player_acc_data.nft_adress,
-        owner:player_acc_data.owner,
-        for_sale:"aa".to_string(),
-        sale_required_price:0,
-        for_rent:"aa".to_string(),
-        rent_required_price:0,
-        who_rents:[0;32],
-        rented:0,
-        rented_for_time:0,
-        rent_end_on:0,
-        offence:player_acc_data.offence,
-        defence:player_acc_data.defence,
-      };
+      let accounts_iter: &mut std::slice::Iter<'_, AccountInfo<'_>> = &mut accounts.iter();
+
+      let player: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let rent_escrow: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let owner: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+
+      let mut escrow_data: RentEscrow = Self::load_rent_escrow(rent_escrow, player, program_id)?
+        .ok_or(GameError::PlayerNotRented)?;
+
+      if owner.key.to_bytes() != escrow_data.owner{return Err(GameError::NotPlayerOwner.into())}
+
+      let clock: Clock= Clock::get()?;
+      let current_time: u64 = clock.unix_timestamp as u64;
+
+      //anyone may crank this, the earned rent can only go to the owner
+      let earned: u64 = escrow_data.earned_at(current_time);
+      let due: u64 = earned - escrow_data.released;
+
+      if due > 0 {
+        let payment_mint: Pubkey = Pubkey::new_from_array(escrow_data.payment_mint);
+
+        if payment_mint == Pubkey::default() {
+          Self::pay_from_escrow(rent_escrow, &escrow_data, owner, None, due)?;
+        }else{
+          let token_program: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+          let escrow_payment: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+          let owner_payment: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+
+          Self::pay_from_escrow(rent_escrow, &escrow_data, owner, Some((token_program, escrow_payment, owner_payment)), due)?;
+        }
+      }
+
+      escrow_data.released = earned;
+
+      escrow_data.pack_into(&mut rent_escrow.data.borrow_mut())?;
+
+      msg!("rent: released {} of {}", earned, escrow_data.amount);
+
+    Ok(())
+  }
+  fn terminate_rent(
+    accounts: &[AccountInfo],
+    program_id:&Pubkey) -> ProgramResult {
+
+      let accounts_iter: &mut std::slice::Iter<'_, AccountInfo<'_>> = &mut accounts.iter();
+
+      let user: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let player: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let rent_escrow: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let owner: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let renter: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+
+      if !user.is_signer{return Err(GameError::MissingSignature.into())}
+      if player.owner != program_id{return Err(GameError::IncorrectProgramOwner.into())}
+
+      let player_acc_data: Player = Player::unpack(&player.data.borrow())?;
+
+      player_acc_data.status.transition(PlayerAction::EndRent)?;
+
+      if owner.key.to_bytes() != player_acc_data.owner{return Err(GameError::NotPlayerOwner.into())}
+      if renter.key.to_bytes() != player_acc_data.who_rents{return Err(GameError::NotRentParty.into())}
+
+      if user.key != owner.key && user.key != renter.key{return Err(GameError::NotRentParty.into())}
+
+      let clock: Clock= Clock::get()?;
+      let current_time: u64 = clock.unix_timestamp as u64;
+
+      //the renter may stop early and is refunded the unused time, the owner has to let the paid
+      //period run out and can then only stop the prepaid periods from starting
+      if user.key != renter.key && current_time < player_acc_data.rent_end_on{return Err(GameError::RentNotOver.into())}
+
+      Self::end_rental(player, &player_acc_data, rent_escrow, owner, renter, accounts_iter.as_slice(), current_time, program_id)?;
 
-      rentover.serialize(&mut &mut player.data.borrow_mut()[..])?;
-   
     Ok(())
   }
   fn claim_nft_player(
@@ -854,38 +1080,41 @@ impl Processor {
       let player: &AccountInfo<'_> = next_account_info(accounts_iter)?;
       let nft: &AccountInfo<'_> = next_account_info(accounts_iter)?;
 
-      let player_acc_data: Player = Player::try_from_slice(&player.data.borrow())?;
+      let player_acc_data: Player = Player::unpack(&player.data.borrow())?;
+
+      let status: PlayerStatus = player_acc_data.status.transition(PlayerAction::ClaimNft)?; //listed, rented and escrowed players keep their owner
 
       let player_nft_key: Pubkey = Pubkey::new_from_array(player_acc_data.nft_adress);
 
-      if nft.key != &player_nft_key{panic!()} // nft adress of player account and nft(token mint) matches
-      if user_ata.owner!=&spl_token::id(){panic!()}
+      if nft.key != &player_nft_key{return Err(GameError::NftMintMismatch.into())} // nft adress of player account and nft(token mint) matches
+      if user_ata.owner!=&spl_token::id(){return Err(GameError::InvalidTokenAccount.into())}
       let user_ata_unpacked: Account = Account::unpack_from_slice(&user_ata.data.borrow())?;//
-      if user.key != &user_ata_unpacked.owner{panic!()}//is the owner of ata user?
-      if user_ata_unpacked.amount != 1{panic!()} //has user the nft?
-      if  nft.key != &user_ata_unpacked.mint {panic!()}//is ata and nft(toekn mint) related?
+      if user.key != &user_ata_unpacked.owner{return Err(GameError::TokenAccountOwnerMismatch.into())}//is the owner of ata user?
+      if user_ata_unpacked.amount != 1{return Err(GameError::NftNotHeld.into())} //has user the nft?
+      if  nft.key != &user_ata_unpacked.mint {return Err(GameError::NftMintMismatch.into())}//is ata and nft(toekn mint) related?
 
       let mut user_address_holder: String = String::from("XXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXX");
       let user_address: &String = &user.key.to_string();
       let offset: usize = user_address.len();
-      user_address_holder.replace_range(..offset, &user_address);
+      user_address_holder.replace_range(..offset, user_address);
 
       let rentover: Player = Player{
         nft_adress:player_acc_data.nft_adress,
         owner:user.key.to_bytes(),
-        for_sale:"aa".to_string(),
+        status,
         sale_required_price:0,
-        for_rent:"aa".to_string(),
         rent_required_price:0,
         who_rents:[0;32],
-        rented:0,
         rented_for_time:0,
         rent_end_on:0,
         offence:player_acc_data.offence,
         defence:player_acc_data.defence,
+        payment_mint:[0;32],
+        max_renewals:0,
+        listing_page:0,
       };
 
-      rentover.serialize(&mut &mut player.data.borrow_mut()[..])?;
+      rentover.pack_into(&mut player.data.borrow_mut())?;
 
     Ok(())
   }
@@ -896,97 +1125,1628 @@ impl Processor {
       let accounts_iter: &mut std::slice::Iter<'_, AccountInfo<'_>> = &mut accounts.iter();
       let pda: &AccountInfo<'_> = next_account_info(accounts_iter)?;
       let user: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let system_program: &AccountInfo<'_> = next_account_info(accounts_iter)?;
 
-      invoke_signed(
-        &system_instruction::create_account( 
-            &user.key, 
-            &pda.key,
-            1000000,
-            0,
-            &program_id
-        ),
-        &[
-            user.clone(), 
-            pda.clone(),
-        ],
-        &[&[b"battleballs", &[254]]],
-      )?;
+      let (pda_key, bump) = Pubkey::find_program_address(&[VAULT_SEED], program_id);
+
+      if pda.key != &pda_key{return Err(GameError::InvalidPda.into())}
+
+      let vault: Vault = Vault{bump};
+
+      Self::create_pda_account(user, pda, system_program, vault.packed_len()?, &[VAULT_SEED, &[bump]], program_id)?;
+
+      vault.pack_into(&mut pda.data.borrow_mut())?;
    
     Ok(())
   }
   fn upgrade_player(
     accounts: &[AccountInfo],
-    upgrade: UpgradePlayer) -> ProgramResult {
+    upgrade: UpgradePlayer,
+    program_id:&Pubkey) -> ProgramResult {
 
       let accounts_iter: &mut std::slice::Iter<'_, AccountInfo<'_>> = &mut accounts.iter();
 
+      let user: &AccountInfo<'_> = next_account_info(accounts_iter)?;
       let user_account: &AccountInfo<'_> = next_account_info(accounts_iter)?;
-      let player: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let team: [&AccountInfo<'_>;TEAM_SIZE] = [
+        next_account_info(accounts_iter)?,
+        next_account_info(accounts_iter)?,
+        next_account_info(accounts_iter)?,
+        next_account_info(accounts_iter)?,
+        next_account_info(accounts_iter)?,
+      ];
 
+      let user_account_data: UserAccount = UserAccount::unpack(&user_account.data.borrow())?;
 
-      let mut player_data: Player = Player::try_from_slice(&player.data.borrow())?;
-      let user_account_data: UserAccount = UserAccount::try_from_slice(&user_account.data.borrow())?;
+      if user.key.to_bytes() != user_account_data.user_address{return Err(GameError::NotAccountOwner.into())}
 
-      if user_account_data.experienced < upgrade.exp{panic!()}
+      if user_account_data.experienced < upgrade.exp{return Err(GameError::NotEnoughExperience.into())}
 
-      let mut new_team_power: u64 = 0;
-        let old_team_power: u64 = user_account_data.team_power;
-        let offence: u64 = player_data.offence as u64;
-      let defence: u64 = player_data.defence as u64;
-      let player_power: u64 = (offence+defence)*2;
+      let slot: usize = upgrade.slot as usize;
 
+      if slot >= TEAM_SIZE{return Err(GameError::InvalidTeamSlot.into())}
 
-      if upgrade.player_no == 1 {
-        let player_key: Pubkey = Pubkey::new_from_array(user_account_data.offence_1);
-        if player.key != &player_key{panic!()}
-        new_team_power = (old_team_power - player_power) + (((offence+upgrade.exp)*2)+(defence+upgrade.exp));
-      }else if upgrade.player_no == 2{
-        let player_key: Pubkey = Pubkey::new_from_array(user_account_data.offence_2);
-        if player.key != &player_key{panic!()}
-        new_team_power = (old_team_power - player_power) + (((offence+upgrade.exp)*2)+(defence+upgrade.exp));
-      }else if upgrade.player_no == 3{
-        let player_key: Pubkey = Pubkey::new_from_array(user_account_data.middle);
-        if player.key != &player_key{panic!()}
-        new_team_power = (old_team_power - player_power) + (((offence+upgrade.exp)+(defence+upgrade.exp))*3);
-      }else if upgrade.player_no == 4{
-        let player_key: Pubkey = Pubkey::new_from_array(user_account_data.defence_1);
-        if player.key != &player_key{panic!()}
-        new_team_power = (old_team_power - player_power) + (((defence+upgrade.exp)*2)+(offence+upgrade.exp));
-      }else if upgrade.player_no == 5{
-        let player_key: Pubkey = Pubkey::new_from_array(user_account_data.defence_2);
-        if player.key != &player_key{panic!()}
-        new_team_power = (old_team_power - player_power) + (((defence+upgrade.exp)*2)+(offence+upgrade.exp));
-      }
+      if user_account_data.team_status != TeamStatus::Ready{return Err(GameError::TeamNotReady.into())}
 
+      //experience only goes to players the user still controls, as in a challenge
+      let mut players: [Player;TEAM_SIZE] = Self::verify_team(&user_account_data, &team, program_id)?
+        .ok_or(GameError::TeamNotEligible)?;
+
+      players[slot].offence = players[slot].offence.checked_add(upgrade.exp).ok_or(GameError::StatOverflow)?;
+      players[slot].defence = players[slot].defence.checked_add(upgrade.exp).ok_or(GameError::StatOverflow)?;
+
+      let new_team_power: u64 = compute_team_power(user_account_data.formation, &players);
 
       let experience_left: u64 = user_account_data.experienced - upgrade.exp;
 
 
-      let formation: UserAccount = UserAccount{
+      let user_team: UserAccount = UserAccount{
         user_address:user_account_data.user_address,
         user_name:user_account_data.user_name,
         user_name_length:user_account_data.user_name_length,
-        team_is_ready:1,
+        team_status:user_account_data.team_status,
         team_power:new_team_power,
-        team_class:user_account_data.team_class,
-        defence_1:user_account_data.defence_1,
-        defence_2:user_account_data.defence_2,
-        middle:user_account_data.middle,
-        offence_1:user_account_data.offence_1,
-        offence_2:user_account_data.offence_2,
-        experienced:experience_left
+        formation:user_account_data.formation,
+        team:user_account_data.team,
+        experienced:experience_left,
+        wins:user_account_data.wins,
+        losses:user_account_data.losses,
+        draws:user_account_data.draws,
+        streak:user_account_data.streak,
+        rating:user_account_data.rating,
       };
 
-      player_data.offence += upgrade.exp;
-      player_data.defence += upgrade.exp;
-  
-      player_data.serialize(&mut &mut player.data.borrow_mut()[..])?;
-      formation.serialize(&mut &mut user_account.data.borrow_mut()[..])?;
+      players[slot].pack_into(&mut team[slot].data.borrow_mut())?;
+      user_team.pack_into(&mut user_account.data.borrow_mut())?;
 
    
     Ok(())
   }
   
+  fn create_auction(
+    accounts: &[AccountInfo],
+    auctionoffer: AuctionOffer,
+    program_id:&Pubkey) -> ProgramResult {
+
+      let accounts_iter: &mut std::slice::Iter<'_, AccountInfo<'_>> = &mut accounts.iter();
+
+      let seller: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let player: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let auction: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let nft: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let seller_ata: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let pda: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let pda_ata: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let token_program: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let system_program: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let ata_program: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+
+      if !seller.is_signer{return Err(GameError::MissingSignature.into())}
+
+      if player.owner != program_id{return Err(GameError::IncorrectProgramOwner.into())}
+
+      let player_acc_data: Player = Player::unpack(&player.data.borrow())?;
+
+      let owner_key: Pubkey = Pubkey::new_from_array(player_acc_data.owner);
+
+      if seller.key != &owner_key{return Err(GameError::NotPlayerOwner.into())}
+
+      let status: PlayerStatus = player_acc_data.status.transition(PlayerAction::StartAuction)?;
+
+      let nft_key: Pubkey = Pubkey::new_from_array(player_acc_data.nft_adress);
+
+      if nft.key != &nft_key{return Err(GameError::NftMintMismatch.into())}
+
+      let (auction_key, auction_bump) = Pubkey::find_program_address(&[AUCTION_SEED, &player.key.to_bytes()], program_id);
+
+      if auction.key != &auction_key{return Err(GameError::InvalidAuction.into())}
+
+      Self::load_vault_bump(pda, program_id)?;
+
+      let clock: Clock= Clock::get()?;
+      let current_time: u64 = clock.unix_timestamp as u64;
+      let end_time: u64 = current_time.checked_add(auctionoffer.duration).ok_or(GameError::InvalidAuctionDuration)?;
+
+      Self::deposit_nft(seller, nft, seller_ata, pda, pda_ata, token_program, system_program, ata_program)?;
+
+      let auction_data: Auction = Auction{
+        seller:seller.key.to_bytes(),
+        player:player.key.to_bytes(),
+        nft_adress:player_acc_data.nft_adress,
+        reserve_price:auctionoffer.reserve_price,
+        end_time,
+        highest_bid:0,
+        highest_bidder:[0;32],
+        bump:auction_bump,
+      };
+
+      Self::create_pda_account(seller, auction, system_program, auction_data.packed_len()?,
+        &[AUCTION_SEED, &player.key.to_bytes(), &[auction_bump]], program_id)?;
+
+      auction_data.pack_into(&mut auction.data.borrow_mut())?;
+
+      let inauction: Player = Player{
+        nft_adress:player_acc_data.nft_adress,
+        owner:player_acc_data.owner,
+        status,
+        sale_required_price:auctionoffer.reserve_price,
+        rent_required_price:0,
+        who_rents:player_acc_data.who_rents,
+        rented_for_time:0,
+        rent_end_on:0,
+        offence:player_acc_data.offence,
+        defence:player_acc_data.defence,
+        payment_mint:[0;32],
+        max_renewals:0,
+        listing_page:0,
+      };
+
+      inauction.pack_into(&mut player.data.borrow_mut())?;
+
+    Ok(())
+  }
+  fn place_bid(
+    accounts: &[AccountInfo],
+    bid: Bid,
+    program_id:&Pubkey) -> ProgramResult {
+
+      let accounts_iter: &mut std::slice::Iter<'_, AccountInfo<'_>> = &mut accounts.iter();
+
+      let bidder: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let auction: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let previous_bidder: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let system_program: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+
+      if !bidder.is_signer{return Err(GameError::MissingSignature.into())}
+
+      let mut auction_data: Auction = Self::load_auction(auction, program_id)?;
+
+      let clock: Clock= Clock::get()?;
+      let current_time: u64 = clock.unix_timestamp as u64;
+
+      if current_time >= auction_data.end_time{return Err(GameError::AuctionEnded.into())}
+
+      if bid.amount < auction_data.reserve_price{return Err(GameError::BidTooLow.into())}
+      if bid.amount <= auction_data.highest_bid{return Err(GameError::BidTooLow.into())}
+
+      //bid is held by the auction account until settle
+      invoke(
+        &system_instruction::transfer(bidder.key, auction.key, bid.amount),
+        &[
+          bidder.clone(),
+          auction.clone(),
+          system_program.clone(),
+        ],
+      )?;
+
+      //refund the bidder that was outbid
+      if auction_data.highest_bid > 0 {
+        let highest_bidder: Pubkey = Pubkey::new_from_array(auction_data.highest_bidder);
+
+        if previous_bidder.key != &highest_bidder{return Err(GameError::BidderMismatch.into())}
+
+        **auction.lamports.borrow_mut() -= auction_data.highest_bid;
+        **previous_bidder.lamports.borrow_mut() += auction_data.highest_bid;
+      }
+
+      auction_data.highest_bid = bid.amount;
+      auction_data.highest_bidder = bidder.key.to_bytes();
+
+      auction_data.pack_into(&mut auction.data.borrow_mut())?;
+
+    Ok(())
+  }
+  fn settle_auction(
+    accounts: &[AccountInfo],
+    program_id:&Pubkey) -> ProgramResult {
+
+      let accounts_iter: &mut std::slice::Iter<'_, AccountInfo<'_>> = &mut accounts.iter();
+
+      let payer: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let auction: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let player: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let nft: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let seller: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let winner: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let winner_ata: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let pda: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let pda_ata: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let config: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let treasury: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let token_program: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let system_program: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let ata_program: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+
+      let auction_data: Auction = Self::load_auction(auction, program_id)?;
+
+      let clock: Clock= Clock::get()?;
+      let current_time: u64 = clock.unix_timestamp as u64;
+
+      if current_time < auction_data.end_time{return Err(GameError::AuctionNotEnded.into())}
+      if auction_data.highest_bid == 0{return Err(GameError::AuctionHasNoBids.into())}
+
+      let seller_key: Pubkey = Pubkey::new_from_array(auction_data.seller);
+      let winner_key: Pubkey = Pubkey::new_from_array(auction_data.highest_bidder);
+      let player_key: Pubkey = Pubkey::new_from_array(auction_data.player);
+      let nft_key: Pubkey = Pubkey::new_from_array(auction_data.nft_adress);
+
+      if seller.key != &seller_key{return Err(GameError::NotPlayerOwner.into())}
+      if winner.key != &winner_key{return Err(GameError::BidderMismatch.into())}
+      if player.key != &player_key{return Err(GameError::InvalidAuction.into())}
+      if nft.key != &nft_key{return Err(GameError::NftMintMismatch.into())}
+
+      let config_data: GameConfig = Self::load_config(config, treasury, program_id)?;
+      let bump: u8 = Self::load_vault_bump(pda, program_id)?;
+
+      if pda_ata.key != &get_associated_token_address(pda.key, &nft_key){return Err(GameError::InvalidEscrowAccount.into())}
+      if winner_ata.key != &get_associated_token_address(winner.key, &nft_key){return Err(GameError::InvalidTokenAccount.into())}
+
+      invoke(
+        &create_associated_token_account_idempotent(
+          payer.key,
+          winner.key,
+          nft.key,
+          token_program.key,
+        ),
+        &[
+          payer.clone(),
+          winner_ata.clone(),
+          winner.clone(),
+          nft.clone(),
+          system_program.clone(),
+          token_program.clone(),
+          ata_program.clone(),
+        ],
+      )?;
+
+      Self::withdraw_nft(token_program, pda, pda_ata, winner_ata, seller, bump)?;
+
+      let (seller_amount, fee) = Self::split_price(auction_data.highest_bid, &config_data)?;
+
+      //fee goes to treasury, the winning bid and the auction rent go to seller
+      **auction.lamports.borrow_mut() -= fee;
+      **treasury.lamports.borrow_mut() += fee;
+      Self::close_program_account(auction, seller)?;
+
+      //like a sale, the log is the only record of the split
+      msg!("auction: price {} seller {} fee {}", auction_data.highest_bid, seller_amount, fee);
+
+      let player_acc_data: Player = Player::unpack(&player.data.borrow())?;
+
+      let status: PlayerStatus = player_acc_data.status.transition(PlayerAction::EndAuction)?;
+
+      let new_owner: Player = Player{
+        nft_adress:player_acc_data.nft_adress,
+        owner:winner.key.to_bytes(),
+        status,
+        sale_required_price:0,
+        rent_required_price:0,
+        who_rents:player_acc_data.who_rents,
+        rented_for_time:0,
+        rent_end_on:0,
+        offence:player_acc_data.offence,
+        defence:player_acc_data.defence,
+        payment_mint:[0;32],
+        max_renewals:0,
+        listing_page:0,
+      };
+
+      new_owner.pack_into(&mut player.data.borrow_mut())?;
+
+    Ok(())
+  }
+  fn cancel_auction(
+    accounts: &[AccountInfo],
+    program_id:&Pubkey) -> ProgramResult {
+
+      let accounts_iter: &mut std::slice::Iter<'_, AccountInfo<'_>> = &mut accounts.iter();
+
+      let seller: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let auction: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let player: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let pda: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let pda_ata: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let seller_ata: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let token_program: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+
+      if !seller.is_signer{return Err(GameError::MissingSignature.into())}
+
+      let auction_data: Auction = Self::load_auction(auction, program_id)?;
+
+      let seller_key: Pubkey = Pubkey::new_from_array(auction_data.seller);
+      let player_key: Pubkey = Pubkey::new_from_array(auction_data.player);
+      let nft_key: Pubkey = Pubkey::new_from_array(auction_data.nft_adress);
+
+      if seller.key != &seller_key{return Err(GameError::NotPlayerOwner.into())}
+      if player.key != &player_key{return Err(GameError::InvalidAuction.into())}
+      if auction_data.highest_bid != 0{return Err(GameError::AuctionHasBids.into())}
+
+      let bump: u8 = Self::load_vault_bump(pda, program_id)?;
+
+      if pda_ata.key != &get_associated_token_address(pda.key, &nft_key){return Err(GameError::InvalidEscrowAccount.into())}
+
+      Self::withdraw_nft(token_program, pda, pda_ata, seller_ata, seller, bump)?;
+
+      Self::close_program_account(auction, seller)?;
+
+      let player_acc_data: Player = Player::unpack(&player.data.borrow())?;
+
+      let status: PlayerStatus = player_acc_data.status.transition(PlayerAction::EndAuction)?;
+
+      let cancelled: Player = Player{
+        nft_adress:player_acc_data.nft_adress,
+        owner:player_acc_data.owner,
+        status,
+        sale_required_price:0,
+        rent_required_price:0,
+        who_rents:player_acc_data.who_rents,
+        rented_for_time:0,
+        rent_end_on:0,
+        offence:player_acc_data.offence,
+        defence:player_acc_data.defence,
+        payment_mint:[0;32],
+        max_renewals:0,
+        listing_page:0,
+      };
+
+      cancelled.pack_into(&mut player.data.borrow_mut())?;
+
+    Ok(())
+  }
+  fn commit_randomness(
+    accounts: &[AccountInfo],
+    commit: CommitData,
+    program_id:&Pubkey) -> ProgramResult {
+
+      let accounts_iter: &mut std::slice::Iter<'_, AccountInfo<'_>> = &mut accounts.iter();
+
+      let user: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let commit_account: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let system_program: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+
+      if !user.is_signer{return Err(GameError::MissingSignature.into())}
+
+      let (commit_key, bump) = Pubkey::find_program_address(&[COMMIT_SEED, &user.key.to_bytes(), &commit.target], program_id);
+
+      if commit_account.key != &commit_key{return Err(GameError::InvalidCommit.into())}
+
+      let clock: Clock= Clock::get()?;
+
+      let commit_data: RandomnessCommit = RandomnessCommit{
+        owner:user.key.to_bytes(),
+        target:commit.target,
+        commitment:commit.commitment,
+        commit_slot:clock.slot,
+        bump,
+      };
+
+      Self::create_pda_account(user, commit_account, system_program, commit_data.packed_len()?,
+        &[COMMIT_SEED, &user.key.to_bytes(), &commit.target, &[bump]], program_id)?;
+
+      commit_data.pack_into(&mut commit_account.data.borrow_mut())?;
+
+      //held until the reveal closes the commit, so not revealing a lost roll costs the bond
+      invoke(
+        &system_instruction::transfer(user.key, commit_account.key, COMMIT_BOND),
+        &[
+          user.clone(),
+          commit_account.clone(),
+          system_program.clone(),
+        ],
+      )?;
+
+    Ok(())
+  }
+  fn cancel_commit(
+    accounts: &[AccountInfo],
+    program_id:&Pubkey) -> ProgramResult {
+
+      let accounts_iter: &mut std::slice::Iter<'_, AccountInfo<'_>> = &mut accounts.iter();
+
+      let user: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let commit: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let config: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let treasury: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+
+      if !user.is_signer{return Err(GameError::MissingSignature.into())}
+      if commit.owner != program_id{return Err(GameError::InvalidCommit.into())}
+
+      let commit_data: RandomnessCommit = RandomnessCommit::unpack(&commit.data.borrow())?;
+
+      let commit_key: Pubkey = Pubkey::create_program_address(
+        &[COMMIT_SEED, &commit_data.owner, &commit_data.target, &[commit_data.bump]], program_id)?;
+
+      if commit.key != &commit_key{return Err(GameError::InvalidCommit.into())}
+      if user.key.to_bytes() != commit_data.owner{return Err(GameError::InvalidCommit.into())}
+
+      Self::load_config(config, treasury, program_id)?;
+
+      //everything above the rent is the bond, the rent goes back to the user with the account
+      let rent: u64 = Rent::get()?.minimum_balance(commit.data_len());
+      let bond: u64 = commit.lamports().saturating_sub(rent);
+
+      **commit.lamports.borrow_mut() -= bond;
+      **treasury.lamports.borrow_mut() += bond;
+
+      Self::close_program_account(commit, user)?;
+
+    Ok(())
+  }
+  fn migrate_account(
+    accounts: &[AccountInfo],
+    account_type: AccountType,
+    program_id:&Pubkey) -> ProgramResult {
+
+      let accounts_iter: &mut std::slice::Iter<'_, AccountInfo<'_>> = &mut accounts.iter();
+
+      let payer: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let account: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let system_program: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+
+      if !payer.is_signer{return Err(GameError::MissingSignature.into())}
+      if account.owner != program_id{return Err(GameError::IncorrectProgramOwner.into())}
+
+      match account_type {
+        AccountType::UserAccount => {
+          let user_account_data: UserAccount = UserAccount::migrate(&account.data.borrow())?;
+          Self::rewrite_account(account, payer, system_program, &user_account_data)?;
+        }
+        AccountType::Player => {
+          let player_data: Player = Player::migrate(&account.data.borrow())?;
+          Self::rewrite_account(account, payer, system_program, &player_data)?;
+        }
+      }
+
+    Ok(())
+  }
+  fn rename_user(
+    accounts: &[AccountInfo],
+    username: UserName,
+    program_id:&Pubkey) -> ProgramResult {
+
+      let accounts_iter: &mut std::slice::Iter<'_, AccountInfo<'_>> = &mut accounts.iter();
+
+      let user: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let user_account: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let old_record: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let new_record: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let system_program: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+
+      if !user.is_signer{return Err(GameError::MissingSignature.into())}
+      if user_account.owner != program_id{return Err(GameError::IncorrectProgramOwner.into())}
+
+      let user_account_data: UserAccount = UserAccount::unpack(&user_account.data.borrow())?;
+
+      if user.key.to_bytes() != user_account_data.user_address{return Err(GameError::InvalidUserAccount.into())}
+
+      let normalized_name: String = username.normalized()?;
+
+      let old_name: String = user_account_data.user_name[..user_account_data.user_name_length as usize].to_ascii_lowercase();
+      let (old_record_key, _) = Pubkey::find_program_address(&[USERNAME_SEED, old_name.as_bytes()], program_id);
+
+      if old_record.key != &old_record_key{return Err(GameError::InvalidUsernameRecord.into())}
+
+      //a change of case only keeps the same record
+      if normalized_name != old_name {
+
+        Self::claim_username(new_record, user, system_program, &normalized_name, program_id)?;
+
+        //users registered before the registry existed may have no record, or one held by someone else
+        if old_record.owner == program_id {
+          let old_record_data: UsernameRecord = UsernameRecord::unpack(&old_record.data.borrow())?;
+          if old_record_data.owner == user.key.to_bytes() {
+            Self::close_program_account(old_record, user)?;
+          }
+        }
+      }
+
+      let mut renamed: UserAccount = user_account_data;
+      renamed.user_name = username.padded();
+      renamed.user_name_length = username.user_name.len() as u8;
+
+      renamed.pack_into(&mut user_account.data.borrow_mut())?;
+
+    Ok(())
+  }
+  //creates the UsernameRecord of a normalized name for user, fails if somebody already holds it
+  fn claim_username<'a>(
+    username_record: &AccountInfo<'a>,
+    user: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    normalized_name: &str,
+    program_id:&Pubkey) -> ProgramResult {
+
+      let (record_key, bump) = Pubkey::find_program_address(&[USERNAME_SEED, normalized_name.as_bytes()], program_id);
+
+      if username_record.key != &record_key{return Err(GameError::InvalidUsernameRecord.into())}
+
+      //lamports sent to the record address do not hold the name, only a record written by the program does
+      if username_record.owner == program_id && UsernameRecord::unpack(&username_record.data.borrow()).is_ok() {
+        return Err(GameError::UserNameTaken.into())
+      }
+
+      let record_data: UsernameRecord = UsernameRecord{
+        owner:user.key.to_bytes(),
+        bump,
+      };
+
+      Self::create_pda_account(user, username_record, system_program, record_data.packed_len()?,
+        &[USERNAME_SEED, normalized_name.as_bytes(), &[bump]], program_id)?;
+
+      record_data.pack_into(&mut username_record.data.borrow_mut())?;
+
+    Ok(())
+  }
+  fn close_user(
+    accounts: &[AccountInfo],
+    program_id:&Pubkey) -> ProgramResult {
+
+      let accounts_iter: &mut std::slice::Iter<'_, AccountInfo<'_>> = &mut accounts.iter();
+
+      let user: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let user_account: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let username_record: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let destination: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+
+      if !user.is_signer{return Err(GameError::MissingSignature.into())}
+      if user_account.owner != program_id{return Err(GameError::IncorrectProgramOwner.into())}
+      if destination.key == user_account.key || destination.key == username_record.key{return Err(GameError::InvalidDestination.into())}
+
+      let user_account_data: UserAccount = UserAccount::unpack(&user_account.data.borrow())?;
+
+      if user.key.to_bytes() != user_account_data.user_address{return Err(GameError::NotAccountOwner.into())}
+
+      let user_name: String = user_account_data.user_name[..user_account_data.user_name_length as usize].to_ascii_lowercase();
+      let (record_key, _) = Pubkey::find_program_address(&[USERNAME_SEED, user_name.as_bytes()], program_id);
+
+      if username_record.key != &record_key{return Err(GameError::InvalidUsernameRecord.into())}
+
+      //the name is released with the account, users registered before the registry may have no record
+      if username_record.owner == program_id {
+        let record_data: UsernameRecord = UsernameRecord::unpack(&username_record.data.borrow())?;
+        if record_data.owner == user.key.to_bytes() {
+          Self::close_program_account(username_record, destination)?;
+        }
+      }
+
+      //team players are passed after the fixed accounts and go back to Idle
+      Self::release_team(user, &user_account_data.team, &[], accounts_iter.as_slice(), program_id)?;
+
+      Self::close_program_account(user_account, destination)?;
+
+    Ok(())
+  }
+  fn close_player(
+    accounts: &[AccountInfo],
+    program_id:&Pubkey) -> ProgramResult {
+
+      let accounts_iter: &mut std::slice::Iter<'_, AccountInfo<'_>> = &mut accounts.iter();
+
+      let user: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let player: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let destination: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+
+      if !user.is_signer{return Err(GameError::MissingSignature.into())}
+      if player.owner != program_id{return Err(GameError::IncorrectProgramOwner.into())}
+      if destination.key == player.key{return Err(GameError::InvalidDestination.into())}
+
+      let player_acc_data: Player = Player::unpack(&player.data.borrow())?;
+
+      if user.key.to_bytes() != player_acc_data.owner{return Err(GameError::NotPlayerOwner.into())}
+
+      player_acc_data.status.transition(PlayerAction::Close)?; //listed, rented, auctioned and team players stay
+
+      Self::close_program_account(player, destination)?;
+
+    Ok(())
+  }
+  fn init_config(
+    accounts: &[AccountInfo],
+    config: ConfigData,
+    program_id:&Pubkey) -> ProgramResult {
+
+      let accounts_iter: &mut std::slice::Iter<'_, AccountInfo<'_>> = &mut accounts.iter();
+
+      let admin: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let config_account: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let system_program: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let program_data: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+
+      if !admin.is_signer{return Err(GameError::MissingSignature.into())}
+      if config.fee_basis_points > 10000{return Err(GameError::InvalidFee.into())}
+
+      //the config is created once, so only the deployer may pick the first admin
+      if Self::upgrade_authority(program_data, program_id)? != *admin.key{return Err(GameError::NotAdmin.into())}
+
+      let (config_key, bump) = Pubkey::find_program_address(&[CONFIG_SEED], program_id);
+
+      if config_account.key != &config_key{return Err(GameError::InvalidConfig.into())}
+
+      let config_data: GameConfig = GameConfig{
+        admin:admin.key.to_bytes(),
+        treasury:config.treasury,
+        fee_basis_points:config.fee_basis_points,
+        bump,
+      };
+
+      Self::create_pda_account(admin, config_account, system_program, config_data.packed_len()?, &[CONFIG_SEED, &[bump]], program_id)?;
+
+      config_data.pack_into(&mut config_account.data.borrow_mut())?;
+
+    Ok(())
+  }
+  fn update_config(
+    accounts: &[AccountInfo],
+    config: ConfigData,
+    program_id:&Pubkey) -> ProgramResult {
+
+      let accounts_iter: &mut std::slice::Iter<'_, AccountInfo<'_>> = &mut accounts.iter();
+
+      let admin: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let config_account: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+
+      if !admin.is_signer{return Err(GameError::MissingSignature.into())}
+      if config_account.owner != program_id{return Err(GameError::IncorrectProgramOwner.into())}
+      if config.fee_basis_points > 10000{return Err(GameError::InvalidFee.into())}
+
+      let mut config_data: GameConfig = GameConfig::unpack(&config_account.data.borrow())?;
+
+      let config_key: Pubkey = Pubkey::create_program_address(&[CONFIG_SEED, &[config_data.bump]], program_id)?;
+
+      if config_account.key != &config_key{return Err(GameError::InvalidConfig.into())}
+
+      let admin_key: Pubkey = Pubkey::new_from_array(config_data.admin);
+
+      if admin.key != &admin_key{return Err(GameError::NotAdmin.into())}
+
+      config_data.treasury = config.treasury;
+      config_data.fee_basis_points = config.fee_basis_points;
+
+      config_data.pack_into(&mut config_account.data.borrow_mut())?;
+
+    Ok(())
+  }
+  fn transfer_admin(
+    accounts: &[AccountInfo],
+    program_id:&Pubkey) -> ProgramResult {
+
+      let accounts_iter: &mut std::slice::Iter<'_, AccountInfo<'_>> = &mut accounts.iter();
+
+      let admin: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let config_account: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let new_admin: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+
+      if !admin.is_signer || !new_admin.is_signer{return Err(GameError::MissingSignature.into())}
+      if config_account.owner != program_id{return Err(GameError::IncorrectProgramOwner.into())}
+
+      let mut config_data: GameConfig = GameConfig::unpack(&config_account.data.borrow())?;
+
+      let config_key: Pubkey = Pubkey::create_program_address(&[CONFIG_SEED, &[config_data.bump]], program_id)?;
+
+      if config_account.key != &config_key{return Err(GameError::InvalidConfig.into())}
+
+      let admin_key: Pubkey = Pubkey::new_from_array(config_data.admin);
+
+      if admin.key != &admin_key{return Err(GameError::NotAdmin.into())}
+
+      config_data.admin = new_admin.key.to_bytes();
+
+      config_data.pack_into(&mut config_account.data.borrow_mut())?;
+
+    Ok(())
+  }
+  //reads the upgrade authority from the ProgramData account of the program: a u32 tag (3),
+  //the u64 deploy slot, then the authority as an Option<Pubkey>, none once made immutable
+  fn upgrade_authority(
+    program_data: &AccountInfo,
+    program_id:&Pubkey) -> Result<Pubkey, ProgramError> {
+
+      let (program_data_key, _) = Pubkey::find_program_address(&[program_id.as_ref()], &bpf_loader_upgradeable::id());
+
+      if program_data.key != &program_data_key{return Err(GameError::InvalidProgramData.into())}
+      if program_data.owner != &bpf_loader_upgradeable::id(){return Err(GameError::InvalidProgramData.into())}
+
+      let data = program_data.data.borrow();
+
+      if data.len() < UpgradeableLoaderState::size_of_programdata_metadata(){return Err(GameError::InvalidProgramData.into())}
+      if data[..4] != 3u32.to_le_bytes() || data[12] != 1{return Err(GameError::InvalidProgramData.into())}
+
+      let authority: [u8;32] = data[13..45].try_into().map_err(|_| GameError::InvalidProgramData)?;
+
+    Ok(Pubkey::new_from_array(authority))
+  }
+  fn load_config(
+    config: &AccountInfo,
+    treasury: &AccountInfo,
+    program_id:&Pubkey) -> Result<GameConfig, ProgramError> {
+
+      if config.owner != program_id{return Err(GameError::IncorrectProgramOwner.into())}
+
+      let config_data: GameConfig = GameConfig::unpack(&config.data.borrow())?;
+
+      let config_key: Pubkey = Pubkey::create_program_address(&[CONFIG_SEED, &[config_data.bump]], program_id)?;
+
+      if config.key != &config_key{return Err(GameError::InvalidConfig.into())}
+
+      let treasury_key: Pubkey = Pubkey::new_from_array(config_data.treasury);
+
+      if treasury.key != &treasury_key{return Err(GameError::TreasuryMismatch.into())}
+
+    Ok(config_data)
+  }
+  fn load_auction(
+    auction: &AccountInfo,
+    program_id:&Pubkey) -> Result<Auction, ProgramError> {
+
+      if auction.owner != program_id{return Err(GameError::IncorrectProgramOwner.into())}
+
+      let auction_data: Auction = Auction::unpack(&auction.data.borrow())?;
+
+      let auction_key: Pubkey = Pubkey::create_program_address(
+        &[AUCTION_SEED, &auction_data.player, &[auction_data.bump]], program_id)?;
+
+      if auction.key != &auction_key{return Err(GameError::InvalidAuction.into())}
+
+    Ok(auction_data)
+  }
+  fn renew_rent(
+    accounts: &[AccountInfo],
+    program_id:&Pubkey) -> ProgramResult {
+
+      let accounts_iter: &mut std::slice::Iter<'_, AccountInfo<'_>> = &mut accounts.iter();
+
+      let player: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let rent_escrow: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let owner: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let renter: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+
+      if player.owner != program_id{return Err(GameError::IncorrectProgramOwner.into())}
+
+      let player_acc_data: Player = Player::unpack(&player.data.borrow())?;
+
+      let status: PlayerStatus = player_acc_data.status.transition(PlayerAction::RenewRent)?;
+
+      let mut escrow_data: RentEscrow = Self::load_rent_escrow(rent_escrow, player, program_id)?
+        .ok_or(GameError::PlayerNotRented)?;
+
+      if owner.key.to_bytes() != escrow_data.owner{return Err(GameError::InvalidRentEscrow.into())}
+      if renter.key.to_bytes() != escrow_data.renter{return Err(GameError::InvalidRentEscrow.into())}
+
+      let clock: Clock= Clock::get()?;
+      let current_time: u64 = clock.unix_timestamp as u64;
+
+      if current_time < escrow_data.end_time{return Err(GameError::RentNotOver.into())}
+
+      //anyone may crank this, without a prepaid period left the rental ends as in ClaimRentOverPlayer
+      if escrow_data.renewals_left == 0 {
+        return Self::end_rental(player, &player_acc_data, rent_escrow, owner, renter, accounts_iter.as_slice(), current_time, program_id)
+      }
+
+      //pay what is left of the period that ended, then start the next one from the prepaid rent
+      let due: u64 = escrow_data.amount - escrow_data.released;
+      let payment_mint: Pubkey = Pubkey::new_from_array(escrow_data.payment_mint);
+
+      if payment_mint == Pubkey::default() {
+        Self::pay_from_escrow(rent_escrow, &escrow_data, owner, None, due)?;
+      }else{
+        let token_program: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+        let escrow_payment: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+        let owner_payment: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+
+        Self::pay_from_escrow(rent_escrow, &escrow_data, owner, Some((token_program, escrow_payment, owner_payment)), due)?;
+      }
+
+      escrow_data.released = 0;
+      escrow_data.start_time = escrow_data.end_time;
+      escrow_data.end_time += escrow_data.period;
+      escrow_data.renewals_left -= 1;
+      escrow_data.prepaid -= escrow_data.amount;
+
+      escrow_data.pack_into(&mut rent_escrow.data.borrow_mut())?;
+
+      let renewed: Player = Player{
+        nft_adress:player_acc_data.nft_adress,
+        owner:player_acc_data.owner,
+        status,
+        sale_required_price:player_acc_data.sale_required_price,
+        rent_required_price:player_acc_data.rent_required_price,
+        who_rents:player_acc_data.who_rents,
+        rented_for_time:player_acc_data.rented_for_time,
+        rent_end_on:escrow_data.end_time,
+        offence:player_acc_data.offence,
+        defence:player_acc_data.defence,
+        payment_mint:player_acc_data.payment_mint,
+        max_renewals:player_acc_data.max_renewals,
+        listing_page:player_acc_data.listing_page,
+      };
+
+      renewed.pack_into(&mut player.data.borrow_mut())?;
+
+      msg!("rent: renewed until {}, {} renewals left", escrow_data.end_time, escrow_data.renewals_left);
+
+    Ok(())
+  }
+  fn refresh_team(
+    accounts: &[AccountInfo],
+    program_id:&Pubkey) -> ProgramResult {
+
+      let accounts_iter: &mut std::slice::Iter<'_, AccountInfo<'_>> = &mut accounts.iter();
+
+      let user_account: &AccountInfo<'_> = next_account_info(accounts_iter)?;
+      let team: [&AccountInfo<'_>;TEAM_SIZE] = [
+        next_account_info(accounts_iter)?,
+        next_account_info(accounts_iter)?,
+        next_account_info(accounts_iter)?,
+        next_account_info(accounts_iter)?,
+        next_account_info(accounts_iter)?,
+      ];
+
+      if user_account.owner != program_id{return Err(GameError::IncorrectProgramOwner.into())}
+
+      let mut user_account_data: UserAccount = UserAccount::unpack(&user_account.data.borrow())?;
+
+      if user_account_data.team_status != TeamStatus::Ready{return Err(GameError::TeamNotReady.into())}
+
+      //anyone may crank this, the slots are kept so SetTeam and CloseUser can still release the players
+      match Self::verify_team(&user_account_data, &team, program_id)? {
+        Some(players) => {
+          user_account_data.team_power = compute_team_power(user_account_data.formation, &players);
+        }
+        None => {
+          user_account_data.team_status = TeamStatus::NoTeam;
+          user_account_data.team_power = 0;
+        }
+      }
+
+      user_account_data.pack_into(&mut user_account.data.borrow_mut())?;
+
+      msg!("team: {:?}, power {}", user_account_data.team_status, user_account_data.team_power);
+
+    Ok(())
+  }
+  //returns None for rentals made before rent was escrowed, they were paid up front
+  fn load_rent_escrow(
+    rent_escrow: &AccountInfo,
+    player: &AccountInfo,
+    program_id:&Pubkey) -> Result<Option<RentEscrow>, ProgramError> {
+
+      let (escrow_key, _) = Pubkey::find_program_address(&[RENT_SEED, &player.key.to_bytes()], program_id);
+
+      if rent_escrow.key != &escrow_key{return Err(GameError::InvalidRentEscrow.into())}
+
+      if rent_escrow.owner != program_id{return Ok(None)}
+
+    Ok(Some(RentEscrow::unpack(&rent_escrow.data.borrow())?))
+  }
+  //pays the owner the rent earned by current_time, refunds the rest to the renter,
+  //closes the escrow and hands the player back to the owner
+  #[allow(clippy::too_many_arguments)]
+  fn end_rental<'a>(
+    player: &AccountInfo<'a>,
+    player_acc_data: &Player,
+    rent_escrow: &AccountInfo<'a>,
+    owner: &AccountInfo<'a>,
+    renter: &AccountInfo<'a>,
+    token_accounts: &[AccountInfo<'a>],
+    current_time: u64,
+    program_id:&Pubkey) -> ProgramResult {
+
+      let status: PlayerStatus = player_acc_data.status.transition(PlayerAction::EndRent)?;
+
+      if let Some(escrow_data) = Self::load_rent_escrow(rent_escrow, player, program_id)? {
+
+        if owner.key.to_bytes() != escrow_data.owner{return Err(GameError::InvalidRentEscrow.into())}
+        if renter.key.to_bytes() != escrow_data.renter{return Err(GameError::InvalidRentEscrow.into())}
+
+        //prepaid periods that never started go back to the renter in full
+        let earned: u64 = escrow_data.earned_at(current_time);
+        let owner_due: u64 = earned - escrow_data.released;
+        let refund: u64 = escrow_data.amount - earned + escrow_data.prepaid;
+
+        let payment_mint: Pubkey = Pubkey::new_from_array(escrow_data.payment_mint);
+
+        if payment_mint == Pubkey::default() {
+          Self::pay_from_escrow(rent_escrow, &escrow_data, owner, None, owner_due)?;
+          Self::pay_from_escrow(rent_escrow, &escrow_data, renter, None, refund)?;
+        }else{
+          let token_iter: &mut std::slice::Iter<'_, AccountInfo<'a>> = &mut token_accounts.iter();
+
+          let token_program: &AccountInfo<'a> = next_account_info(token_iter)?;
+          let escrow_payment: &AccountInfo<'a> = next_account_info(token_iter)?;
+          let owner_payment: &AccountInfo<'a> = next_account_info(token_iter)?;
+          let renter_payment: &AccountInfo<'a> = next_account_info(token_iter)?;
+
+          Self::pay_from_escrow(rent_escrow, &escrow_data, owner, Some((token_program, escrow_payment, owner_payment)), owner_due)?;
+          Self::pay_from_escrow(rent_escrow, &escrow_data, renter, Some((token_program, escrow_payment, renter_payment)), refund)?;
+
+          //the renter paid for the escrow token account
+          let close_ix: solana_program::instruction::Instruction = close_account(token_program.key,
+            escrow_payment.key,
+            renter.key,
+            rent_escrow.key,
+            &[rent_escrow.key])?;
+
+          invoke_signed(
+            &close_ix,
+            &[
+              token_program.clone(),
+              escrow_payment.clone(),
+              renter.clone(),
+              rent_escrow.clone(),
+            ],
+            &[&[RENT_SEED, &escrow_data.player, &[escrow_data.bump]]],
+          )?;
+        }
+
+        msg!("rent: owner {} renter refund {}", owner_due, refund);
+
+        Self::close_program_account(rent_escrow, renter)?;
+      }
+
+      let rentover: Player = Player{
+        nft_adress:player_acc_data.nft_adress,
+        owner:player_acc_data.owner,
+        status,
+        sale_required_price:0,
+        rent_required_price:0,
+        who_rents:[0;32],
+        rented_for_time:0,
+        rent_end_on:0,
+        offence:player_acc_data.offence,
+        defence:player_acc_data.defence,
+        payment_mint:[0;32],
+        max_renewals:0,
+        listing_page:0,
+      };
+
+      rentover.pack_into(&mut player.data.borrow_mut())?;
+
+    Ok(())
+  }
+  //moves amount of escrowed rent to destination, out of the escrow lamports or,
+  //for token rents, from the escrow token account into destination_payment
+  fn pay_from_escrow<'a>(
+    rent_escrow: &AccountInfo<'a>,
+    escrow_data: &RentEscrow,
+    destination: &AccountInfo<'a>,
+    token_accounts: Option<(&AccountInfo<'a>, &AccountInfo<'a>, &AccountInfo<'a>)>,
+    amount: u64) -> ProgramResult {
+
+      match token_accounts {
+        None => {
+          **rent_escrow.lamports.borrow_mut() -= amount;
+          **destination.lamports.borrow_mut() += amount;
+        }
+        Some((token_program, escrow_payment, destination_payment)) => {
+          let payment_mint: Pubkey = Pubkey::new_from_array(escrow_data.payment_mint);
+
+          if token_program.key != &spl_token::id(){return Err(GameError::InvalidTokenAccount.into())}
+          if escrow_payment.key != &get_associated_token_address(rent_escrow.key, &payment_mint){return Err(GameError::InvalidEscrowAccount.into())}
+          if destination_payment.owner != &spl_token::id(){return Err(GameError::InvalidPaymentAccount.into())}
+
+          let destination_unpacked: Account = Account::unpack_from_slice(&destination_payment.data.borrow())?;
+
+          if destination_unpacked.mint != payment_mint{return Err(GameError::PaymentMintMismatch.into())}
+          if destination.key != &destination_unpacked.owner{return Err(GameError::InvalidPaymentAccount.into())}
+
+          if amount == 0 {return Ok(())}
+
+          let trans_ix: solana_program::instruction::Instruction = transfer( token_program.key,
+              escrow_payment.key,
+              destination_payment.key,
+              rent_escrow.key,
+              &[rent_escrow.key],
+              amount)?;
+
+          invoke_signed(
+            &trans_ix,
+            &[
+              token_program.clone(),
+              escrow_payment.clone(),
+              destination_payment.clone(),
+              rent_escrow.clone(),
+            ],
+            &[&[RENT_SEED, &escrow_data.player, &[escrow_data.bump]]],
+          )?;
+        }
+      }
+
+    Ok(())
+  }
+  //players of the team slots, in slot order
+  fn unpack_team(
+    team: &[&AccountInfo;TEAM_SIZE]) -> Result<[Player;TEAM_SIZE], ProgramError> {
+
+    Ok([
+      Player::unpack(&team[0].data.borrow())?,
+      Player::unpack(&team[1].data.borrow())?,
+      Player::unpack(&team[2].data.borrow())?,
+      Player::unpack(&team[3].data.borrow())?,
+      Player::unpack(&team[4].data.borrow())?,
+    ])
+  }
+  //returns the players fielded in the team slots, None once the user no longer controls one of them:
+  //an owned player must still be in the team, a rented one rented to the user and not expired
+  fn verify_team(
+    user_account_data: &UserAccount,
+    team: &[&AccountInfo;TEAM_SIZE],
+    program_id:&Pubkey) -> Result<Option<[Player;TEAM_SIZE]>, ProgramError> {
+
+      let current_time: u64 = Clock::get()?.unix_timestamp as u64;
+      let mut players: Vec<Player> = Vec::with_capacity(TEAM_SIZE);
+
+      for (player, nft) in team.iter().zip(user_account_data.team.iter()) {
+        let (player_key, _) = Pubkey::find_program_address(&[PLAYER_SEED, nft], program_id);
+
+        if player.key != &player_key{return Err(GameError::PlayerNotInTeam.into())}
+
+        //a closed player is no longer there to field
+        if player.owner != program_id{return Ok(None)}
+
+        let player_data: Player = Player::unpack(&player.data.borrow())?;
+
+        let controlled: bool = match player_data.status {
+          PlayerStatus::InTeam => player_data.owner == user_account_data.user_address,
+          PlayerStatus::Rented => player_data.who_rents == user_account_data.user_address && current_time < player_data.rent_end_on,
+          _ => false,
+        };
+
+        if !controlled{return Ok(None)}
+
+        players.push(player_data);
+      }
+
+      let players: [Player;TEAM_SIZE] = players.try_into().map_err(|_| GameError::PlayerNotInTeam)?;
+
+    Ok(Some(players))
+  }
+  //moves the players of team that are not in kept from InTeam back to Idle. players rented into
+  //the team, sold or closed since have nothing to release, but every one must be passed
+  fn release_team(
+    user: &AccountInfo,
+    team: &[[u8;32];TEAM_SIZE],
+    kept: &[[u8;32]],
+    players: &[AccountInfo],
+    program_id:&Pubkey) -> ProgramResult {
+
+      for nft in team.iter() {
+        if nft == &[0;32] || kept.contains(nft){continue}
+
+        let (player_key, _) = Pubkey::find_program_address(&[PLAYER_SEED, nft], program_id);
+
+        let player: &AccountInfo<'_> = players.iter().find(|player| player.key == &player_key).ok_or(GameError::TeamPlayerMissing)?;
+
+        if player.owner != program_id{continue}
+
+        let mut player_data: Player = Player::unpack(&player.data.borrow())?;
+
+        if player_data.status != PlayerStatus::InTeam || player_data.owner != user.key.to_bytes(){continue}
+
+        player_data.status = player_data.status.transition(PlayerAction::LeaveTeam)?;
+        player_data.pack_into(&mut player.data.borrow_mut())?;
+      }
+
+    Ok(())
+  }
+  //adds a listing to the given page of its price bucket, the first lister of a page pays for it
+  #[allow(clippy::too_many_arguments)]
+  fn insert_listing<'a>(
+    listing_book: &AccountInfo<'a>,
+    user: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    kind: ListingKind,
+    page: u16,
+    listing: Listing,
+    program_id:&Pubkey) -> ProgramResult {
+
+      if page == UNINDEXED_LISTING{return Err(GameError::InvalidListingBook.into())}
+
+      let bucket: u8 = ListingBook::bucket_of(listing.price);
+      let seeds: [&[u8]; 4] = [LISTING_SEED, &[kind as u8], &[bucket], &page.to_le_bytes()];
+      let (book_key, bump) = Pubkey::find_program_address(&seeds, program_id);
+
+      if listing_book.key != &book_key{return Err(GameError::InvalidListingBook.into())}
+
+      //lamports sent to a page that does not exist yet do not make it a page
+      let mut book_data: ListingBook = if listing_book.owner == program_id {
+        ListingBook::unpack(&listing_book.data.borrow())?
+      }else{
+        Self::create_pda_account(user, listing_book, system_program, ListingBook::SPACE,
+          &[LISTING_SEED, &[kind as u8], &[bucket], &page.to_le_bytes(), &[bump]], program_id)?;
+
+        ListingBook{kind, bucket, page, bump, entries:Vec::new()}
+      };
+
+      book_data.insert(listing)?;
+      book_data.pack_into(&mut listing_book.data.borrow_mut())?;
+
+    Ok(())
+  }
+  //drops the player from the listing page it was listed in. a listing made before ListingBook
+  //existed is in no page, it is dropped from the passed page if there and skipped otherwise
+  fn remove_listing(
+    listing_book: &AccountInfo,
+    kind: ListingKind,
+    player: &AccountInfo,
+    player_data: &Player,
+    program_id:&Pubkey) -> ProgramResult {
+
+      if player_data.listing_page == UNINDEXED_LISTING {
+        if listing_book.owner != program_id{return Ok(())}
+
+        let mut book_data: ListingBook = match ListingBook::unpack(&listing_book.data.borrow()) {
+          Ok(book_data) if book_data.kind == kind => book_data,
+          _ => return Ok(()),
+        };
+
+        if book_data.remove(&player.key.to_bytes()).is_ok() {
+          book_data.pack_into(&mut listing_book.data.borrow_mut())?;
+        }
+
+        return Ok(())
+      }
+
+      let price: u64 = match kind {
+        ListingKind::Sale => player_data.sale_required_price,
+        ListingKind::Rent => player_data.rent_required_price,
+      };
+      let seeds: [&[u8]; 4] = [LISTING_SEED, &[kind as u8], &[ListingBook::bucket_of(price)], &player_data.listing_page.to_le_bytes()];
+      let (book_key, _) = Pubkey::find_program_address(&seeds, program_id);
+
+      if listing_book.key != &book_key{return Err(GameError::InvalidListingBook.into())}
+      if listing_book.owner != program_id{return Err(GameError::InvalidListingBook.into())}
+
+      let mut book_data: ListingBook = ListingBook::unpack(&listing_book.data.borrow())?;
+
+      book_data.remove(&player.key.to_bytes())?;
+      book_data.pack_into(&mut listing_book.data.borrow_mut())?;
+
+    Ok(())
+  }
+  //moves every lamport of a program owned account to destination and wipes its data
+  fn close_program_account(
+    account: &AccountInfo,
+    destination: &AccountInfo) -> ProgramResult {
+
+      let lamports: u64 = **account.lamports.borrow();
+
+      **account.lamports.borrow_mut() = 0;
+      **destination.lamports.borrow_mut() += lamports;
+
+      account.data.borrow_mut().fill(0);
+
+    Ok(())
+  }
+  //win/loss/draw counters, streaks and elo (k = 32) for both sides of a challenge
+  fn update_stats(
+    user: &mut UserAccount,
+    opponent: &mut UserAccount,
+    user_score: i32,
+    opponent_score: i32) {
+
+      let score: f64 = if user_score > opponent_score {
+        user.wins += 1;
+        opponent.losses += 1;
+        user.streak = if user.streak > 0 {user.streak + 1} else {1};
+        opponent.streak = if opponent.streak < 0 {opponent.streak - 1} else {-1};
+        1.0
+      }else if user_score < opponent_score {
+        user.losses += 1;
+        opponent.wins += 1;
+        user.streak = if user.streak < 0 {user.streak - 1} else {-1};
+        opponent.streak = if opponent.streak > 0 {opponent.streak + 1} else {1};
+        0.0
+      }else{
+        user.draws += 1;
+        opponent.draws += 1;
+        user.streak = 0;
+        opponent.streak = 0;
+        0.5
+      };
+
+      let expected: f64 = 1.0 / (1.0 + 10f64.powf((opponent.rating as f64 - user.rating as f64) / 400.0));
+      let delta: i64 = (32.0 * (score - expected)).round() as i64;
+
+      user.rating = (user.rating as i64 + delta).max(0) as u64;
+      opponent.rating = (opponent.rating as i64 - delta).max(0) as u64;
+  }
+  //writes data in its newest layout, growing the account first if the layout got bigger
+  fn rewrite_account<'a, T: ProgramAccount>(
+    account: &AccountInfo<'a>,
+    payer: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    data: &T) -> ProgramResult {
+
+      Self::grow_account(account, payer, system_program, data.packed_len()?)?;
+
+      let mut account_data = account.data.borrow_mut();
+      account_data.fill(0);
+      data.pack_into(&mut account_data)?;
+
+    Ok(())
+  }
+  //creates the program owned pda of seeds with space bytes, payer funds the rent. anyone can send
+  //lamports to a pda before it exists, so then only the missing rent is paid and the account is
+  //allocated and assigned in place. an account that already has data or an owner still fails
+  fn create_pda_account<'a>(
+    payer: &AccountInfo<'a>,
+    account: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    space: usize,
+    seeds: &[&[u8]],
+    program_id:&Pubkey) -> ProgramResult {
+
+      let lamports: u64 = Rent::get()?.minimum_balance(space);
+
+      if account.lamports() == 0 {
+        return invoke_signed(
+          &system_instruction::create_account(payer.key, account.key, lamports, space as u64, program_id),
+          &[
+            payer.clone(),
+            account.clone(),
+            system_program.clone(),
+          ],
+          &[seeds],
+        )
+      }
+
+      let rent_needed: u64 = lamports.saturating_sub(account.lamports());
+
+      if rent_needed > 0 {
+        invoke(
+          &system_instruction::transfer(payer.key, account.key, rent_needed),
+          &[
+            payer.clone(),
+            account.clone(),
+            system_program.clone(),
+          ],
+        )?;
+      }
+
+      invoke_signed(
+        &system_instruction::allocate(account.key, space as u64),
+        &[
+          account.clone(),
+          system_program.clone(),
+        ],
+        &[seeds],
+      )?;
+
+      invoke_signed(
+        &system_instruction::assign(account.key, program_id),
+        &[
+          account.clone(),
+          system_program.clone(),
+        ],
+        &[seeds],
+      )?;
+
+    Ok(())
+  }
+  //reallocs a program owned account to new_len, payer tops up the rent
+  fn grow_account<'a>(
+    account: &AccountInfo<'a>,
+    payer: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    new_len: usize) -> ProgramResult {
+
+      if account.data_len() >= new_len {return Ok(())}
+
+      let rent_needed: u64 = Rent::get()?.minimum_balance(new_len).saturating_sub(account.lamports());
+
+      if rent_needed > 0 {
+        invoke(
+          &system_instruction::transfer(payer.key, account.key, rent_needed),
+          &[
+            payer.clone(),
+            account.clone(),
+            system_program.clone(),
+          ],
+        )?;
+      }
+
+      account.realloc(new_len, false)?;
+
+    Ok(())
+  }
+  //checks the secret, and the lineup of a challenge, against the user's commit for target and mixes
+  //it with the first slot hash after the commit, the caller closes the commit once its cpis are done
+  fn reveal_randomness(
+    user: &AccountInfo,
+    commit: &AccountInfo,
+    slot_hashes: &AccountInfo,
+    target: &Pubkey,
+    secret: &[u8;32],
+    lineup: Option<&Lineup>,
+    program_id:&Pubkey) -> Result<keccak::Hash, ProgramError> {
+
+      if commit.owner != program_id{return Err(GameError::InvalidCommit.into())}
+      if slot_hashes.key != &sysvar::slot_hashes::id(){return Err(GameError::InvalidCommit.into())}
+
+      let commit_data: RandomnessCommit = RandomnessCommit::unpack(&commit.data.borrow())?;
+
+      let commit_key: Pubkey = Pubkey::create_program_address(
+        &[COMMIT_SEED, &commit_data.owner, &commit_data.target, &[commit_data.bump]], program_id)?;
+
+      if commit.key != &commit_key{return Err(GameError::InvalidCommit.into())}
+      if user.key.to_bytes() != commit_data.owner{return Err(GameError::InvalidCommit.into())}
+      if target.to_bytes() != commit_data.target{return Err(GameError::InvalidCommit.into())}
+
+      let commitment: [u8;32] = match lineup {
+        Some(lineup) => randomness::challenge_commitment(secret, lineup),
+        None => randomness::commitment(secret),
+      };
+
+      if commitment != commit_data.commitment{return Err(GameError::CommitmentMismatch.into())}
+
+      let (_, slot_hash) = match randomness::slot_hash_after(&slot_hashes.data.borrow(), commit_data.commit_slot) {
+        Some(entry) => entry,
+        None => {
+          let clock: Clock= Clock::get()?;
+          if clock.slot > commit_data.commit_slot + MAX_COMMIT_AGE{return Err(GameError::CommitExpired.into())}
+          return Err(GameError::RevealTooEarly.into())
+        }
+      };
+
+    Ok(randomness::mix(secret, &slot_hash, user.key, target))
+  }
+  //checks the passed pda against the bump stored at CreatePDA time
+  fn load_vault_bump(
+    pda: &AccountInfo,
+    program_id:&Pubkey) -> Result<u8, ProgramError> {
+
+      if pda.owner != program_id{return Err(GameError::InvalidPda.into())}
+
+      let vault: Vault = Vault::unpack(&pda.data.borrow())?;
+
+      let pda_key: Pubkey = Pubkey::create_program_address(&[VAULT_SEED, &[vault.bump]], program_id)?;
+
+      if pda.key != &pda_key{return Err(GameError::InvalidPda.into())}
+
+    Ok(vault.bump)
+  }
+  //moves the nft from the user's ata into the pda escrow ata, creating it if needed
+  #[allow(clippy::too_many_arguments)]
+  fn deposit_nft<'a>(
+    user: &AccountInfo<'a>,
+    nft: &AccountInfo<'a>,
+    user_ata: &AccountInfo<'a>,
+    pda: &AccountInfo<'a>,
+    pda_ata: &AccountInfo<'a>,
+    token_program: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    ata_program: &AccountInfo<'a>) -> ProgramResult {
+
+      if token_program.key != &spl_token::id(){return Err(GameError::InvalidTokenAccount.into())}
+      if user_ata.owner != &spl_token::id(){return Err(GameError::InvalidTokenAccount.into())}
+      let user_ata_unpacked: Account = Account::unpack_from_slice(&user_ata.data.borrow())?;
+      if user.key != &user_ata_unpacked.owner{return Err(GameError::TokenAccountOwnerMismatch.into())}
+      if user_ata_unpacked.amount != 1{return Err(GameError::NftNotHeld.into())}
+      if nft.key != &user_ata_unpacked.mint{return Err(GameError::NftMintMismatch.into())}
+
+      if pda_ata.key != &get_associated_token_address(pda.key, nft.key){return Err(GameError::InvalidEscrowAccount.into())}
+
+      invoke(
+        &create_associated_token_account_idempotent(
+          user.key,
+          pda.key,
+          nft.key,
+          token_program.key,
+        ),
+        &[
+          user.clone(),
+          pda_ata.clone(),
+          pda.clone(),
+          nft.clone(),
+          system_program.clone(),
+          token_program.clone(),
+          ata_program.clone(),
+        ],
+      )?;
+
+      let trans_ix: solana_program::instruction::Instruction = transfer( token_program.key,
+          user_ata.key, 
+          pda_ata.key, 
+          user.key, 
+          &[user.key], 
+          1)?;
+
+      invoke(
+        &trans_ix,
+        &[
+          token_program.clone(),
+          user_ata.clone(),
+          pda_ata.clone(),
+          user.clone(),
+        ],
+      )?;
+
+    Ok(())
+  }
+  //moves the nft out of the pda escrow ata and closes it, rent goes to rent_receiver
+  fn withdraw_nft<'a>(
+    token_program: &AccountInfo<'a>,
+    pda: &AccountInfo<'a>,
+    pda_ata: &AccountInfo<'a>,
+    destination_ata: &AccountInfo<'a>,
+    rent_receiver: &AccountInfo<'a>,
+    bump: u8) -> ProgramResult {
+
+      if token_program.key != &spl_token::id(){return Err(GameError::InvalidTokenAccount.into())}
+
+      let trans_ix: solana_program::instruction::Instruction = transfer( token_program.key,
+          pda_ata.key, 
+          destination_ata.key, 
+          pda.key, 
+          &[pda.key], 
+          1)?;
+
+      invoke_signed(
+        &trans_ix,
+        &[
+          token_program.clone(),
+          pda_ata.clone(),
+          destination_ata.clone(),
+          pda.clone(),
+        ],
+        &[&[VAULT_SEED, &[bump]]],
+      )?;
+
+      let close_ix: solana_program::instruction::Instruction = close_account(token_program.key, 
+        pda_ata.key,
+         rent_receiver.key, 
+         pda.key,
+         &[pda.key])?;
+
+      invoke_signed(
+          &close_ix,
+          &[
+            token_program.clone(),
+            pda_ata.clone(),
+            rent_receiver.clone(),
+            pda.clone(),
+          ],
+          &[&[VAULT_SEED, &[bump]]],
+        )?;
+
+    Ok(())
+  }
+  //pays a listing in the game currency, seller and treasury token accounts must hold the listing mint
+  #[allow(clippy::too_many_arguments)]
+  fn pay_with_token<'a>(
+    token_program: &AccountInfo<'a>,
+    payer: &AccountInfo<'a>,
+    payer_token: &AccountInfo<'a>,
+    seller_token: &AccountInfo<'a>,
+    treasury_token: &AccountInfo<'a>,
+    payment_mint: &Pubkey,
+    seller: &Pubkey,
+    treasury: &Pubkey,
+    seller_amount: u64,
+    fee: u64) -> ProgramResult {
+
+      if token_program.key != &spl_token::id(){return Err(GameError::InvalidTokenAccount.into())}
+      if payer_token.owner != &spl_token::id(){return Err(GameError::InvalidPaymentAccount.into())}
+      if seller_token.owner != &spl_token::id(){return Err(GameError::InvalidPaymentAccount.into())}
+      if treasury_token.owner != &spl_token::id(){return Err(GameError::InvalidPaymentAccount.into())}
+
+      let payer_token_unpacked: Account = Account::unpack_from_slice(&payer_token.data.borrow())?;
+      let seller_token_unpacked: Account = Account::unpack_from_slice(&seller_token.data.borrow())?;
+      let treasury_token_unpacked: Account = Account::unpack_from_slice(&treasury_token.data.borrow())?;
+
+      if &payer_token_unpacked.mint != payment_mint{return Err(GameError::PaymentMintMismatch.into())}
+      if &seller_token_unpacked.mint != payment_mint{return Err(GameError::PaymentMintMismatch.into())}
+      if &treasury_token_unpacked.mint != payment_mint{return Err(GameError::PaymentMintMismatch.into())}
+
+      if payer.key != &payer_token_unpacked.owner{return Err(GameError::InvalidPaymentAccount.into())}
+      if seller != &seller_token_unpacked.owner{return Err(GameError::InvalidPaymentAccount.into())}
+      if treasury != &treasury_token_unpacked.owner{return Err(GameError::TreasuryMismatch.into())}
+
+      if payer_token_unpacked.amount < seller_amount + fee{return Err(GameError::InsufficientFunds.into())}
+
+      let seller_ix: solana_program::instruction::Instruction = transfer( token_program.key,
+          payer_token.key, 
+          seller_token.key, 
+          payer.key, 
+          &[payer.key], 
+          seller_amount)?;
+
+      invoke(
+        &seller_ix,
+        &[
+          token_program.clone(),
+          payer_token.clone(),
+          seller_token.clone(),
+          payer.clone(),
+        ],
+      )?;
+
+      if fee > 0 {
+        let fee_ix: solana_program::instruction::Instruction = transfer( token_program.key,
+            payer_token.key, 
+            treasury_token.key, 
+            payer.key, 
+            &[payer.key], 
+            fee)?;
+
+        invoke(
+          &fee_ix,
+          &[
+            token_program.clone(),
+            payer_token.clone(),
+            treasury_token.clone(),
+            payer.clone(),
+          ],
+        )?;
+      }
+
+    Ok(())
+  }
+  //returns (amount to seller, fee to treasury)
+  fn split_price(
+    price: u64,
+    config: &GameConfig) -> Result<(u64, u64), ProgramError> {
+
+      let fee: u64 = (price as u128 * config.fee_basis_points as u128 / 10000) as u64;
+      let seller_amount: u64 = price.checked_sub(fee).ok_or(GameError::InvalidFee)?;
+
+    Ok((seller_amount, fee))
+  }
+  //prices `periods` periods of a sale or rent listing as (price, seller amount, fee), every
+  //period is split alone so a rent escrow holds a whole number of periods, a sale is one period
+  fn quote(
+    player_data: &Player,
+    kind: ListingKind,
+    periods: u64,
+    max_price: Option<u64>,
+    config: &GameConfig) -> Result<(u64, u64, u64), ProgramError> {
+
+      let period_price: u64 = match kind {
+        ListingKind::Sale => player_data.sale_required_price,
+        ListingKind::Rent => player_data.rent_required_price,
+      };
+
+      let (period_amount, period_fee) = Self::split_price(period_price, config)?;
+
+      let price: u64 = period_price.checked_mul(periods).ok_or(GameError::InsufficientFunds)?;
+      let seller_amount: u64 = period_amount * periods;
+      let fee: u64 = period_fee * periods;
+
+      if max_price.is_some_and(|max_price| price > max_price){return Err(GameError::PriceAboveMaximum.into())}
+
+    Ok((price, seller_amount, fee))
+  }
+  //the payer's wallet pays the seller amount and the fee through the system program
+  fn pay_with_lamports<'a>(
+    system_program: &AccountInfo<'a>,
+    payer: &AccountInfo<'a>,
+    seller: &AccountInfo<'a>,
+    treasury: &AccountInfo<'a>,
+    seller_amount: u64,
+    fee: u64) -> ProgramResult {
+
+      if payer.lamports() < seller_amount + fee{return Err(GameError::InsufficientFunds.into())}
+
+      invoke(
+        &system_instruction::transfer(payer.key, seller.key, seller_amount),
+        &[
+          payer.clone(),
+          seller.clone(),
+          system_program.clone(),
+        ],
+      )?;
+
+      invoke(
+        &system_instruction::transfer(payer.key, treasury.key, fee),
+        &[
+          payer.clone(),
+          treasury.clone(),
+          system_program.clone(),
+        ],
+      )?;
+
+    Ok(())
+  }
 }
 
 
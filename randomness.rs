@@ -0,0 +1,56 @@
+use crate::simulation::Lineup;
+use solana_program::{keccak, pubkey::Pubkey};
+
+//(slot, hash) entry of the raw SlotHashes sysvar data
+const SLOT_HASH_ENTRY_LEN: usize = 8 + 32;
+
+//what CommitRandomness stores for a secret revealed later
+pub fn commitment(secret: &[u8; 32]) -> [u8; 32] {
+  keccak::hash(secret).to_bytes()
+}
+
+//a challenge also commits to the challenger's lineup, so the team can not be changed
+//once the slot hash is known
+pub fn challenge_commitment(secret: &[u8; 32], lineup: &Lineup) -> [u8; 32] {
+  keccak::hashv(&[secret, &lineup_hash(lineup)]).to_bytes()
+}
+
+//formation and the nft and stats of every slot
+pub fn lineup_hash(lineup: &Lineup) -> [u8; 32] {
+  let mut data: Vec<u8> = vec![lineup.formation as u8];
+  for player in lineup.players {
+    data.extend_from_slice(&player.nft_adress);
+    data.extend_from_slice(&player.offence.to_le_bytes());
+    data.extend_from_slice(&player.defence.to_le_bytes());
+  }
+  keccak::hash(&data).to_bytes()
+}
+
+//first slot hash after commit_slot, None while there is none yet or once it left the sysvar.
+//reads the raw bytes, newest first, since deserializing all 512 entries does not fit the compute budget
+pub fn slot_hash_after(slot_hashes_data: &[u8], commit_slot: u64) -> Option<(u64, [u8; 32])> {
+  let len_bytes: [u8; 8] = slot_hashes_data.get(..8)?.try_into().ok()?;
+  let len: usize = u64::from_le_bytes(len_bytes) as usize;
+
+  let mut found: Option<(u64, [u8; 32])> = None;
+
+  for i in 0..len {
+    let start: usize = 8 + i * SLOT_HASH_ENTRY_LEN;
+    let entry: &[u8] = slot_hashes_data.get(start..start + SLOT_HASH_ENTRY_LEN)?;
+    let slot: u64 = u64::from_le_bytes(entry[..8].try_into().ok()?);
+
+    if slot <= commit_slot {
+      //the oldest slot in the sysvar must be at or before the commit,
+      //otherwise the first slot after it may already have been dropped
+      return found;
+    }
+    found = Some((slot, entry[8..].try_into().ok()?));
+  }
+
+  None
+}
+
+//the slot hash did not exist at commit time, so nobody knows the result when committing
+pub fn mix(secret: &[u8; 32], slot_hash: &[u8; 32], user: &Pubkey, target: &Pubkey) -> keccak::Hash {
+  keccak::hashv(&[secret, slot_hash, &user.to_bytes(), &target.to_bytes()])
+}
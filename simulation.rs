@@ -0,0 +1,108 @@
+use crate::state::{Formation, Player, Role, TEAM_SIZE};
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::keccak;
+
+//possessions in a challenge, the teams alternate and the user attacks first
+pub const POSSESSIONS: u8 = 10;
+
+//random bytes of one possession: the attacker, the defender and the shot
+pub const POSSESSION_BYTES: usize = 3;
+
+//out of 256, the chance a shot scores against no defence
+const SHOT_CHANCE: u128 = 128;
+
+pub type Rolls = [u8; POSSESSION_BYTES];
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+  User,
+  Opponent,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Goal {
+  pub possession: u8, //counted from 0
+  pub side: Side,
+  pub scorer_slot: u8,
+  pub defender_slot: u8, //slot of the other side
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Lineup<'a> {
+  pub formation: Formation,
+  pub players: &'a [Player; TEAM_SIZE],
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MatchResult {
+  pub user_score: u8,
+  pub opponent_score: u8,
+  pub goals: Vec<Goal>,
+}
+
+//every possession rolls from keccak(seed, possession), so a recorded match replays
+//off-chain from its seed, possessions and the players' stats
+pub fn simulate(user: &Lineup, opponent: &Lineup, seed: &[u8; 32], possessions: u8) -> MatchResult {
+  simulate_rolls(user, opponent, (0..possessions).map(|possession| possession_rolls(seed, possession)))
+}
+
+pub fn possession_rolls(seed: &[u8; 32], possession: u8) -> Rolls {
+  let hash: [u8; 32] = keccak::hashv(&[seed.as_ref(), &[possession]]).to_bytes();
+  [hash[0], hash[1], hash[2]]
+}
+
+//a possession per entry of rolls, at most 256 since goals keep the possession as a u8.
+//an offence or middle slot of the attacking team duels a defence or middle slot of the other
+pub fn simulate_rolls(user: &Lineup, opponent: &Lineup, rolls: impl IntoIterator<Item = Rolls>) -> MatchResult {
+  let mut result: MatchResult = MatchResult { user_score: 0, opponent_score: 0, goals: Vec::new() };
+
+  for (possession, rolls) in rolls.into_iter().take(u8::MAX as usize + 1).enumerate() {
+    let (side, attack, defence) = if possession % 2 == 0 {
+      (Side::User, user, opponent)
+    } else {
+      (Side::Opponent, opponent, user)
+    };
+
+    let scorer_slot: usize = pick_slot(attack.formation, Role::Offence, rolls[0]);
+    let defender_slot: usize = pick_slot(defence.formation, Role::Defence, rolls[1]);
+
+    if !scores(attack.players[scorer_slot].offence, defence.players[defender_slot].defence, rolls[2]) {
+      continue;
+    }
+
+    match side {
+      Side::User => result.user_score += 1,
+      Side::Opponent => result.opponent_score += 1,
+    }
+    result.goals.push(Goal {
+      possession: possession as u8,
+      side,
+      scorer_slot: scorer_slot as u8,
+      defender_slot: defender_slot as u8,
+    });
+  }
+
+  result
+}
+
+//the middle both attacks and defends, every formation has an offence and a defence slot
+fn pick_slot(formation: Formation, role: Role, roll: u8) -> usize {
+  let roles: [Role; TEAM_SIZE] = formation.roles();
+  let takes_part = |slot_role: &Role| *slot_role == role || *slot_role == Role::Middle;
+  let count: usize = roles.iter().filter(|slot_role| takes_part(slot_role)).count();
+
+  roles
+    .iter()
+    .enumerate()
+    .filter(|(_, slot_role)| takes_part(slot_role))
+    .nth(roll as usize % count)
+    .map(|(slot, _)| slot)
+    .unwrap_or(0)
+}
+
+//scores with a chance of offence / (offence + defence), scaled by SHOT_CHANCE
+fn scores(offence: u64, defence: u64, roll: u8) -> bool {
+  let total: u128 = offence as u128 + defence as u128;
+
+  roll as u128 * total < offence as u128 * SHOT_CHANCE
+}
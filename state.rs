@@ -1,41 +1,685 @@
 use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::program_error::ProgramError;
 
+use crate::error::GameError;
+use crate::simulation::Goal;
 
+pub const INITIAL_RATING: u64 = 1200;
+
+pub const DISCRIMINATOR_LEN: usize = 8;
+pub const ACCOUNT_HEADER_LEN: usize = DISCRIMINATOR_LEN + 1; //discriminator + layout version
+
+//every program owned account starts with an 8 byte discriminator and a layout version,
+//so an account of one type can not be passed where another is expected
+pub trait ProgramAccount: BorshSerialize + BorshDeserialize {
+    const DISCRIMINATOR: [u8; DISCRIMINATOR_LEN];
+    const VERSION: u8;
+
+    //rejects accounts of another type or written with another layout
+    fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        if data.len() < ACCOUNT_HEADER_LEN || data[..DISCRIMINATOR_LEN] != Self::DISCRIMINATOR {
+            return Err(GameError::InvalidAccountType.into())
+        }
+        if data[DISCRIMINATOR_LEN] != Self::VERSION {return Err(GameError::UnsupportedAccountVersion.into())}
+        let mut body: &[u8] = &data[ACCOUNT_HEADER_LEN..];
+        Ok(Self::deserialize(&mut body)?)
+    }
+
+    fn pack_into(&self, data: &mut [u8]) -> Result<(), ProgramError> {
+        if data.len() < self.packed_len()? {return Err(ProgramError::AccountDataTooSmall)}
+        data[..DISCRIMINATOR_LEN].copy_from_slice(&Self::DISCRIMINATOR);
+        data[DISCRIMINATOR_LEN] = Self::VERSION;
+        self.serialize(&mut &mut data[ACCOUNT_HEADER_LEN..])?;
+        Ok(())
+    }
+
+    //space to allocate for the account, header included
+    fn packed_len(&self) -> Result<usize, ProgramError> {
+        Ok(ACCOUNT_HEADER_LEN + self.try_to_vec()?.len())
+    }
+
+    //layout version in the header, None for accounts written before headers
+    fn stored_version(data: &[u8]) -> Option<u8> {
+        if data.len() < ACCOUNT_HEADER_LEN || data[..DISCRIMINATOR_LEN] != Self::DISCRIMINATOR {
+            return None
+        }
+        Some(data[DISCRIMINATOR_LEN])
+    }
+}
+
+//version registry used by MigrateAccount. to add a field: copy the current struct
+//into a frozen VN layout below, bump VERSION, add From<VN> and a match arm in migrate
+pub trait Migrate: ProgramAccount {
+    //reads every layout the account was ever written with into the newest one
+    fn migrate(data: &[u8]) -> Result<Self, ProgramError>;
+}
+
+//account types MigrateAccount can upgrade
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq)]
+pub enum AccountType{
+
+    UserAccount,
+    Player,
+
+}
+
+pub const USER_SEED: &[u8] = b"user";
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TeamStatus{
+
+    NoTeam,
+    Ready, //five players set by SetTeam
+
+}
+
+pub const TEAM_SIZE: usize = 5;
+
+//what a team slot weighs in the team power
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role{
+
+    Defence,
+    Middle,
+    Offence,
+
+}
+
+impl Role {
+    //(offence weight, defence weight)
+    pub fn weights(self) -> (u64, u64) {
+        match self {
+            Role::Defence => (1, 2),
+            Role::Middle => (3, 3),
+            Role::Offence => (2, 1),
+        }
+    }
+
+    pub fn power(self, player: &Player) -> u64 {
+        let (offence_weight, defence_weight) = self.weights();
+        player.offence * offence_weight + player.defence * defence_weight
+    }
+}
+
+//how the team slots are split into defence, middle and offence, named defence first
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Formation{
+
+    TwoOneTwo,
+    OneTwoTwo,
+    ThreeOneOne,
+
+}
+
+impl Formation {
+    //role of every team slot, defence slots first
+    pub fn roles(self) -> [Role; TEAM_SIZE] {
+        use Role::*;
+        match self {
+            Formation::TwoOneTwo => [Defence, Defence, Middle, Offence, Offence],
+            Formation::OneTwoTwo => [Defence, Middle, Middle, Offence, Offence],
+            Formation::ThreeOneOne => [Defence, Defence, Defence, Middle, Offence],
+        }
+    }
+}
+
+//team power of players fielded in the slots of formation, SetTeam and Upgrade both use it
+pub fn compute_team_power(formation: Formation, players: &[Player; TEAM_SIZE]) -> u64 {
+    formation.roles().iter().zip(players).map(|(role, player)| role.power(player)).sum()
+}
+
+//pda seeded by the user's wallet, created by RegisterUser
 #[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
 pub struct UserAccount{
 
     pub user_address:[u8;32], //48
     pub user_name:String, //14
     pub user_name_length:u8, //1
-    pub team_is_ready:u8, //1
+    pub team_status:TeamStatus, //1
     pub team_power:u64, //8
-    pub team_class:String, //4
+    pub formation:Formation, //1
+    pub team:[[u8;32];TEAM_SIZE], //nft of the player in every slot of formation
+    pub experienced:u64,
+    pub wins:u64,
+    pub losses:u64,
+    pub draws:u64,
+    pub streak:i64, //positive for a win streak, negative for a losing streak
+    pub rating:u64,
+
+}
+
+//version 4 replaced the five named team slots with formation and team,
+//version 3 replaced team_is_ready and the team_class string with team_status,
+//version 0 had no stats and version 1 had no header
+impl ProgramAccount for UserAccount {
+    const DISCRIMINATOR: [u8; DISCRIMINATOR_LEN] = *b"USERACCT";
+    const VERSION: u8 = 4;
+}
+
+impl Migrate for UserAccount {
+    fn migrate(data: &[u8]) -> Result<UserAccount, ProgramError> {
+        match UserAccount::stored_version(data) {
+            Some(version) if version == UserAccount::VERSION => UserAccount::unpack(data),
+            Some(3) => {
+                let mut body: &[u8] = &data[ACCOUNT_HEADER_LEN..];
+                let v3: UserAccountV3 = UserAccountV3::deserialize(&mut body)?;
+                Ok(v3.into())
+            }
+            Some(2) => {
+                let mut body: &[u8] = &data[ACCOUNT_HEADER_LEN..];
+                let v2: UserAccountV2 = UserAccountV2::deserialize(&mut body)?;
+                Ok(UserAccountV3::from(v2).into())
+            }
+            Some(_) => Err(GameError::UnsupportedAccountVersion.into()),
+            None => {
+                //headerless accounts were sized exactly for their layout, so only one parses
+                if let Ok(v1) = UserAccountV1::try_from_slice(data) {
+                    return Ok(UserAccountV3::from(UserAccountV2::from(v1)).into())
+                }
+                let v0: UserAccountV0 = UserAccountV0::try_from_slice(data).map_err(|_| GameError::InvalidAccountType)?;
+                Ok(UserAccountV3::from(UserAccountV2::from(UserAccountV1::from(v0))).into())
+            }
+        }
+    }
+}
+
+//version 3 layout, the 2-1-2 team in named slots
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct UserAccountV3{
+
+    pub user_address:[u8;32],
+    pub user_name:String,
+    pub user_name_length:u8,
+    pub team_status:TeamStatus,
+    pub team_power:u64,
+    pub defence_1:[u8;32],
+    pub defence_2:[u8;32],
+    pub middle:[u8;32],
+    pub offence_1:[u8;32],
+    pub offence_2:[u8;32],
+    pub experienced:u64,
+    pub wins:u64,
+    pub losses:u64,
+    pub draws:u64,
+    pub streak:i64,
+    pub rating:u64,
+
+}
+
+impl From<UserAccountV3> for UserAccount {
+    fn from(v3: UserAccountV3) -> UserAccount {
+        UserAccount{
+            user_address:v3.user_address,
+            user_name:v3.user_name,
+            user_name_length:v3.user_name_length,
+            team_status:v3.team_status,
+            team_power:v3.team_power,
+            formation:Formation::TwoOneTwo,
+            team:[v3.defence_1, v3.defence_2, v3.middle, v3.offence_1, v3.offence_2],
+            experienced:v3.experienced,
+            wins:v3.wins,
+            losses:v3.losses,
+            draws:v3.draws,
+            streak:v3.streak,
+            rating:v3.rating,
+        }
+    }
+}
+
+//version 2 layout, team state kept in team_is_ready and team_class
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct UserAccountV2{
+
+    pub user_address:[u8;32],
+    pub user_name:String,
+    pub user_name_length:u8,
+    pub team_is_ready:u8,
+    pub team_power:u64,
+    pub team_class:String,
+    pub defence_1:[u8;32],
+    pub defence_2:[u8;32],
+    pub middle:[u8;32],
+    pub offence_1:[u8;32],
+    pub offence_2:[u8;32],
+    pub experienced:u64,
+    pub wins:u64,
+    pub losses:u64,
+    pub draws:u64,
+    pub streak:i64,
+    pub rating:u64,
+
+}
+
+//players of a version 2 team were never marked InTeam, so they could be sold or rented out
+//while fielded. the team is dropped and has to be set again
+impl From<UserAccountV2> for UserAccountV3 {
+    fn from(v2: UserAccountV2) -> UserAccountV3 {
+        UserAccountV3{
+            user_address:v2.user_address,
+            user_name:v2.user_name,
+            user_name_length:v2.user_name_length,
+            team_status:TeamStatus::NoTeam,
+            team_power:0,
+            defence_1:[0;32],
+            defence_2:[0;32],
+            middle:[0;32],
+            offence_1:[0;32],
+            offence_2:[0;32],
+            experienced:v2.experienced,
+            wins:v2.wins,
+            losses:v2.losses,
+            draws:v2.draws,
+            streak:v2.streak,
+            rating:v2.rating,
+        }
+    }
+}
+
+//version 0 layout, no header
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct UserAccountV0{
+
+    pub user_address:[u8;32],
+    pub user_name:String,
+    pub user_name_length:u8,
+    pub team_is_ready:u8,
+    pub team_power:u64,
+    pub team_class:String,
+    pub defence_1:[u8;32],
+    pub defence_2:[u8;32],
+    pub middle:[u8;32],
+    pub offence_1:[u8;32],
+    pub offence_2:[u8;32],
+    pub experienced:u64,
+
+}
+
+//version 1 layout, no header, version was stored in the body
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct UserAccountV1{
+
+    pub user_address:[u8;32],
+    pub user_name:String,
+    pub user_name_length:u8,
+    pub team_is_ready:u8,
+    pub team_power:u64,
+    pub team_class:String,
     pub defence_1:[u8;32],
     pub defence_2:[u8;32],
     pub middle:[u8;32],
     pub offence_1:[u8;32],
     pub offence_2:[u8;32],
     pub experienced:u64,
+    pub version:u8,
+    pub wins:u64,
+    pub losses:u64,
+    pub draws:u64,
+    pub streak:i64,
+    pub rating:u64,
 
 }
 
+impl From<UserAccountV0> for UserAccountV1 {
+    fn from(v0: UserAccountV0) -> UserAccountV1 {
+        UserAccountV1{
+            user_address:v0.user_address,
+            user_name:v0.user_name,
+            user_name_length:v0.user_name_length,
+            team_is_ready:v0.team_is_ready,
+            team_power:v0.team_power,
+            team_class:v0.team_class,
+            defence_1:v0.defence_1,
+            defence_2:v0.defence_2,
+            middle:v0.middle,
+            offence_1:v0.offence_1,
+            offence_2:v0.offence_2,
+            experienced:v0.experienced,
+            version:1,
+            wins:0,
+            losses:0,
+            draws:0,
+            streak:0,
+            rating:INITIAL_RATING,
+        }
+    }
+}
+
+impl From<UserAccountV1> for UserAccountV2 {
+    fn from(v1: UserAccountV1) -> UserAccountV2 {
+        UserAccountV2{
+            user_address:v1.user_address,
+            user_name:v1.user_name,
+            user_name_length:v1.user_name_length,
+            team_is_ready:v1.team_is_ready,
+            team_power:v1.team_power,
+            team_class:v1.team_class,
+            defence_1:v1.defence_1,
+            defence_2:v1.defence_2,
+            middle:v1.middle,
+            offence_1:v1.offence_1,
+            offence_2:v1.offence_2,
+            experienced:v1.experienced,
+            wins:v1.wins,
+            losses:v1.losses,
+            draws:v1.draws,
+            streak:v1.streak,
+            rating:v1.rating,
+        }
+    }
+}
+
+pub const PLAYER_SEED: &[u8] = b"player";
+
+//where a player is in its lifecycle, only PlayerStatus::transition moves it
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlayerStatus{
+
+    Idle,
+    ListedForSale, //nft escrowed by the vault, in a sale ListingBook
+    ListedForRent, //in a rent ListingBook
+    Rented,        //who_rents controls it until rent_end_on
+    InAuction,     //nft escrowed by the vault
+    InTeam,        //in the owner's team
+
+}
+
+//what a handler does to a player
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlayerAction{
+
+    ListForSale,
+    AbortSale,
+    Buy,
+    ListForRent,
+    AbortRent,
+    Rent,
+    RenewRent,
+    EndRent,
+    StartAuction,
+    EndAuction,
+    JoinTeam,
+    LeaveTeam,
+    ClaimNft,
+    Close,
+
+}
+
+impl PlayerStatus {
+    //the status after action, or why the player can not take it
+    pub fn transition(self, action: PlayerAction) -> Result<PlayerStatus, GameError> {
+        use PlayerAction::*;
+        use PlayerStatus::*;
+        match (self, action) {
+            (Idle, ListForSale) => Ok(ListedForSale),
+            (ListedForSale, AbortSale) | (ListedForSale, Buy) => Ok(Idle),
+            (Idle, ListForRent) => Ok(ListedForRent),
+            (ListedForRent, AbortRent) => Ok(Idle),
+            (ListedForRent, Rent) => Ok(Rented),
+            (Rented, RenewRent) => Ok(Rented),
+            (Rented, EndRent) => Ok(Idle),
+            (Idle, StartAuction) => Ok(InAuction),
+            (InAuction, EndAuction) => Ok(Idle),
+            (Idle, JoinTeam) | (InTeam, JoinTeam) => Ok(InTeam),
+            (Rented, JoinTeam) => Ok(Rented), //the renter's team, the rental decides how long
+            (InTeam, LeaveTeam) => Ok(Idle),
+            (Idle, ClaimNft) | (InTeam, ClaimNft) => Ok(Idle), //the nft left the wallet outside the program
+            (Idle, Close) => Ok(Idle),
+            (status, action) => Err(status.rejection(action)),
+        }
+    }
+
+    fn rejection(self, action: PlayerAction) -> GameError {
+        match (self, action) {
+            (_, PlayerAction::AbortSale) | (_, PlayerAction::Buy) => GameError::PlayerNotForSale,
+            (PlayerStatus::Rented, PlayerAction::AbortRent) => GameError::PlayerRented,
+            (_, PlayerAction::AbortRent) | (_, PlayerAction::Rent) => GameError::PlayerNotForRent,
+            (_, PlayerAction::RenewRent) | (_, PlayerAction::EndRent) => GameError::PlayerNotRented,
+            (_, PlayerAction::EndAuction) => GameError::InvalidAuction,
+            (_, PlayerAction::LeaveTeam) => GameError::PlayerNotInTeam,
+            (PlayerStatus::ListedForSale, _) | (PlayerStatus::ListedForRent, _) => GameError::PlayerListed,
+            (PlayerStatus::Rented, _) => GameError::PlayerRented,
+            (PlayerStatus::InAuction, _) => GameError::PlayerInAuction,
+            (PlayerStatus::InTeam, _) => GameError::PlayerInTeam,
+            (PlayerStatus::Idle, _) => GameError::IllegalStatusTransition,
+        }
+    }
+}
+
+//pda seeded by the nft mint, created by GeneratePlayer
 #[derive(BorshSerialize, BorshDeserialize, Debug)]
 pub struct Player{
     
     pub nft_adress:[u8;32],          //44
     pub owner:[u8;32],               //44
-    pub for_sale:String,            //2
+    pub status:PlayerStatus,         //1
     pub sale_required_price:u64,    //8
-    pub for_rent:String,            //2
     pub rent_required_price:u64,    //8
     pub who_rents:[u8;32],           //44
-    pub rented:u8,                  //1
     pub rented_for_time:u64,        //8
     pub rent_end_on:u64,            //8
     pub offence:u64,                //8
     pub defence:u64,                //8
+    pub payment_mint:[u8;32],        //all zero when the price is in lamports
+    pub max_renewals:u16,            //periods of rented_for_time a renter may prepay after the first
+    pub listing_page:u16,            //ListingBook page of a sale or rent listing, UNINDEXED_LISTING if in none
+}
+
+//version 4 added listing_page, version 3 replaced the for_sale/for_rent strings
+//and rented with status, version 2 added max_renewals, version 1 was first
+//written without a header, before that version 0 had no payment mint
+impl ProgramAccount for Player {
+    const DISCRIMINATOR: [u8; DISCRIMINATOR_LEN] = *b"PLAYER__";
+    const VERSION: u8 = 4;
+}
+
+impl Migrate for Player {
+    fn migrate(data: &[u8]) -> Result<Player, ProgramError> {
+        match Player::stored_version(data) {
+            Some(version) if version == Player::VERSION => Player::unpack(data),
+            Some(3) => {
+                let mut body: &[u8] = &data[ACCOUNT_HEADER_LEN..];
+                let v3: PlayerV3 = PlayerV3::deserialize(&mut body)?;
+                Ok(v3.into())
+            }
+            Some(2) => {
+                let mut body: &[u8] = &data[ACCOUNT_HEADER_LEN..];
+                let v2: PlayerV2 = PlayerV2::deserialize(&mut body)?;
+                Ok(PlayerV3::from(v2).into())
+            }
+            Some(1) => {
+                let mut body: &[u8] = &data[ACCOUNT_HEADER_LEN..];
+                let v1: PlayerV1 = PlayerV1::deserialize(&mut body)?;
+                Ok(PlayerV3::from(PlayerV2::from(v1)).into())
+            }
+            Some(_) => Err(GameError::UnsupportedAccountVersion.into()),
+            None => {
+                if let Ok(v1) = PlayerV1::try_from_slice(data) {
+                    return Ok(PlayerV3::from(PlayerV2::from(v1)).into())
+                }
+                let v0: PlayerV0 = PlayerV0::try_from_slice(data).map_err(|_| GameError::InvalidAccountType)?;
+                Ok(PlayerV3::from(PlayerV2::from(PlayerV1::from(v0))).into())
+            }
+        }
+    }
+}
+
+//version 3 layout, listings were not tied to a ListingBook page
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct PlayerV3{
+
+    pub nft_adress:[u8;32],
+    pub owner:[u8;32],
+    pub status:PlayerStatus,
+    pub sale_required_price:u64,
+    pub rent_required_price:u64,
+    pub who_rents:[u8;32],
+    pub rented_for_time:u64,
+    pub rent_end_on:u64,
+    pub offence:u64,
+    pub defence:u64,
+    pub payment_mint:[u8;32],
+    pub max_renewals:u16,
+
+}
+
+impl From<PlayerV3> for Player {
+    fn from(v3: PlayerV3) -> Player {
+        //a listing of this layout may predate ListingBook, so its page is unknown
+        Player{
+            nft_adress:v3.nft_adress,
+            owner:v3.owner,
+            status:v3.status,
+            sale_required_price:v3.sale_required_price,
+            rent_required_price:v3.rent_required_price,
+            who_rents:v3.who_rents,
+            rented_for_time:v3.rented_for_time,
+            rent_end_on:v3.rent_end_on,
+            offence:v3.offence,
+            defence:v3.defence,
+            payment_mint:v3.payment_mint,
+            max_renewals:v3.max_renewals,
+            listing_page:UNINDEXED_LISTING,
+        }
+    }
+}
+
+//version 2 layout, status kept in strings and rented
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct PlayerV2{
+
+    pub nft_adress:[u8;32],
+    pub owner:[u8;32],
+    pub for_sale:String,
+    pub sale_required_price:u64,
+    pub for_rent:String,
+    pub rent_required_price:u64,
+    pub who_rents:[u8;32],
+    pub rented:u8,
+    pub rented_for_time:u64,
+    pub rent_end_on:u64,
+    pub offence:u64,
+    pub defence:u64,
+    pub payment_mint:[u8;32],
+    pub max_renewals:u16,
+
+}
+
+impl From<PlayerV2> for PlayerV3 {
+    fn from(v2: PlayerV2) -> PlayerV3 {
+        //version 2 kept no team flag on the player: set_team took any player whose for_sale and
+        //for_rent were not "aa" and never checked defence_2. such a player is mapped from its
+        //flags like any other and the UserAccountV2 migration drops the team it was in
+        let status: PlayerStatus = if v2.rented != 0 {
+            PlayerStatus::Rented
+        }else if v2.for_sale == "XX" {
+            PlayerStatus::ListedForSale
+        }else if v2.for_sale == "AU" {
+            PlayerStatus::InAuction
+        }else if v2.for_rent == "XX" {
+            PlayerStatus::ListedForRent
+        }else{
+            PlayerStatus::Idle
+        };
+        PlayerV3{
+            nft_adress:v2.nft_adress,
+            owner:v2.owner,
+            status,
+            sale_required_price:v2.sale_required_price,
+            rent_required_price:v2.rent_required_price,
+            who_rents:v2.who_rents,
+            rented_for_time:v2.rented_for_time,
+            rent_end_on:v2.rent_end_on,
+            offence:v2.offence,
+            defence:v2.defence,
+            payment_mint:v2.payment_mint,
+            max_renewals:v2.max_renewals,
+        }
+    }
+}
+
+//version 1 layout, written with and without a header
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct PlayerV1{
+
+    pub nft_adress:[u8;32],
+    pub owner:[u8;32],
+    pub for_sale:String,
+    pub sale_required_price:u64,
+    pub for_rent:String,
+    pub rent_required_price:u64,
+    pub who_rents:[u8;32],
+    pub rented:u8,
+    pub rented_for_time:u64,
+    pub rent_end_on:u64,
+    pub offence:u64,
+    pub defence:u64,
+    pub payment_mint:[u8;32],
+
+}
+
+impl From<PlayerV1> for PlayerV2 {
+    fn from(v1: PlayerV1) -> PlayerV2 {
+        PlayerV2{
+            nft_adress:v1.nft_adress,
+            owner:v1.owner,
+            for_sale:v1.for_sale,
+            sale_required_price:v1.sale_required_price,
+            for_rent:v1.for_rent,
+            rent_required_price:v1.rent_required_price,
+            who_rents:v1.who_rents,
+            rented:v1.rented,
+            rented_for_time:v1.rented_for_time,
+            rent_end_on:v1.rent_end_on,
+            offence:v1.offence,
+            defence:v1.defence,
+            payment_mint:v1.payment_mint,
+            max_renewals:0,
+        }
+    }
+}
+
+//version 0 layout, no header
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct PlayerV0{
+
+    pub nft_adress:[u8;32],
+    pub owner:[u8;32],
+    pub for_sale:String,
+    pub sale_required_price:u64,
+    pub for_rent:String,
+    pub rent_required_price:u64,
+    pub who_rents:[u8;32],
+    pub rented:u8,
+    pub rented_for_time:u64,
+    pub rent_end_on:u64,
+    pub offence:u64,
+    pub defence:u64,
+
+}
+
+impl From<PlayerV0> for PlayerV1 {
+    fn from(v0: PlayerV0) -> PlayerV1 {
+        PlayerV1{
+            nft_adress:v0.nft_adress,
+            owner:v0.owner,
+            for_sale:v0.for_sale,
+            sale_required_price:v0.sale_required_price,
+            for_rent:v0.for_rent,
+            rent_required_price:v0.rent_required_price,
+            who_rents:v0.who_rents,
+            rented:v0.rented,
+            rented_for_time:v0.rented_for_time,
+            rent_end_on:v0.rent_end_on,
+            offence:v0.offence,
+            defence:v0.defence,
+            payment_mint:[0;32],
+        }
+    }
 }
 
+pub const MIN_USER_NAME_LEN: usize = 3;
+pub const MAX_USER_NAME_LEN: usize = 10;
+
 #[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
 pub struct UserName{
 
@@ -43,11 +687,64 @@ pub struct UserName{
 
 }
 
+impl UserName {
+    //lowercase form used as the UsernameRecord seed, names differing only in case collide
+    pub fn normalized(&self) -> Result<String, GameError> {
+        if self.user_name.len() > MAX_USER_NAME_LEN {return Err(GameError::UserNameTooLong)}
+        if self.user_name.len() < MIN_USER_NAME_LEN {return Err(GameError::UserNameTooShort)}
+        if !self.user_name.bytes().all(|c| c.is_ascii_alphanumeric() || c == b'_') {
+            return Err(GameError::InvalidUserNameCharacter)
+        }
+        Ok(self.user_name.to_ascii_lowercase())
+    }
+
+    //names are stored padded with X to MAX_USER_NAME_LEN, user_name_length keeps the real length
+    pub fn padded(&self) -> String {
+        let mut user_name_holder: String = String::from("XXXXXXXXXX");
+        user_name_holder.replace_range(..self.user_name.len(), &self.user_name);
+        user_name_holder
+    }
+}
+
+pub const USERNAME_SEED: &[u8] = b"username";
+
+//pda seeded by the normalized user name, owned by whoever registered or renamed to it
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct UsernameRecord{
+
+    pub owner:[u8;32],
+    pub bump:u8,
+
+}
+
+impl ProgramAccount for UsernameRecord {
+    const DISCRIMINATOR: [u8; DISCRIMINATOR_LEN] = *b"USERNAME";
+    const VERSION: u8 = 1;
+}
+
 #[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
 pub struct RentOffer{
 
-    pub rent_required_price:u64,
-    pub rented_for_time:u64,
+    pub rent_required_price:u64, //per period
+    pub rented_for_time:u64,     //length of one period
+    pub payment_mint:[u8;32],
+    pub max_renewals:u16,
+    pub listing_page:u16,        //ListingBook page of the price bucket to list in
+
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct BuyRequest{
+
+    pub max_price:Option<u64>, //the buyer rejects the purchase if the listing costs more
+
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct RentRequest{
+
+    pub periods:u16, //periods paid up front, the first one plus up to max_renewals renewals
+    pub max_price:Option<u64>, //the renter rejects the rental if all periods cost more
 
 }
 
@@ -55,13 +752,277 @@ pub struct RentOffer{
 pub struct SellOffer{
 
     pub sell_required_price:u64,
+    pub payment_mint:[u8;32],
+    pub listing_page:u16,        //ListingBook page of the price bucket to list in
 
 }
 
+//breaking change: this byte was player_no, where 1 and 2 were offence_1 and offence_2,
+//3 the middle and 4 and 5 defence_1 and defence_2. with formations it is the index into
+//UserAccount::team, defence first, and Upgrade now passes the players of all five slots
+//so the team power can be recomputed
 #[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
 pub struct UpgradePlayer{
 
-    pub player_no:u8,
+    pub slot:u8, //index of the player in UserAccount::team, 0 to TEAM_SIZE - 1
     pub exp:u64,
 
 }
+
+pub const CONFIG_SEED: &[u8] = b"config";
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct GameConfig{
+
+    pub admin:[u8;32],
+    pub treasury:[u8;32],
+    pub fee_basis_points:u16,
+    pub bump:u8,
+
+}
+
+impl ProgramAccount for GameConfig {
+    const DISCRIMINATOR: [u8; DISCRIMINATOR_LEN] = *b"CONFIG__";
+    const VERSION: u8 = 1;
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct ConfigData{
+
+    pub treasury:[u8;32],
+    pub fee_basis_points:u16,
+
+}
+
+pub const VAULT_SEED: &[u8] = b"battleballs";
+
+//stored in the "battleballs" pda, which owns every escrowed nft
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct Vault{
+
+    pub bump:u8,
+
+}
+
+impl ProgramAccount for Vault {
+    const DISCRIMINATOR: [u8; DISCRIMINATOR_LEN] = *b"VAULT___";
+    const VERSION: u8 = 1;
+}
+
+pub const AUCTION_SEED: &[u8] = b"auction";
+
+//pda seeded by the player account, also holds the highest bid in lamports
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct Auction{
+
+    pub seller:[u8;32],
+    pub player:[u8;32],
+    pub nft_adress:[u8;32],
+    pub reserve_price:u64,
+    pub end_time:u64,
+    pub highest_bid:u64,
+    pub highest_bidder:[u8;32],
+    pub bump:u8,
+
+}
+
+impl ProgramAccount for Auction {
+    const DISCRIMINATOR: [u8; DISCRIMINATOR_LEN] = *b"AUCTION_";
+    const VERSION: u8 = 1;
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct AuctionOffer{
+
+    pub reserve_price:u64,
+    pub duration:u64,
+
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct Bid{
+
+    pub amount:u64,
+
+}
+
+pub const RENT_SEED: &[u8] = b"rent";
+
+//pda seeded by the player account, holds the rent of an active rental until it is earned by the owner
+//lamport rents sit in this account, token rents in its associated token account for payment_mint.
+//only the current period is released linearly, prepaid periods wait for RenewRent
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct RentEscrow{
+
+    pub owner:[u8;32],
+    pub renter:[u8;32],
+    pub player:[u8;32],
+    pub payment_mint:[u8;32],  //all zero when the rent is in lamports
+    pub amount:u64,            //rent of the current period after the marketplace fee
+    pub released:u64,          //part of amount already paid to the owner
+    pub start_time:u64,        //start of the current period
+    pub end_time:u64,          //end of the current period, mirrored in Player.rent_end_on
+    pub bump:u8,
+    pub period:u64,
+    pub renewals_left:u16,     //prepaid periods RenewRent can still start
+    pub prepaid:u64,           //rent held for those periods, amount each
+
+}
+
+impl ProgramAccount for RentEscrow {
+    const DISCRIMINATOR: [u8; DISCRIMINATOR_LEN] = *b"RENTESCR";
+    const VERSION: u8 = 1;
+}
+
+impl RentEscrow {
+    //part of amount the owner has earned at current_time, growing linearly from start_time to end_time
+    pub fn earned_at(&self, current_time: u64) -> u64 {
+        if current_time >= self.end_time || self.end_time <= self.start_time {return self.amount}
+        if current_time <= self.start_time {return 0}
+        let elapsed: u128 = (current_time - self.start_time) as u128;
+        let duration: u128 = (self.end_time - self.start_time) as u128;
+        (self.amount as u128 * elapsed / duration) as u64
+    }
+}
+
+pub const LISTING_SEED: &[u8] = b"listing";
+pub const LISTING_PAGE_CAPACITY: usize = 16;
+pub const LISTING_LEN: usize = 32 + 8 + 8 + 8 + 32;
+//listing_page of a player listed before ListingBook existed, its listing is in no page
+pub const UNINDEXED_LISTING: u16 = u16::MAX;
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq)]
+pub enum ListingKind{
+
+    Sale,
+    Rent,
+
+}
+
+//a listed player as clients see it in a ListingBook page
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct Listing{
+
+    pub player:[u8;32],
+    pub price:u64,             //sale price, or rent per period
+    pub offence:u64,
+    pub defence:u64,
+    pub payment_mint:[u8;32],  //all zero when priced in lamports
+
+}
+
+//pda seeded by the kind, the price bucket and the page number. bucket b holds prices in
+//[2^(b-1), 2^b), so paging buckets in order and pages in order walks listings by price.
+//entries of a page are kept sorted by price, cheapest first
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct ListingBook{
+
+    pub kind:ListingKind,
+    pub bucket:u8,
+    pub page:u16,
+    pub bump:u8,
+    pub entries:Vec<Listing>,
+
+}
+
+impl ProgramAccount for ListingBook {
+    const DISCRIMINATOR: [u8; DISCRIMINATOR_LEN] = *b"LISTBOOK";
+    const VERSION: u8 = 1;
+}
+
+impl ListingBook {
+    //pages are allocated full size once so inserts never realloc
+    pub const SPACE: usize = ACCOUNT_HEADER_LEN + 1 + 1 + 2 + 1 + 4 + LISTING_PAGE_CAPACITY * LISTING_LEN;
+
+    pub fn bucket_of(price: u64) -> u8 {
+        (64 - price.leading_zeros()) as u8
+    }
+
+    //equal prices keep the order they were listed in
+    pub fn insert(&mut self, listing: Listing) -> Result<(), GameError> {
+        if self.entries.len() >= LISTING_PAGE_CAPACITY {return Err(GameError::ListingPageFull)}
+        let position: usize = self.entries.partition_point(|entry| entry.price <= listing.price);
+        self.entries.insert(position, listing);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, player: &[u8;32]) -> Result<Listing, GameError> {
+        let position: usize = self.entries.iter().position(|entry| &entry.player == player).ok_or(GameError::ListingNotFound)?;
+        Ok(self.entries.remove(position))
+    }
+}
+
+pub const MATCH_SEED: &[u8] = b"match";
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct ChallengeData{
+
+    pub match_id:u64, //picked by the challenger, unique per challenger
+    pub secret:[u8;32], //revealed secret of the commit made for the opponent
+
+}
+
+//pda seeded by the challenger and match_id, written once by Challenge
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct MatchRecord{
+
+    pub match_id:u64,
+    pub user:[u8;32],
+    pub opponent:[u8;32],
+    pub user_team_power:u64,
+    pub opponent_team_power:u64,
+    pub seed:[u8;32], //randomness the match was simulated with
+    pub possessions:u8, //possessions simulate played from the seed
+    pub user_score:u8,
+    pub opponent_score:u8,
+    pub goals:Vec<Goal>,
+    pub winner:[u8;32], //all zero on a draw
+    pub slot:u64,
+    pub played_on:u64,
+
+}
+
+//version 3 stretches the seed per possession and stores how many were played,
+//version 2 stores the seed and goal timeline of simulate in place of the
+//raw rolls, records are written once so older ones are left as they are
+impl ProgramAccount for MatchRecord {
+    const DISCRIMINATOR: [u8; DISCRIMINATOR_LEN] = *b"MATCHREC";
+    const VERSION: u8 = 3;
+}
+
+pub const COMMIT_SEED: &[u8] = b"commit";
+pub const MAX_COMMIT_AGE: u64 = 512; //slots kept in the SlotHashes sysvar
+pub const COMMIT_BOND: u64 = 10_000_000; //lamports a commit holds on top of its rent, refunded on reveal
+
+//pda seeded by the user and the target (opponent user account or nft mint), the reveal
+//refunds the bond and CancelCommit forfeits it, so a result is not free to withhold
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct RandomnessCommit{
+
+    pub owner:[u8;32],
+    pub target:[u8;32],
+    pub commitment:[u8;32], //keccak of the secret, for a challenge also of the challenger's lineup
+    pub commit_slot:u64,
+    pub bump:u8,
+
+}
+
+impl ProgramAccount for RandomnessCommit {
+    const DISCRIMINATOR: [u8; DISCRIMINATOR_LEN] = *b"COMMIT__";
+    const VERSION: u8 = 1;
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct CommitData{
+
+    pub commitment:[u8;32],
+    pub target:[u8;32],
+
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct Reveal{
+
+    pub secret:[u8;32],
+
+}
@@ -0,0 +1,288 @@
+mod common;
+
+use common::*;
+use sol_battle_balls::{
+  error::GameError,
+  instruction,
+  state::{Auction, Player, PlayerStatus, ProgramAccount},
+};
+use solana_program::pubkey::Pubkey;
+use solana_program_test::{tokio, BanksClientError, ProgramTestContext};
+use solana_sdk::signature::{Keypair, Signer};
+
+const RESERVE: u64 = 1_000_000_000;
+const DURATION: u64 = 3600;
+
+/// Puts a freshly generated player up for auction. Returns (seller, player, nft).
+async fn auctioned_player(context: &mut ProgramTestContext) -> (Keypair, Pubkey, Pubkey) {
+  let seller: Keypair = create_user(context).await;
+  let (player, nft, ata) = generate(context, &seller).await;
+  process(
+    context,
+    &[instruction::create_auction(&program_id(), &seller.pubkey(), &player, &nft, &ata, RESERVE, DURATION)],
+    &[&seller],
+  )
+  .await
+  .unwrap();
+  (seller, player, nft)
+}
+
+async fn bid(
+  context: &mut ProgramTestContext,
+  bidder: &Keypair,
+  player: &Pubkey,
+  previous_bidder: &Pubkey,
+  amount: u64,
+) -> Result<(), BanksClientError> {
+  process(
+    context,
+    &[instruction::place_bid(&program_id(), &bidder.pubkey(), player, previous_bidder, amount)],
+    &[bidder],
+  )
+  .await
+}
+
+async fn get_auction(context: &mut ProgramTestContext, player: &Pubkey) -> Auction {
+  let auction_address: Pubkey = instruction::find_auction_address(&program_id(), player);
+  Auction::unpack(&get_account(context, &auction_address).await.data).unwrap()
+}
+
+async fn end_auction(context: &mut ProgramTestContext) {
+  let timestamp: i64 = now(context).await;
+  set_clock(context, timestamp + DURATION as i64).await;
+}
+
+#[tokio::test]
+async fn create_auction_escrows_nft() {
+  let mut context = start().await;
+  setup_marketplace(&mut context).await;
+
+  let (seller, player, nft) = auctioned_player(&mut context).await;
+
+  let auction: Auction = get_auction(&mut context, &player).await;
+  assert_eq!(auction.seller, seller.pubkey().to_bytes());
+  assert_eq!(auction.reserve_price, RESERVE);
+  assert_eq!(auction.end_time, now(&mut context).await as u64 + DURATION);
+  assert_eq!(auction.highest_bid, 0);
+  assert_eq!(get_player(&mut context, &player).await.status, PlayerStatus::InAuction);
+  assert_eq!(get_token_amount(&mut context, &escrow_address(&nft)).await, 1);
+}
+
+#[tokio::test]
+async fn create_auction_rejects_overflowing_duration() {
+  let mut context = start().await;
+  setup_marketplace(&mut context).await;
+  let seller: Keypair = create_user(&mut context).await;
+  let (player, nft, ata) = generate(&mut context, &seller).await;
+
+  let result = process(
+    &mut context,
+    &[instruction::create_auction(&program_id(), &seller.pubkey(), &player, &nft, &ata, RESERVE, u64::MAX)],
+    &[&seller],
+  )
+  .await;
+
+  assert_game_error(result, GameError::InvalidAuctionDuration);
+}
+
+#[tokio::test]
+async fn place_bid_refunds_outbid_bidder() {
+  let mut context = start().await;
+  setup_marketplace(&mut context).await;
+  let (_, player, _) = auctioned_player(&mut context).await;
+  let first: Keypair = create_user(&mut context).await;
+  let second: Keypair = create_user(&mut context).await;
+  let first_before: u64 = get_lamports(&mut context, &first.pubkey()).await;
+
+  bid(&mut context, &first, &player, &first.pubkey(), RESERVE).await.unwrap();
+  assert_eq!(first_before - get_lamports(&mut context, &first.pubkey()).await, RESERVE);
+
+  bid(&mut context, &second, &player, &first.pubkey(), RESERVE + 1).await.unwrap();
+
+  assert_eq!(get_lamports(&mut context, &first.pubkey()).await, first_before);
+  let auction: Auction = get_auction(&mut context, &player).await;
+  assert_eq!(auction.highest_bid, RESERVE + 1);
+  assert_eq!(auction.highest_bidder, second.pubkey().to_bytes());
+}
+
+#[tokio::test]
+async fn place_bid_rejects_bid_below_reserve() {
+  let mut context = start().await;
+  setup_marketplace(&mut context).await;
+  let (_, player, _) = auctioned_player(&mut context).await;
+  let bidder: Keypair = create_user(&mut context).await;
+
+  let result = bid(&mut context, &bidder, &player, &bidder.pubkey(), RESERVE - 1).await;
+
+  assert_game_error(result, GameError::BidTooLow);
+}
+
+#[tokio::test]
+async fn place_bid_rejects_bid_not_above_highest() {
+  let mut context = start().await;
+  setup_marketplace(&mut context).await;
+  let (_, player, _) = auctioned_player(&mut context).await;
+  let first: Keypair = create_user(&mut context).await;
+  let second: Keypair = create_user(&mut context).await;
+  bid(&mut context, &first, &player, &first.pubkey(), RESERVE).await.unwrap();
+
+  let result = bid(&mut context, &second, &player, &first.pubkey(), RESERVE).await;
+
+  assert_game_error(result, GameError::BidTooLow);
+}
+
+#[tokio::test]
+async fn place_bid_rejects_wrong_previous_bidder() {
+  let mut context = start().await;
+  setup_marketplace(&mut context).await;
+  let (_, player, _) = auctioned_player(&mut context).await;
+  let first: Keypair = create_user(&mut context).await;
+  let second: Keypair = create_user(&mut context).await;
+  bid(&mut context, &first, &player, &first.pubkey(), RESERVE).await.unwrap();
+
+  let result = bid(&mut context, &second, &player, &second.pubkey(), RESERVE + 1).await;
+
+  assert_game_error(result, GameError::BidderMismatch);
+}
+
+#[tokio::test]
+async fn place_bid_rejects_ended_auction() {
+  let mut context = start().await;
+  setup_marketplace(&mut context).await;
+  let (_, player, _) = auctioned_player(&mut context).await;
+  let bidder: Keypair = create_user(&mut context).await;
+  end_auction(&mut context).await;
+
+  let result = bid(&mut context, &bidder, &player, &bidder.pubkey(), RESERVE).await;
+
+  assert_game_error(result, GameError::AuctionEnded);
+}
+
+#[tokio::test]
+async fn settle_auction_pays_seller_and_moves_player() {
+  let mut context = start().await;
+  let treasury: Pubkey = setup_marketplace(&mut context).await;
+  let (seller, player, nft) = auctioned_player(&mut context).await;
+  let winner: Keypair = create_user(&mut context).await;
+  bid(&mut context, &winner, &player, &winner.pubkey(), RESERVE).await.unwrap();
+  end_auction(&mut context).await;
+  let auction_address: Pubkey = instruction::find_auction_address(&program_id(), &player);
+  let auction_lamports: u64 = get_lamports(&mut context, &auction_address).await;
+  let seller_before: u64 = get_lamports(&mut context, &seller.pubkey()).await;
+  let treasury_before: u64 = get_lamports(&mut context, &treasury).await;
+  let payer: Pubkey = context.payer.pubkey();
+
+  process(
+    &mut context,
+    &[instruction::settle_auction(&program_id(), &payer, &player, &nft, &seller.pubkey(), &winner.pubkey(), &treasury)],
+    &[],
+  )
+  .await
+  .unwrap();
+
+  let fee: u64 = RESERVE * FEE_BASIS_POINTS as u64 / 10_000;
+  assert_eq!(get_lamports(&mut context, &treasury).await - treasury_before, fee);
+  // the seller also gets back the rent of the auction and escrow accounts
+  assert!(get_lamports(&mut context, &seller.pubkey()).await - seller_before > auction_lamports - fee);
+  assert!(!account_exists(&mut context, &auction_address).await);
+  assert!(!account_exists(&mut context, &escrow_address(&nft)).await);
+
+  let data: Player = get_player(&mut context, &player).await;
+  assert_eq!(data.owner, winner.pubkey().to_bytes());
+  assert_eq!(data.status, PlayerStatus::Idle);
+  let winner_ata: Pubkey = spl_associated_token_account::get_associated_token_address(&winner.pubkey(), &nft);
+  assert_eq!(get_token_amount(&mut context, &winner_ata).await, 1);
+}
+
+#[tokio::test]
+async fn settle_auction_rejects_running_auction() {
+  let mut context = start().await;
+  let treasury: Pubkey = setup_marketplace(&mut context).await;
+  let (seller, player, nft) = auctioned_player(&mut context).await;
+  let winner: Keypair = create_user(&mut context).await;
+  bid(&mut context, &winner, &player, &winner.pubkey(), RESERVE).await.unwrap();
+  let payer: Pubkey = context.payer.pubkey();
+
+  let result = process(
+    &mut context,
+    &[instruction::settle_auction(&program_id(), &payer, &player, &nft, &seller.pubkey(), &winner.pubkey(), &treasury)],
+    &[],
+  )
+  .await;
+
+  assert_game_error(result, GameError::AuctionNotEnded);
+}
+
+#[tokio::test]
+async fn settle_auction_rejects_auction_without_bids() {
+  let mut context = start().await;
+  let treasury: Pubkey = setup_marketplace(&mut context).await;
+  let (seller, player, nft) = auctioned_player(&mut context).await;
+  end_auction(&mut context).await;
+  let payer: Pubkey = context.payer.pubkey();
+
+  let result = process(
+    &mut context,
+    &[instruction::settle_auction(&program_id(), &payer, &player, &nft, &seller.pubkey(), &payer, &treasury)],
+    &[],
+  )
+  .await;
+
+  assert_game_error(result, GameError::AuctionHasNoBids);
+}
+
+#[tokio::test]
+async fn cancel_auction_returns_nft() {
+  let mut context = start().await;
+  setup_marketplace(&mut context).await;
+  let (seller, player, nft) = auctioned_player(&mut context).await;
+  let ata: Pubkey = spl_associated_token_account::get_associated_token_address(&seller.pubkey(), &nft);
+
+  process(
+    &mut context,
+    &[instruction::cancel_auction(&program_id(), &seller.pubkey(), &player, &nft, &ata)],
+    &[&seller],
+  )
+  .await
+  .unwrap();
+
+  assert_eq!(get_token_amount(&mut context, &ata).await, 1);
+  assert_eq!(get_player(&mut context, &player).await.status, PlayerStatus::Idle);
+  let auction_address: Pubkey = instruction::find_auction_address(&program_id(), &player);
+  assert!(!account_exists(&mut context, &auction_address).await);
+}
+
+#[tokio::test]
+async fn cancel_auction_rejects_auction_with_bids() {
+  let mut context = start().await;
+  setup_marketplace(&mut context).await;
+  let (seller, player, nft) = auctioned_player(&mut context).await;
+  let bidder: Keypair = create_user(&mut context).await;
+  bid(&mut context, &bidder, &player, &bidder.pubkey(), RESERVE).await.unwrap();
+  let ata: Pubkey = spl_associated_token_account::get_associated_token_address(&seller.pubkey(), &nft);
+
+  let result = process(
+    &mut context,
+    &[instruction::cancel_auction(&program_id(), &seller.pubkey(), &player, &nft, &ata)],
+    &[&seller],
+  )
+  .await;
+
+  assert_game_error(result, GameError::AuctionHasBids);
+}
+
+#[tokio::test]
+async fn rent_your_player_rejects_player_in_auction() {
+  let mut context = start().await;
+  setup_marketplace(&mut context).await;
+  let (seller, player, _) = auctioned_player(&mut context).await;
+
+  let result = process(
+    &mut context,
+    &[instruction::rent_your_player(&program_id(), &seller.pubkey(), &player, RESERVE, DURATION, 0, None, 0)],
+    &[&seller],
+  )
+  .await;
+
+  assert_game_error(result, GameError::PlayerInAuction);
+}
@@ -0,0 +1,228 @@
+mod common;
+
+use common::*;
+use sol_battle_balls::{
+  error::GameError,
+  instruction,
+  state::{Player, PlayerStatus, UserAccount},
+};
+use solana_program::pubkey::Pubkey;
+use solana_program_test::{tokio, BanksClientError, ProgramTestContext};
+use solana_sdk::signature::{Keypair, Signer};
+
+async fn close_user(
+  context: &mut ProgramTestContext,
+  user: &Keypair,
+  name: &str,
+  destination: &Pubkey,
+) -> Result<(), BanksClientError> {
+  process(
+    context,
+    &[instruction::close_user(&program_id(), &user.pubkey(), name, destination, &[])],
+    &[user],
+  )
+  .await
+}
+
+async fn close_player(
+  context: &mut ProgramTestContext,
+  user: &Keypair,
+  nft: &Pubkey,
+  destination: &Pubkey,
+) -> Result<(), BanksClientError> {
+  process(
+    context,
+    &[instruction::close_player(&program_id(), &user.pubkey(), nft, destination)],
+    &[user],
+  )
+  .await
+}
+
+#[tokio::test]
+async fn close_user_refunds_account_and_name_record() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+  let destination: Pubkey = Pubkey::new_unique();
+  let user_account: Pubkey = register(&mut context, &user, "keeper").await;
+  let record: Pubkey = instruction::find_username_address(&program_id(), "keeper");
+  let locked: u64 = get_lamports(&mut context, &user_account).await + get_lamports(&mut context, &record).await;
+
+  close_user(&mut context, &user, "keeper", &destination).await.unwrap();
+
+  assert!(!account_exists(&mut context, &user_account).await);
+  assert!(!account_exists(&mut context, &record).await);
+  assert_eq!(get_lamports(&mut context, &destination).await, locked);
+}
+
+#[tokio::test]
+async fn close_user_without_name_record_refunds_account() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+  let destination: Pubkey = Pubkey::new_unique();
+  let user_account: Pubkey = register(&mut context, &user, "keeper").await;
+  remove_account(&mut context, &instruction::find_username_address(&program_id(), "keeper"));
+  let locked: u64 = get_lamports(&mut context, &user_account).await;
+
+  close_user(&mut context, &user, "keeper", &destination).await.unwrap();
+
+  assert!(!account_exists(&mut context, &user_account).await);
+  assert_eq!(get_lamports(&mut context, &destination).await, locked);
+}
+
+#[tokio::test]
+async fn close_user_lets_the_wallet_register_again() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+  register(&mut context, &user, "keeper").await;
+  close_user(&mut context, &user, "keeper", &user.pubkey()).await.unwrap();
+
+  let user_account: Pubkey = register(&mut context, &user, "keeper").await;
+
+  let data: UserAccount = get_user(&mut context, &user_account).await;
+  assert_eq!(data.user_name, "keeperXXXX");
+}
+
+#[tokio::test]
+async fn close_user_releases_team_players() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+  let user_account: Pubkey = register(&mut context, &user, "keeper").await;
+  let (player, nft, _) = generate(&mut context, &user).await;
+  let mut data: Player = get_player(&mut context, &player).await;
+  data.status = PlayerStatus::InTeam;
+  set_player(&mut context, &player, &data).await;
+  let mut user_data: UserAccount = get_user(&mut context, &user_account).await;
+  user_data.team[2] = nft.to_bytes();
+  set_user(&mut context, &user_account, &user_data).await;
+
+  process(
+    &mut context,
+    &[instruction::close_user(&program_id(), &user.pubkey(), "keeper", &user.pubkey(), &[nft])],
+    &[&user],
+  )
+  .await
+  .unwrap();
+
+  assert_eq!(get_player(&mut context, &player).await.status, PlayerStatus::Idle);
+  close_player(&mut context, &user, &nft, &user.pubkey()).await.unwrap();
+}
+
+#[tokio::test]
+async fn close_user_rejects_missing_team_player() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+  let user_account: Pubkey = register(&mut context, &user, "keeper").await;
+  let (_, nft, _) = generate(&mut context, &user).await;
+  let mut user_data: UserAccount = get_user(&mut context, &user_account).await;
+  user_data.team[2] = nft.to_bytes();
+  set_user(&mut context, &user_account, &user_data).await;
+
+  let result = close_user(&mut context, &user, "keeper", &user.pubkey()).await;
+
+  assert_game_error(result, GameError::TeamPlayerMissing);
+}
+
+#[tokio::test]
+async fn close_user_rejects_other_signer() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+  let thief: Keypair = create_user(&mut context).await;
+  let user_account: Pubkey = register(&mut context, &user, "keeper").await;
+  let mut close = instruction::close_user(&program_id(), &thief.pubkey(), "keeper", &thief.pubkey(), &[]);
+  close.accounts[1].pubkey = user_account;
+
+  let result = process(&mut context, &[close], &[&thief]).await;
+
+  assert_game_error(result, GameError::NotAccountOwner);
+  assert!(account_exists(&mut context, &user_account).await);
+}
+
+#[tokio::test]
+async fn close_user_rejects_refund_to_itself() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+  let user_account: Pubkey = register(&mut context, &user, "keeper").await;
+
+  let result = close_user(&mut context, &user, "keeper", &user_account).await;
+
+  assert_game_error(result, GameError::InvalidDestination);
+}
+
+#[tokio::test]
+async fn close_player_refunds_destination() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+  let destination: Pubkey = Pubkey::new_unique();
+  let (player, nft, _) = generate(&mut context, &user).await;
+  let locked: u64 = get_lamports(&mut context, &player).await;
+
+  close_player(&mut context, &user, &nft, &destination).await.unwrap();
+
+  assert!(!account_exists(&mut context, &player).await);
+  assert_eq!(get_lamports(&mut context, &destination).await, locked);
+}
+
+#[tokio::test]
+async fn close_player_rejects_other_signer() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+  let thief: Keypair = create_user(&mut context).await;
+  let (player, nft, _) = generate(&mut context, &user).await;
+
+  let result = close_player(&mut context, &thief, &nft, &thief.pubkey()).await;
+
+  assert_game_error(result, GameError::NotPlayerOwner);
+  assert!(account_exists(&mut context, &player).await);
+}
+
+#[tokio::test]
+async fn close_player_rejects_listed_player() {
+  let mut context = start().await;
+  setup_marketplace(&mut context).await;
+  let user: Keypair = create_user(&mut context).await;
+  let (player, nft, ata) = generate(&mut context, &user).await;
+  list_for_sale(&mut context, &user, &player, &nft, &ata, 1_000_000).await.unwrap();
+
+  let result = close_player(&mut context, &user, &nft, &user.pubkey()).await;
+
+  assert_game_error(result, GameError::PlayerListed);
+}
+
+#[tokio::test]
+async fn close_player_rejects_rented_player() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+  let (player, nft, _) = generate(&mut context, &user).await;
+  let mut data: Player = get_player(&mut context, &player).await;
+  data.status = PlayerStatus::Rented;
+  set_player(&mut context, &player, &data).await;
+
+  let result = close_player(&mut context, &user, &nft, &user.pubkey()).await;
+
+  assert_game_error(result, GameError::PlayerRented);
+}
+
+#[tokio::test]
+async fn close_player_rejects_player_in_team() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+  let (player, nft, _) = generate(&mut context, &user).await;
+  let mut data: Player = get_player(&mut context, &player).await;
+  data.status = PlayerStatus::InTeam;
+  set_player(&mut context, &player, &data).await;
+
+  let result = close_player(&mut context, &user, &nft, &user.pubkey()).await;
+
+  assert_game_error(result, GameError::PlayerInTeam);
+}
+
+#[tokio::test]
+async fn close_player_rejects_refund_to_itself() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+  let (player, nft, _) = generate(&mut context, &user).await;
+
+  let result = close_player(&mut context, &user, &nft, &player).await;
+
+  assert_game_error(result, GameError::InvalidDestination);
+}
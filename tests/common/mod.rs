@@ -0,0 +1,516 @@
+#![allow(dead_code)]
+
+use sol_battle_balls::{
+  error::GameError,
+  instruction,
+  processor::Processor,
+  randomness,
+  simulation::Lineup,
+  state::{Formation, GameConfig, ListingKind, Player, ProgramAccount, RandomnessCommit, UserAccount, TEAM_SIZE},
+};
+use solana_program::{
+  bpf_loader_upgradeable,
+  clock::Clock,
+  hash::Hash,
+  instruction::{Instruction, InstructionError},
+  program_pack::Pack,
+  pubkey::Pubkey,
+  rent::Rent,
+  slot_hashes::SlotHashes,
+  system_instruction,
+};
+use solana_program_test::{processor, BanksClientError, ProgramTest, ProgramTestContext};
+use solana_sdk::{
+  account::Account,
+  signature::{Keypair, Signer},
+  transaction::{Transaction, TransactionError},
+};
+
+pub const FEE_BASIS_POINTS: u16 = 500;
+
+pub fn program_id() -> Pubkey {
+  Pubkey::new_from_array([7; 32])
+}
+
+pub async fn start() -> ProgramTestContext {
+  let program_test: ProgramTest = ProgramTest::new(
+    "sol_battle_balls",
+    program_id(),
+    processor!(Processor::process),
+  );
+  program_test.start_with_context().await
+}
+
+pub async fn process(
+  context: &mut ProgramTestContext,
+  instructions: &[Instruction],
+  signers: &[&Keypair],
+) -> Result<(), BanksClientError> {
+  let blockhash: Hash = context.banks_client.get_latest_blockhash().await.unwrap();
+  let mut all_signers: Vec<&Keypair> = vec![&context.payer];
+  all_signers.extend_from_slice(signers);
+  let transaction: Transaction = Transaction::new_signed_with_payer(
+    instructions,
+    Some(&context.payer.pubkey()),
+    &all_signers,
+    blockhash,
+  );
+  context.banks_client.process_transaction(transaction).await
+}
+
+pub fn assert_game_error(result: Result<(), BanksClientError>, error: GameError) {
+  match result.unwrap_err().unwrap() {
+    TransactionError::InstructionError(_, InstructionError::Custom(code)) => {
+      assert_eq!(code, error as u32, "expected {:?}", error)
+    }
+    other => panic!("expected {:?}, got {:?}", error, other),
+  }
+}
+
+pub async fn get_account(context: &mut ProgramTestContext, key: &Pubkey) -> Account {
+  context.banks_client.get_account(*key).await.unwrap().unwrap()
+}
+
+pub async fn get_lamports(context: &mut ProgramTestContext, key: &Pubkey) -> u64 {
+  context
+    .banks_client
+    .get_account(*key)
+    .await
+    .unwrap()
+    .map(|account| account.lamports)
+    .unwrap_or(0)
+}
+
+pub async fn account_exists(context: &mut ProgramTestContext, key: &Pubkey) -> bool {
+  context.banks_client.get_account(*key).await.unwrap().is_some()
+}
+
+pub async fn get_player(context: &mut ProgramTestContext, key: &Pubkey) -> Player {
+  Player::unpack(&get_account(context, key).await.data).unwrap()
+}
+
+pub async fn get_user(context: &mut ProgramTestContext, key: &Pubkey) -> UserAccount {
+  UserAccount::unpack(&get_account(context, key).await.data).unwrap()
+}
+
+pub async fn get_config(context: &mut ProgramTestContext) -> GameConfig {
+  let config_address: Pubkey = instruction::find_config_address(&program_id());
+  GameConfig::unpack(&get_account(context, &config_address).await.data).unwrap()
+}
+
+pub async fn get_token_amount(context: &mut ProgramTestContext, key: &Pubkey) -> u64 {
+  spl_token::state::Account::unpack(&get_account(context, key).await.data)
+    .unwrap()
+    .amount
+}
+
+pub async fn set_user(context: &mut ProgramTestContext, key: &Pubkey, user_account: &UserAccount) {
+  let mut account: Account = get_account(context, key).await;
+  account.data = vec![0; user_account.packed_len().unwrap()];
+  user_account.pack_into(&mut account.data).unwrap();
+  context.set_account(key, &account.into());
+}
+
+pub async fn set_player(context: &mut ProgramTestContext, key: &Pubkey, player: &Player) {
+  let mut account: Account = get_account(context, key).await;
+  account.data = vec![0; player.packed_len().unwrap()];
+  player.pack_into(&mut account.data).unwrap();
+  context.set_account(key, &account.into());
+}
+
+/// Stores `data` in a new program-owned account, as an older program version would have.
+pub async fn set_program_account(context: &mut ProgramTestContext, data: Vec<u8>) -> Pubkey {
+  let key: Pubkey = create_program_account(context, data.len()).await.pubkey();
+  let mut account: Account = get_account(context, &key).await;
+  account.data = data;
+  context.set_account(&key, &account.into());
+  key
+}
+
+/// Deletes the account at `key`, like a username record a user registered
+/// before the name registry never had.
+pub fn remove_account(context: &mut ProgramTestContext, key: &Pubkey) {
+  context.set_account(key, &Account::default().into());
+}
+
+/// Moves to a new slot and sets the clock there, so later transactions see `unix_timestamp`.
+pub async fn set_clock(context: &mut ProgramTestContext, unix_timestamp: i64) {
+  let slot: u64 = context.banks_client.get_root_slot().await.unwrap();
+  context.warp_to_slot(slot + 1).unwrap();
+  let mut clock: Clock = context.banks_client.get_sysvar::<Clock>().await.unwrap();
+  clock.unix_timestamp = unix_timestamp;
+  context.set_sysvar(&clock);
+}
+
+pub async fn now(context: &mut ProgramTestContext) -> i64 {
+  context
+    .banks_client
+    .get_sysvar::<Clock>()
+    .await
+    .unwrap()
+    .unix_timestamp
+}
+
+pub async fn create_user(context: &mut ProgramTestContext) -> Keypair {
+  let user: Keypair = Keypair::new();
+  let payer: Pubkey = context.payer.pubkey();
+  process(
+    context,
+    &[system_instruction::transfer(&payer, &user.pubkey(), 10_000_000_000)],
+    &[],
+  )
+  .await
+  .unwrap();
+  user
+}
+
+/// Sends `key` the least lamports a new address can hold, as anyone could
+/// before the program creates an account there.
+pub async fn prefund(context: &mut ProgramTestContext, key: &Pubkey) {
+  let payer: Pubkey = context.payer.pubkey();
+  let lamports: u64 = Rent::default().minimum_balance(0);
+  process(context, &[system_instruction::transfer(&payer, key, lamports)], &[])
+    .await
+    .unwrap();
+}
+
+/// Creates an empty program-owned account of `space` bytes.
+pub async fn create_program_account(context: &mut ProgramTestContext, space: usize) -> Keypair {
+  let account: Keypair = Keypair::new();
+  let payer: Pubkey = context.payer.pubkey();
+  let lamports: u64 = Rent::default().minimum_balance(space);
+  process(
+    context,
+    &[system_instruction::create_account(
+      &payer,
+      &account.pubkey(),
+      lamports,
+      space as u64,
+      &program_id(),
+    )],
+    &[&account],
+  )
+  .await
+  .unwrap();
+  account
+}
+
+pub async fn create_mint(context: &mut ProgramTestContext, decimals: u8) -> Pubkey {
+  let mint: Keypair = Keypair::new();
+  let payer: Pubkey = context.payer.pubkey();
+  let lamports: u64 = Rent::default().minimum_balance(spl_token::state::Mint::LEN);
+  process(
+    context,
+    &[
+      system_instruction::create_account(
+        &payer,
+        &mint.pubkey(),
+        lamports,
+        spl_token::state::Mint::LEN as u64,
+        &spl_token::id(),
+      ),
+      spl_token::instruction::initialize_mint(&spl_token::id(), &mint.pubkey(), &payer, None, decimals)
+        .unwrap(),
+    ],
+    &[&mint],
+  )
+  .await
+  .unwrap();
+  mint.pubkey()
+}
+
+pub async fn create_ata(context: &mut ProgramTestContext, owner: &Pubkey, mint: &Pubkey) -> Pubkey {
+  let payer: Pubkey = context.payer.pubkey();
+  process(
+    context,
+    &[spl_associated_token_account::instruction::create_associated_token_account_idempotent(
+      &payer,
+      owner,
+      mint,
+      &spl_token::id(),
+    )],
+    &[],
+  )
+  .await
+  .unwrap();
+  spl_associated_token_account::get_associated_token_address(owner, mint)
+}
+
+pub async fn mint_to(context: &mut ProgramTestContext, mint: &Pubkey, account: &Pubkey, amount: u64) {
+  let payer: Pubkey = context.payer.pubkey();
+  process(
+    context,
+    &[spl_token::instruction::mint_to(&spl_token::id(), mint, account, &payer, &[], amount).unwrap()],
+    &[],
+  )
+  .await
+  .unwrap();
+}
+
+/// Mints a one-of-one NFT into the owner's associated token account.
+pub async fn mint_nft(context: &mut ProgramTestContext, owner: &Pubkey) -> (Pubkey, Pubkey) {
+  let mint: Pubkey = create_mint(context, 0).await;
+  let ata: Pubkey = create_ata(context, owner, &mint).await;
+  mint_to(context, &mint, &ata, 1).await;
+  (mint, ata)
+}
+
+/// Registers `user` and returns its `UserAccount` address.
+pub async fn register(context: &mut ProgramTestContext, user: &Keypair, name: &str) -> Pubkey {
+  process(
+    context,
+    &[instruction::register_user(&program_id(), &user.pubkey(), name.to_string())],
+    &[user],
+  )
+  .await
+  .unwrap();
+  instruction::find_user_address(&program_id(), &user.pubkey())
+}
+
+/// Commits to `secret` for `target` and moves the bank past the commit slot
+/// so the reveal can mix in the first slot hash after it.
+pub async fn commit(context: &mut ProgramTestContext, user: &Keypair, target: &Pubkey, secret: [u8; 32]) {
+  commit_to(context, user, target, randomness::commitment(&secret)).await;
+}
+
+/// Commits to `secret` and the lineup `user_account` has now for a challenge
+/// of `opponent_account`, see `commit`.
+pub async fn commit_challenge(
+  context: &mut ProgramTestContext,
+  user: &Keypair,
+  user_account: &Pubkey,
+  opponent_account: &Pubkey,
+  secret: [u8; 32],
+) {
+  let data: UserAccount = get_user(context, user_account).await;
+  let mut players: Vec<Player> = Vec::new();
+  for nft in data.team {
+    let player: Pubkey = instruction::find_player_address(&program_id(), &Pubkey::new_from_array(nft));
+    players.push(get_player(context, &player).await);
+  }
+  let players: [Player; TEAM_SIZE] = players.try_into().unwrap();
+  let lineup: Lineup = Lineup { formation: data.formation, players: &players };
+  commit_to(context, user, opponent_account, randomness::challenge_commitment(&secret, &lineup)).await;
+}
+
+async fn commit_to(context: &mut ProgramTestContext, user: &Keypair, target: &Pubkey, commitment: [u8; 32]) {
+  process(
+    context,
+    &[instruction::commit_randomness(&program_id(), &user.pubkey(), target, commitment)],
+    &[user],
+  )
+  .await
+  .unwrap();
+  let commit_address: Pubkey = instruction::find_commit_address(&program_id(), &user.pubkey(), target);
+  let commit_data: RandomnessCommit =
+    RandomnessCommit::unpack(&get_account(context, &commit_address).await.data).unwrap();
+  advance_past(context, commit_data.commit_slot).await;
+}
+
+/// Warps past `slot` and publishes a slot hash for the slot after it.
+pub async fn advance_past(context: &mut ProgramTestContext, slot: u64) {
+  let timestamp: i64 = now(context).await;
+  context.warp_to_slot(slot + 2).unwrap();
+  set_clock(context, timestamp).await;
+  context.set_sysvar(&SlotHashes::new(&[
+    (slot + 1, Hash::new_unique()),
+    (slot, Hash::new_unique()),
+  ]));
+}
+
+/// Mints an NFT for `user` and turns it into a `Player`.
+/// Returns (player account, nft mint, user ata).
+pub async fn generate(context: &mut ProgramTestContext, user: &Keypair) -> (Pubkey, Pubkey, Pubkey) {
+  let (nft, ata) = mint_nft(context, &user.pubkey()).await;
+  let secret: [u8; 32] = Hash::new_unique().to_bytes();
+  commit(context, user, &nft, secret).await;
+  process(
+    context,
+    &[instruction::generate_player(&program_id(), &user.pubkey(), &ata, &nft, secret)],
+    &[user],
+  )
+  .await
+  .unwrap();
+  (instruction::find_player_address(&program_id(), &nft), nft, ata)
+}
+
+/// Generates a player for every team slot. Returns (player accounts, nft mints).
+pub async fn generate_team(context: &mut ProgramTestContext, user: &Keypair) -> ([Pubkey; TEAM_SIZE], [Pubkey; TEAM_SIZE]) {
+  let mut players: [Pubkey; TEAM_SIZE] = [Pubkey::default(); TEAM_SIZE];
+  let mut nfts: [Pubkey; TEAM_SIZE] = [Pubkey::default(); TEAM_SIZE];
+  for (player, nft) in players.iter_mut().zip(nfts.iter_mut()) {
+    (*player, *nft, _) = generate(context, user).await;
+  }
+  (players, nfts)
+}
+
+/// Generates a team for `user` and sets it in a 2-1-2 formation. Returns the nft mints.
+pub async fn field_team(context: &mut ProgramTestContext, user: &Keypair, user_account: &Pubkey) -> [Pubkey; TEAM_SIZE] {
+  let (team, nfts) = generate_team(context, user).await;
+  process(
+    context,
+    &[instruction::set_team(&program_id(), &user.pubkey(), user_account, Formation::TwoOneTwo, &team, &[])],
+    &[user],
+  )
+  .await
+  .unwrap();
+  nfts
+}
+
+/// Writes the `ProgramData` account of the program with `authority` as its
+/// upgrade authority, as a deploy through the upgradeable loader would.
+/// The lamports come from the payer so the bank's capitalization still adds up.
+pub async fn set_upgrade_authority(context: &mut ProgramTestContext, authority: &Pubkey) {
+  let mut data: Vec<u8> = 3u32.to_le_bytes().to_vec();
+  data.extend_from_slice(&0u64.to_le_bytes());
+  data.push(1);
+  data.extend_from_slice(&authority.to_bytes());
+  let program_data: Pubkey = instruction::find_program_data_address(&program_id());
+  let payer: Pubkey = context.payer.pubkey();
+  let lamports: u64 = Rent::default().minimum_balance(data.len());
+  process(context, &[system_instruction::transfer(&payer, &program_data, lamports)], &[])
+    .await
+    .unwrap();
+  let account: Account = Account {
+    lamports,
+    data,
+    owner: bpf_loader_upgradeable::id(),
+    executable: false,
+    rent_epoch: 0,
+  };
+  context.set_account(&program_data, &account.into());
+}
+
+/// Creates the escrow PDA and the fee config with the payer as admin. Returns the treasury.
+pub async fn setup_marketplace(context: &mut ProgramTestContext) -> Pubkey {
+  let treasury: Keypair = create_user(context).await;
+  let payer: Pubkey = context.payer.pubkey();
+  set_upgrade_authority(context, &payer).await;
+  process(
+    context,
+    &[
+      instruction::create_pda(&program_id(), &instruction::find_vault_address(&program_id()), &payer),
+      instruction::init_config(&program_id(), &payer, &treasury.pubkey(), FEE_BASIS_POINTS),
+    ],
+    &[],
+  )
+  .await
+  .unwrap();
+  treasury.pubkey()
+}
+
+/// Lists a freshly generated player for sale at `price`. Returns (seller, player, nft).
+pub async fn listed_player(context: &mut ProgramTestContext, price: u64) -> (Keypair, Pubkey, Pubkey) {
+  let seller: Keypair = create_user(context).await;
+  let (player, nft, ata) = generate(context, &seller).await;
+  list_for_sale(context, &seller, &player, &nft, &ata, price).await.unwrap();
+  (seller, player, nft)
+}
+
+/// Lists a freshly generated player for rent at `price` for `duration`
+/// seconds. Returns (owner, player).
+pub async fn rent_listed_player(context: &mut ProgramTestContext, price: u64, duration: u64) -> (Keypair, Pubkey) {
+  let owner: Keypair = create_user(context).await;
+  let (player, _, _) = generate(context, &owner).await;
+  list_for_rent(context, &owner, &player, price, duration, 0, None).await.unwrap();
+  (owner, player)
+}
+
+pub async fn list_for_sale(
+  context: &mut ProgramTestContext,
+  user: &Keypair,
+  player: &Pubkey,
+  nft: &Pubkey,
+  ata: &Pubkey,
+  price: u64,
+) -> Result<(), BanksClientError> {
+  process(
+    context,
+    &[instruction::sell_player(
+      &program_id(),
+      &user.pubkey(),
+      player,
+      nft,
+      ata,
+      &instruction::find_vault_address(&program_id()),
+      price,
+      None,
+      0,
+    )],
+    &[user],
+  )
+  .await
+}
+
+pub async fn list_for_rent(
+  context: &mut ProgramTestContext,
+  owner: &Keypair,
+  player: &Pubkey,
+  price: u64,
+  duration: u64,
+  max_renewals: u16,
+  payment_mint: Option<Pubkey>,
+) -> Result<(), BanksClientError> {
+  process(
+    context,
+    &[instruction::rent_your_player(
+      &program_id(),
+      &owner.pubkey(),
+      player,
+      price,
+      duration,
+      max_renewals,
+      payment_mint,
+      0,
+    )],
+    &[owner],
+  )
+  .await
+}
+
+/// Rents `player` for `periods` periods of `price` paid from the renter's wallet.
+pub async fn rent_player(
+  context: &mut ProgramTestContext,
+  renter: &Keypair,
+  player: &Pubkey,
+  owner: &Pubkey,
+  treasury: &Pubkey,
+  price: u64,
+  periods: u16,
+) -> Result<(), BanksClientError> {
+  process(
+    context,
+    &[instruction::rent_another_player(
+      &program_id(),
+      &renter.pubkey(),
+      player,
+      owner,
+      treasury,
+      &rent_book(price),
+      periods,
+      None,
+      None,
+    )],
+    &[renter],
+  )
+  .await
+}
+
+/// First `ListingBook` page of the sale listings priced like `price`, where
+/// `list_for_sale` lists.
+pub fn sale_book(price: u64) -> Pubkey {
+  instruction::find_listing_book_address(&program_id(), ListingKind::Sale, price, 0)
+}
+
+/// First `ListingBook` page of the rent listings priced like `price`, where
+/// `list_for_rent` lists.
+pub fn rent_book(price: u64) -> Pubkey {
+  instruction::find_listing_book_address(&program_id(), ListingKind::Rent, price, 0)
+}
+
+pub fn escrow_address(nft: &Pubkey) -> Pubkey {
+  spl_associated_token_account::get_associated_token_address(
+    &instruction::find_vault_address(&program_id()),
+    nft,
+  )
+}
@@ -0,0 +1,190 @@
+mod common;
+
+use common::*;
+use sol_battle_balls::{
+  error::GameError,
+  instruction,
+  state::{GameConfig, ProgramAccount, Vault},
+};
+use solana_program::pubkey::Pubkey;
+use solana_program_test::tokio;
+use solana_sdk::signature::{Keypair, Signer};
+
+#[tokio::test]
+async fn create_pda_stores_vault_bump() {
+  let mut context = start().await;
+  let payer: Pubkey = context.payer.pubkey();
+  let vault_address: Pubkey = instruction::find_vault_address(&program_id());
+
+  process(&mut context, &[instruction::create_pda(&program_id(), &vault_address, &payer)], &[])
+    .await
+    .unwrap();
+
+  let account = get_account(&mut context, &vault_address).await;
+  let vault: Vault = Vault::unpack(&account.data).unwrap();
+  let (_, bump) = Pubkey::find_program_address(&[sol_battle_balls::state::VAULT_SEED], &program_id());
+  assert_eq!(account.owner, program_id());
+  assert_eq!(vault.bump, bump);
+}
+
+#[tokio::test]
+async fn create_pda_rejects_other_address() {
+  let mut context = start().await;
+  let payer: Pubkey = context.payer.pubkey();
+
+  let result = process(
+    &mut context,
+    &[instruction::create_pda(&program_id(), &Pubkey::new_unique(), &payer)],
+    &[],
+  )
+  .await;
+
+  assert_game_error(result, GameError::InvalidPda);
+}
+
+#[tokio::test]
+async fn init_config_makes_signer_admin() {
+  let mut context = start().await;
+  let admin: Keypair = create_user(&mut context).await;
+  let treasury: Pubkey = Pubkey::new_unique();
+  set_upgrade_authority(&mut context, &admin.pubkey()).await;
+
+  process(
+    &mut context,
+    &[instruction::init_config(&program_id(), &admin.pubkey(), &treasury, 250)],
+    &[&admin],
+  )
+  .await
+  .unwrap();
+
+  let config: GameConfig = get_config(&mut context).await;
+  assert_eq!(config.admin, admin.pubkey().to_bytes());
+  assert_eq!(config.treasury, treasury.to_bytes());
+  assert_eq!(config.fee_basis_points, 250);
+}
+
+#[tokio::test]
+async fn init_config_rejects_fee_above_100_percent() {
+  let mut context = start().await;
+  let admin: Keypair = create_user(&mut context).await;
+  set_upgrade_authority(&mut context, &admin.pubkey()).await;
+
+  let result = process(
+    &mut context,
+    &[instruction::init_config(&program_id(), &admin.pubkey(), &Pubkey::new_unique(), 10001)],
+    &[&admin],
+  )
+  .await;
+
+  assert_game_error(result, GameError::InvalidFee);
+}
+
+#[tokio::test]
+async fn init_config_rejects_signer_other_than_upgrade_authority() {
+  let mut context = start().await;
+  let admin: Keypair = create_user(&mut context).await;
+  let squatter: Keypair = create_user(&mut context).await;
+  set_upgrade_authority(&mut context, &admin.pubkey()).await;
+
+  let result = process(
+    &mut context,
+    &[instruction::init_config(&program_id(), &squatter.pubkey(), &squatter.pubkey(), 0)],
+    &[&squatter],
+  )
+  .await;
+
+  assert_game_error(result, GameError::NotAdmin);
+}
+
+#[tokio::test]
+async fn update_config_changes_treasury_and_fee() {
+  let mut context = start().await;
+  setup_marketplace(&mut context).await;
+  let payer: Pubkey = context.payer.pubkey();
+  let treasury: Pubkey = Pubkey::new_unique();
+
+  process(
+    &mut context,
+    &[instruction::update_config(&program_id(), &payer, &treasury, 1000)],
+    &[],
+  )
+  .await
+  .unwrap();
+
+  let config: GameConfig = get_config(&mut context).await;
+  assert_eq!(config.treasury, treasury.to_bytes());
+  assert_eq!(config.fee_basis_points, 1000);
+}
+
+#[tokio::test]
+async fn update_config_rejects_other_signer() {
+  let mut context = start().await;
+  setup_marketplace(&mut context).await;
+  let other: Keypair = create_user(&mut context).await;
+
+  let result = process(
+    &mut context,
+    &[instruction::update_config(&program_id(), &other.pubkey(), &other.pubkey(), 0)],
+    &[&other],
+  )
+  .await;
+
+  assert_game_error(result, GameError::NotAdmin);
+}
+
+#[tokio::test]
+async fn update_config_rejects_fee_above_100_percent() {
+  let mut context = start().await;
+  setup_marketplace(&mut context).await;
+  let payer: Pubkey = context.payer.pubkey();
+
+  let result = process(
+    &mut context,
+    &[instruction::update_config(&program_id(), &payer, &payer, 10001)],
+    &[],
+  )
+  .await;
+
+  assert_game_error(result, GameError::InvalidFee);
+}
+
+#[tokio::test]
+async fn transfer_admin_hands_over_config() {
+  let mut context = start().await;
+  setup_marketplace(&mut context).await;
+  let payer: Pubkey = context.payer.pubkey();
+  let new_admin: Keypair = create_user(&mut context).await;
+
+  process(
+    &mut context,
+    &[instruction::transfer_admin(&program_id(), &payer, &new_admin.pubkey())],
+    &[&new_admin],
+  )
+  .await
+  .unwrap();
+
+  assert_eq!(get_config(&mut context).await.admin, new_admin.pubkey().to_bytes());
+  let result = process(
+    &mut context,
+    &[instruction::update_config(&program_id(), &payer, &payer, 0)],
+    &[],
+  )
+  .await;
+  assert_game_error(result, GameError::NotAdmin);
+}
+
+#[tokio::test]
+async fn transfer_admin_rejects_other_signer() {
+  let mut context = start().await;
+  setup_marketplace(&mut context).await;
+  let other: Keypair = create_user(&mut context).await;
+
+  let result = process(
+    &mut context,
+    &[instruction::transfer_admin(&program_id(), &other.pubkey(), &other.pubkey())],
+    &[&other],
+  )
+  .await;
+
+  assert_game_error(result, GameError::NotAdmin);
+}
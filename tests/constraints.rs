@@ -0,0 +1,321 @@
+mod common;
+
+use common::*;
+use sol_battle_balls::{
+  constraints::{self, AccountConstraint},
+  error::GameError,
+  instruction::{self, GameInstruction},
+  state::{AccountType, Formation, UserAccount, TEAM_SIZE},
+};
+use solana_program::{
+  hash::Hash,
+  instruction::{Instruction, InstructionError},
+  pubkey::Pubkey,
+};
+use solana_program_test::tokio;
+use solana_sdk::{
+  signature::{Keypair, Signer},
+  transaction::TransactionError,
+};
+
+const PRICE: u64 = 1_000_000;
+
+/// `ix` with the account at `index` no longer signing, as an attacker would send it.
+fn unsigned(mut ix: Instruction, index: usize) -> Instruction {
+  ix.accounts[index].is_signer = false;
+  ix
+}
+
+fn buy(buyer: &Pubkey, player: &Pubkey, nft: &Pubkey, seller: &Pubkey, treasury: &Pubkey) -> Instruction {
+  instruction::buy_player(
+    &program_id(),
+    buyer,
+    player,
+    nft,
+    seller,
+    &instruction::find_vault_address(&program_id()),
+    &escrow_address(nft),
+    &spl_associated_token_account::get_associated_token_address(buyer, nft),
+    treasury,
+    &sale_book(PRICE),
+    None,
+    None,
+  )
+}
+
+#[test]
+fn builders_satisfy_the_declared_constraints() {
+  let id: Pubkey = program_id();
+  let key: Pubkey = Pubkey::new_unique();
+  let instructions: Vec<Instruction> = vec![
+    instruction::register_user(&id, &key, "alice".to_string()),
+    instruction::generate_player(&id, &key, &key, &key, [0; 32]),
+    instruction::buy_player(&id, &key, &key, &key, &key, &key, &key, &key, &key, &key, None, None),
+    instruction::rent_another_player(&id, &key, &key, &key, &key, &key, 1, None, None),
+    instruction::sell_player(&id, &key, &key, &key, &key, &key, PRICE, None, 0),
+    instruction::rent_your_player(&id, &key, &key, PRICE, 60, 0, None, 0),
+    instruction::set_team(&id, &key, &key, Formation::TwoOneTwo, &[key; TEAM_SIZE], &[key]),
+    instruction::challenge(&id, &key, &key, &key, &[key; TEAM_SIZE], &[key; TEAM_SIZE], 1, [0; 32]),
+    instruction::abort_rent(&id, &key, &key, &key),
+    instruction::abort_sell(&id, &key, &key, &key, &key, &key, &key),
+    instruction::claim_rent_over_player(&id, &key, &key, &key, None),
+    instruction::claim_nft_player(&id, &key, &key, &key, &key),
+    instruction::create_pda(&id, &key, &key),
+    instruction::upgrade(&id, &key, &[key; TEAM_SIZE], 0, 1),
+    instruction::init_config(&id, &key, &key, 100),
+    instruction::update_config(&id, &key, &key, 100),
+    instruction::create_auction(&id, &key, &key, &key, &key, PRICE, 60),
+    instruction::place_bid(&id, &key, &key, &key, PRICE),
+    instruction::settle_auction(&id, &key, &key, &key, &key, &key, &key),
+    instruction::cancel_auction(&id, &key, &key, &key, &key),
+    instruction::commit_randomness(&id, &key, &key, [0; 32]),
+    instruction::migrate_account(&id, &key, &key, AccountType::Player),
+    instruction::rename_user(&id, &key, "alice", "bob".to_string()),
+    instruction::close_user(&id, &key, "alice", &key, &[]),
+    instruction::close_player(&id, &key, &key, &key),
+    instruction::release_rent(&id, &key, &key, None),
+    instruction::terminate_rent(&id, &key, &key, &key, &key, None),
+    instruction::renew_rent(&id, &key, &key, &key, None),
+    instruction::refresh_team(&id, &key, &[key; TEAM_SIZE]),
+    instruction::cancel_commit(&id, &key, &key, &key),
+    instruction::transfer_admin(&id, &key, &key),
+  ];
+
+  for ix in instructions {
+    let game_instruction: GameInstruction = GameInstruction::unpack(&ix.data).unwrap();
+    let declared: &[AccountConstraint] = constraints::account_constraints(&game_instruction);
+    assert!(ix.accounts.len() >= declared.len(), "{:?}", game_instruction);
+    for (meta, constraint) in ix.accounts.iter().zip(declared) {
+      assert!(meta.is_signer || !constraint.signer, "{:?} {}", game_instruction, meta.pubkey);
+      assert!(meta.is_writable || !constraint.writable, "{:?} {}", game_instruction, meta.pubkey);
+    }
+  }
+}
+
+#[tokio::test]
+async fn buy_player_rejects_unsigned_buyer() {
+  let mut context = start().await;
+  let treasury: Pubkey = setup_marketplace(&mut context).await;
+  let (seller, player, nft) = listed_player(&mut context, PRICE).await;
+  let victim: Keypair = create_user(&mut context).await;
+  create_ata(&mut context, &victim.pubkey(), &nft).await;
+  let victim_before: u64 = get_lamports(&mut context, &victim.pubkey()).await;
+  let ix = buy(&victim.pubkey(), &player, &nft, &seller.pubkey(), &treasury);
+
+  let result = process(&mut context, &[unsigned(ix, 0)], &[]).await;
+
+  assert_game_error(result, GameError::MissingSignature);
+  assert_eq!(get_lamports(&mut context, &victim.pubkey()).await, victim_before);
+}
+
+#[tokio::test]
+async fn buy_player_rejects_fake_system_program() {
+  let mut context = start().await;
+  let treasury: Pubkey = setup_marketplace(&mut context).await;
+  let (seller, player, nft) = listed_player(&mut context, PRICE).await;
+  let buyer: Keypair = create_user(&mut context).await;
+  create_ata(&mut context, &buyer.pubkey(), &nft).await;
+  let mut ix = buy(&buyer.pubkey(), &player, &nft, &seller.pubkey(), &treasury);
+  ix.accounts[4].pubkey = Pubkey::new_unique();
+
+  let result = process(&mut context, &[ix], &[&buyer]).await;
+
+  assert_game_error(result, GameError::UnexpectedAccount);
+}
+
+#[tokio::test]
+async fn rent_another_player_rejects_unsigned_renter() {
+  let mut context = start().await;
+  let treasury: Pubkey = setup_marketplace(&mut context).await;
+  let owner: Keypair = create_user(&mut context).await;
+  let (player, _, _) = generate(&mut context, &owner).await;
+  list_for_rent(&mut context, &owner, &player, PRICE, 3600, 0, None).await.unwrap();
+  let victim: Keypair = create_user(&mut context).await;
+  let ix = instruction::rent_another_player(
+    &program_id(),
+    &victim.pubkey(),
+    &player,
+    &owner.pubkey(),
+    &treasury,
+    &rent_book(PRICE),
+    1,
+    None,
+    None,
+  );
+
+  let result = process(&mut context, &[unsigned(ix, 0)], &[]).await;
+
+  assert_game_error(result, GameError::MissingSignature);
+}
+
+#[tokio::test]
+async fn set_team_rejects_unsigned_user() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+  let user_account: Pubkey = register(&mut context, &user, "alice").await;
+  let (player, _, _) = generate(&mut context, &user).await;
+  let ix = instruction::set_team(&program_id(), &user.pubkey(), &user_account, Formation::TwoOneTwo, &[player; TEAM_SIZE], &[]);
+
+  let result = process(&mut context, &[unsigned(ix, 0)], &[]).await;
+
+  assert_game_error(result, GameError::MissingSignature);
+}
+
+#[tokio::test]
+async fn challenge_rejects_unsigned_user() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+  let opponent: Keypair = create_user(&mut context).await;
+  let user_account: Pubkey = register(&mut context, &user, "alice").await;
+  let opponent_account: Pubkey = register(&mut context, &opponent, "bob").await;
+  let secret: [u8; 32] = Hash::new_unique().to_bytes();
+  commit(&mut context, &user, &opponent_account, secret).await;
+  let team: [Pubkey; TEAM_SIZE] = [Pubkey::default(); TEAM_SIZE];
+  let ix = instruction::challenge(&program_id(), &user.pubkey(), &user_account, &opponent_account, &team, &team, 1, secret);
+
+  let result = process(&mut context, &[unsigned(ix, 0)], &[]).await;
+
+  assert_game_error(result, GameError::MissingSignature);
+  assert_eq!(get_user(&mut context, &user_account).await.wins, 0);
+}
+
+#[tokio::test]
+async fn claim_rent_over_player_rejects_unsigned_owner() {
+  let mut context = start().await;
+  let treasury: Pubkey = setup_marketplace(&mut context).await;
+  let owner: Keypair = create_user(&mut context).await;
+  let renter: Keypair = create_user(&mut context).await;
+  let (player, _, _) = generate(&mut context, &owner).await;
+  list_for_rent(&mut context, &owner, &player, PRICE, 60, 0, None).await.unwrap();
+  rent_player(&mut context, &renter, &player, &owner.pubkey(), &treasury, PRICE, 1).await.unwrap();
+  let timestamp: i64 = now(&mut context).await;
+  set_clock(&mut context, timestamp + 60).await;
+  let ix = instruction::claim_rent_over_player(&program_id(), &owner.pubkey(), &player, &renter.pubkey(), None);
+
+  let result = process(&mut context, &[unsigned(ix, 0)], &[]).await;
+
+  assert_game_error(result, GameError::MissingSignature);
+}
+
+#[tokio::test]
+async fn claim_nft_player_rejects_unsigned_user() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+  let (player, nft, ata) = generate(&mut context, &user).await;
+  let ix = instruction::claim_nft_player(&program_id(), &user.pubkey(), &ata, &player, &nft);
+
+  let result = process(&mut context, &[unsigned(ix, 0)], &[]).await;
+
+  assert_game_error(result, GameError::MissingSignature);
+}
+
+#[tokio::test]
+async fn upgrade_rejects_unsigned_user() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+  let user_account: Pubkey = register(&mut context, &user, "alice").await;
+  let (team, _) = generate_team(&mut context, &user).await;
+  process(
+    &mut context,
+    &[instruction::set_team(&program_id(), &user.pubkey(), &user_account, Formation::TwoOneTwo, &team, &[])],
+    &[&user],
+  )
+  .await
+  .unwrap();
+  let mut data: UserAccount = get_user(&mut context, &user_account).await;
+  data.experienced = 10;
+  set_user(&mut context, &user_account, &data).await;
+  let ix = instruction::upgrade(&program_id(), &user.pubkey(), &team, 0, 4);
+
+  let result = process(&mut context, &[unsigned(ix, 0)], &[]).await;
+
+  assert_game_error(result, GameError::MissingSignature);
+  assert_eq!(get_user(&mut context, &user_account).await.experienced, 10);
+}
+
+#[tokio::test]
+async fn upgrade_rejects_user_account_of_another_wallet() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+  let thief: Keypair = create_user(&mut context).await;
+  let user_account: Pubkey = register(&mut context, &user, "alice").await;
+  let (player, _, _) = generate(&mut context, &user).await;
+  let mut ix = instruction::upgrade(&program_id(), &thief.pubkey(), &[player; TEAM_SIZE], 0, 4);
+  ix.accounts[1].pubkey = user_account;
+
+  let result = process(&mut context, &[ix], &[&thief]).await;
+
+  assert_game_error(result, GameError::InvalidPda);
+}
+
+#[tokio::test]
+async fn sell_player_rejects_readonly_player() {
+  let mut context = start().await;
+  setup_marketplace(&mut context).await;
+  let seller: Keypair = create_user(&mut context).await;
+  let (player, nft, ata) = generate(&mut context, &seller).await;
+  let vault: Pubkey = instruction::find_vault_address(&program_id());
+  let mut ix = instruction::sell_player(&program_id(), &seller.pubkey(), &player, &nft, &ata, &vault, PRICE, None, 0);
+  ix.accounts[1].is_writable = false;
+
+  let result = process(&mut context, &[ix], &[&seller]).await;
+
+  assert_game_error(result, GameError::NotWritable);
+}
+
+#[tokio::test]
+async fn sell_player_rejects_fake_token_program() {
+  let mut context = start().await;
+  setup_marketplace(&mut context).await;
+  let seller: Keypair = create_user(&mut context).await;
+  let (player, nft, ata) = generate(&mut context, &seller).await;
+  let vault: Pubkey = instruction::find_vault_address(&program_id());
+  let mut ix = instruction::sell_player(&program_id(), &seller.pubkey(), &player, &nft, &ata, &vault, PRICE, None, 0);
+  ix.accounts[6].pubkey = Pubkey::new_unique();
+
+  let result = process(&mut context, &[ix], &[&seller]).await;
+
+  assert_game_error(result, GameError::UnexpectedAccount);
+}
+
+#[tokio::test]
+async fn abort_rent_rejects_player_of_other_program() {
+  let mut context = start().await;
+  let owner: Keypair = create_user(&mut context).await;
+  let impostor: Keypair = create_user(&mut context).await;
+  let ix = instruction::abort_rent(&program_id(), &owner.pubkey(), &impostor.pubkey(), &rent_book(PRICE));
+
+  let result = process(&mut context, &[ix], &[&owner]).await;
+
+  assert_game_error(result, GameError::IncorrectProgramOwner);
+}
+
+#[tokio::test]
+async fn create_auction_rejects_auction_of_another_player() {
+  let mut context = start().await;
+  setup_marketplace(&mut context).await;
+  let seller: Keypair = create_user(&mut context).await;
+  let (player, nft, ata) = generate(&mut context, &seller).await;
+  let mut ix = instruction::create_auction(&program_id(), &seller.pubkey(), &player, &nft, &ata, PRICE, 3600);
+  ix.accounts[2].pubkey = instruction::find_auction_address(&program_id(), &Pubkey::new_unique());
+
+  let result = process(&mut context, &[ix], &[&seller]).await;
+
+  assert_game_error(result, GameError::InvalidPda);
+}
+
+#[tokio::test]
+async fn instruction_rejects_missing_accounts() {
+  let mut context = start().await;
+  let owner: Keypair = create_user(&mut context).await;
+  let (player, _, _) = generate(&mut context, &owner).await;
+  let mut ix = instruction::abort_rent(&program_id(), &owner.pubkey(), &player, &rent_book(PRICE));
+  ix.accounts.pop();
+
+  let result = process(&mut context, &[ix], &[&owner]).await;
+
+  assert_eq!(
+    result.unwrap_err().unwrap(),
+    TransactionError::InstructionError(0, InstructionError::NotEnoughAccountKeys)
+  );
+}
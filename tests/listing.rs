@@ -0,0 +1,319 @@
+mod common;
+
+use common::*;
+use sol_battle_balls::{
+  error::GameError,
+  instruction,
+  state::{Listing, ListingBook, ListingKind, Player, PlayerStatus, ProgramAccount, LISTING_PAGE_CAPACITY, UNINDEXED_LISTING},
+};
+use solana_program::{pubkey::Pubkey, rent::Rent};
+use solana_program_test::{tokio, ProgramTestContext};
+use solana_sdk::{
+  account::Account,
+  signature::{Keypair, Signer},
+};
+
+//all in the bucket of [2^20, 2^21)
+const CHEAP: u64 = 1_050_000;
+const PRICE: u64 = 1_100_000;
+const DEAR: u64 = 1_200_000;
+
+async fn get_book(context: &mut ProgramTestContext, key: &Pubkey) -> ListingBook {
+  ListingBook::unpack(&get_account(context, key).await.data).unwrap()
+}
+
+fn listed_players(book: &ListingBook) -> Vec<[u8; 32]> {
+  book.entries.iter().map(|entry| entry.player).collect()
+}
+
+#[tokio::test]
+async fn sell_player_creates_page_with_listing_stats() {
+  let mut context = start().await;
+  setup_marketplace(&mut context).await;
+
+  let (_, player, _) = listed_player(&mut context, PRICE).await;
+
+  let book: ListingBook = get_book(&mut context, &sale_book(PRICE)).await;
+  let data: Player = get_player(&mut context, &player).await;
+  assert_eq!(book.kind, ListingKind::Sale);
+  assert_eq!(book.bucket, 21);
+  assert_eq!(book.page, 0);
+  assert_eq!(
+    book.entries,
+    vec![Listing {
+      player: player.to_bytes(),
+      price: PRICE,
+      offence: data.offence,
+      defence: data.defence,
+      payment_mint: [0; 32],
+    }]
+  );
+  assert_eq!(
+    get_lamports(&mut context, &sale_book(PRICE)).await,
+    Rent::default().minimum_balance(ListingBook::SPACE)
+  );
+}
+
+#[tokio::test]
+async fn sell_player_creates_page_prefunded_with_lamports() {
+  let mut context = start().await;
+  setup_marketplace(&mut context).await;
+  prefund(&mut context, &sale_book(PRICE)).await;
+
+  let (_, player, _) = listed_player(&mut context, PRICE).await;
+
+  let book: ListingBook = get_book(&mut context, &sale_book(PRICE)).await;
+  assert_eq!(listed_players(&book), vec![player.to_bytes()]);
+  assert_eq!(get_account(&mut context, &sale_book(PRICE)).await.owner, program_id());
+}
+
+#[tokio::test]
+async fn sell_player_records_listing_page() {
+  let mut context = start().await;
+  setup_marketplace(&mut context).await;
+
+  let (_, player, _) = listed_player(&mut context, PRICE).await;
+
+  assert_eq!(get_player(&mut context, &player).await.listing_page, 0);
+}
+
+#[tokio::test]
+async fn sell_player_rejects_unindexed_page_number() {
+  let mut context = start().await;
+  setup_marketplace(&mut context).await;
+  let seller: Keypair = create_user(&mut context).await;
+  let (player, nft, ata) = generate(&mut context, &seller).await;
+  let vault: Pubkey = instruction::find_vault_address(&program_id());
+  let sell =
+    instruction::sell_player(&program_id(), &seller.pubkey(), &player, &nft, &ata, &vault, PRICE, None, UNINDEXED_LISTING);
+
+  let result = process(&mut context, &[sell], &[&seller]).await;
+
+  assert_game_error(result, GameError::InvalidListingBook);
+}
+
+#[tokio::test]
+async fn listings_are_sorted_by_price() {
+  let mut context = start().await;
+  setup_marketplace(&mut context).await;
+
+  let (_, dear, _) = listed_player(&mut context, DEAR).await;
+  let (_, cheap, _) = listed_player(&mut context, CHEAP).await;
+  let (_, middle, _) = listed_player(&mut context, PRICE).await;
+
+  let book: ListingBook = get_book(&mut context, &sale_book(PRICE)).await;
+  assert_eq!(listed_players(&book), vec![cheap.to_bytes(), middle.to_bytes(), dear.to_bytes()]);
+}
+
+#[tokio::test]
+async fn listings_of_other_buckets_use_other_pages() {
+  let mut context = start().await;
+  setup_marketplace(&mut context).await;
+
+  let (_, player, _) = listed_player(&mut context, PRICE).await;
+  let (_, cheaper, _) = listed_player(&mut context, 1_000).await;
+
+  assert_ne!(sale_book(PRICE), sale_book(1_000));
+  assert_eq!(listed_players(&get_book(&mut context, &sale_book(PRICE)).await), vec![player.to_bytes()]);
+  assert_eq!(listed_players(&get_book(&mut context, &sale_book(1_000)).await), vec![cheaper.to_bytes()]);
+}
+
+#[tokio::test]
+async fn sell_player_rejects_page_of_another_bucket() {
+  let mut context = start().await;
+  setup_marketplace(&mut context).await;
+  let seller: Keypair = create_user(&mut context).await;
+  let (player, nft, ata) = generate(&mut context, &seller).await;
+  let vault: Pubkey = instruction::find_vault_address(&program_id());
+  let mut sell = instruction::sell_player(&program_id(), &seller.pubkey(), &player, &nft, &ata, &vault, PRICE, None, 0);
+  sell.accounts[9].pubkey = sale_book(1_000);
+
+  let result = process(&mut context, &[sell], &[&seller]).await;
+
+  assert_game_error(result, GameError::InvalidListingBook);
+}
+
+#[tokio::test]
+async fn full_page_rejects_listing_and_next_page_takes_it() {
+  let mut context = start().await;
+  setup_marketplace(&mut context).await;
+  listed_player(&mut context, PRICE).await;
+  let mut book: ListingBook = get_book(&mut context, &sale_book(PRICE)).await;
+  while book.entries.len() < LISTING_PAGE_CAPACITY {
+    let filler = Listing { player: Pubkey::new_unique().to_bytes(), price: PRICE, offence: 0, defence: 0, payment_mint: [0; 32] };
+    book.insert(filler).unwrap();
+  }
+  let mut account: Account = get_account(&mut context, &sale_book(PRICE)).await;
+  book.pack_into(&mut account.data).unwrap();
+  context.set_account(&sale_book(PRICE), &account.into());
+  let seller: Keypair = create_user(&mut context).await;
+  let (player, nft, ata) = generate(&mut context, &seller).await;
+  let vault: Pubkey = instruction::find_vault_address(&program_id());
+
+  let result = list_for_sale(&mut context, &seller, &player, &nft, &ata, PRICE).await;
+  assert_game_error(result, GameError::ListingPageFull);
+
+  let sell = instruction::sell_player(&program_id(), &seller.pubkey(), &player, &nft, &ata, &vault, PRICE, None, 1);
+  process(&mut context, &[sell], &[&seller]).await.unwrap();
+
+  let next_page: Pubkey = instruction::find_listing_book_address(&program_id(), ListingKind::Sale, PRICE, 1);
+  let book: ListingBook = get_book(&mut context, &next_page).await;
+  assert_eq!(book.page, 1);
+  assert_eq!(listed_players(&book), vec![player.to_bytes()]);
+}
+
+#[tokio::test]
+async fn abort_sell_removes_listing() {
+  let mut context = start().await;
+  setup_marketplace(&mut context).await;
+  let (_, kept, _) = listed_player(&mut context, CHEAP).await;
+  let (seller, player, nft) = listed_player(&mut context, PRICE).await;
+  let ata: Pubkey = spl_associated_token_account::get_associated_token_address(&seller.pubkey(), &nft);
+  let vault: Pubkey = instruction::find_vault_address(&program_id());
+  let abort =
+    instruction::abort_sell(&program_id(), &seller.pubkey(), &player, &vault, &escrow_address(&nft), &ata, &sale_book(PRICE));
+
+  process(&mut context, &[abort], &[&seller]).await.unwrap();
+
+  assert_eq!(listed_players(&get_book(&mut context, &sale_book(PRICE)).await), vec![kept.to_bytes()]);
+}
+
+#[tokio::test]
+async fn buy_player_removes_listing() {
+  let mut context = start().await;
+  let treasury: Pubkey = setup_marketplace(&mut context).await;
+  let (seller, player, nft) = listed_player(&mut context, PRICE).await;
+  let buyer: Keypair = create_user(&mut context).await;
+  let buyer_ata: Pubkey = create_ata(&mut context, &buyer.pubkey(), &nft).await;
+
+  process(
+    &mut context,
+    &[instruction::buy_player(
+      &program_id(),
+      &buyer.pubkey(),
+      &player,
+      &nft,
+      &seller.pubkey(),
+      &instruction::find_vault_address(&program_id()),
+      &escrow_address(&nft),
+      &buyer_ata,
+      &treasury,
+      &sale_book(PRICE),
+      None,
+      None,
+    )],
+    &[&buyer],
+  )
+  .await
+  .unwrap();
+
+  assert!(get_book(&mut context, &sale_book(PRICE)).await.entries.is_empty());
+}
+
+#[tokio::test]
+async fn rent_your_player_adds_rent_listing() {
+  let mut context = start().await;
+  setup_marketplace(&mut context).await;
+
+  let (_, player) = rent_listed_player(&mut context, PRICE, 3600).await;
+
+  let book: ListingBook = get_book(&mut context, &rent_book(PRICE)).await;
+  assert_eq!(book.kind, ListingKind::Rent);
+  assert_eq!(listed_players(&book), vec![player.to_bytes()]);
+  assert!(!account_exists(&mut context, &sale_book(PRICE)).await);
+}
+
+#[tokio::test]
+async fn rent_your_player_rejects_listed_player() {
+  let mut context = start().await;
+  setup_marketplace(&mut context).await;
+  let (owner, player) = rent_listed_player(&mut context, PRICE, 3600).await;
+
+  let result = list_for_rent(&mut context, &owner, &player, DEAR, 3600, 0, None).await;
+
+  assert_game_error(result, GameError::PlayerListed);
+}
+
+#[tokio::test]
+async fn abort_rent_removes_listing() {
+  let mut context = start().await;
+  setup_marketplace(&mut context).await;
+  let (owner, player) = rent_listed_player(&mut context, PRICE, 3600).await;
+
+  process(
+    &mut context,
+    &[instruction::abort_rent(&program_id(), &owner.pubkey(), &player, &rent_book(PRICE))],
+    &[&owner],
+  )
+  .await
+  .unwrap();
+
+  assert!(get_book(&mut context, &rent_book(PRICE)).await.entries.is_empty());
+}
+
+#[tokio::test]
+async fn abort_rent_drops_unindexed_listing_still_in_a_page() {
+  let mut context = start().await;
+  setup_marketplace(&mut context).await;
+  let (owner, player) = rent_listed_player(&mut context, PRICE, 3600).await;
+  let mut data: Player = get_player(&mut context, &player).await;
+  data.listing_page = UNINDEXED_LISTING;
+  set_player(&mut context, &player, &data).await;
+
+  process(
+    &mut context,
+    &[instruction::abort_rent(&program_id(), &owner.pubkey(), &player, &rent_book(PRICE))],
+    &[&owner],
+  )
+  .await
+  .unwrap();
+
+  assert!(get_book(&mut context, &rent_book(PRICE)).await.entries.is_empty());
+  assert_eq!(get_player(&mut context, &player).await.status, PlayerStatus::Idle);
+}
+
+#[tokio::test]
+async fn abort_rent_rejects_page_the_listing_is_not_in() {
+  let mut context = start().await;
+  setup_marketplace(&mut context).await;
+  let (owner, player) = rent_listed_player(&mut context, PRICE, 3600).await;
+  let other_page: Pubkey = instruction::find_listing_book_address(&program_id(), ListingKind::Rent, PRICE, 1);
+
+  let result = process(
+    &mut context,
+    &[instruction::abort_rent(&program_id(), &owner.pubkey(), &player, &other_page)],
+    &[&owner],
+  )
+  .await;
+
+  assert_game_error(result, GameError::InvalidListingBook);
+}
+
+#[tokio::test]
+async fn abort_rent_rejects_book_of_other_kind() {
+  let mut context = start().await;
+  setup_marketplace(&mut context).await;
+  let (owner, player) = rent_listed_player(&mut context, PRICE, 3600).await;
+  listed_player(&mut context, PRICE).await;
+
+  let result = process(
+    &mut context,
+    &[instruction::abort_rent(&program_id(), &owner.pubkey(), &player, &sale_book(PRICE))],
+    &[&owner],
+  )
+  .await;
+
+  assert_game_error(result, GameError::InvalidListingBook);
+}
+
+#[tokio::test]
+async fn rent_another_player_removes_listing() {
+  let mut context = start().await;
+  let treasury: Pubkey = setup_marketplace(&mut context).await;
+  let (owner, player) = rent_listed_player(&mut context, PRICE, 3600).await;
+  let renter: Keypair = create_user(&mut context).await;
+
+  rent_player(&mut context, &renter, &player, &owner.pubkey(), &treasury, PRICE, 1).await.unwrap();
+
+  assert!(get_book(&mut context, &rent_book(PRICE)).await.entries.is_empty());
+}
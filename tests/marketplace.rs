@@ -0,0 +1,485 @@
+mod common;
+
+use common::*;
+use sol_battle_balls::{
+  error::GameError,
+  instruction::{self, TokenPayment},
+  state::{Player, PlayerStatus},
+};
+use solana_program::{program_pack::Pack, pubkey::Pubkey, rent::Rent, system_instruction};
+use solana_program_test::{tokio, ProgramTestContext};
+use solana_sdk::signature::{Keypair, Signer};
+
+const PRICE: u64 = 1_000_000_000;
+
+async fn buy(
+  context: &mut ProgramTestContext,
+  buyer: &Keypair,
+  player: &Pubkey,
+  nft: &Pubkey,
+  seller: &Pubkey,
+  treasury: &Pubkey,
+) -> Result<(), solana_program_test::BanksClientError> {
+  buy_at_most(context, buyer, player, nft, seller, treasury, None).await
+}
+
+async fn buy_at_most(
+  context: &mut ProgramTestContext,
+  buyer: &Keypair,
+  player: &Pubkey,
+  nft: &Pubkey,
+  seller: &Pubkey,
+  treasury: &Pubkey,
+  max_price: Option<u64>,
+) -> Result<(), solana_program_test::BanksClientError> {
+  let buyer_ata: Pubkey = create_ata(context, &buyer.pubkey(), nft).await;
+  process(
+    context,
+    &[instruction::buy_player(
+      &program_id(),
+      &buyer.pubkey(),
+      player,
+      nft,
+      seller,
+      &instruction::find_vault_address(&program_id()),
+      &escrow_address(nft),
+      &buyer_ata,
+      treasury,
+      &sale_book(PRICE),
+      max_price,
+      None,
+    )],
+    &[buyer],
+  )
+  .await
+}
+
+async fn rent(
+  context: &mut ProgramTestContext,
+  renter: &Keypair,
+  player: &Pubkey,
+  owner: &Pubkey,
+  treasury: &Pubkey,
+) -> Result<(), solana_program_test::BanksClientError> {
+  rent_player(context, renter, player, owner, treasury, PRICE, 1).await
+}
+
+/// A wallet holding exactly `lamports`.
+async fn wallet_with(context: &mut ProgramTestContext, lamports: u64) -> Keypair {
+  let wallet: Keypair = Keypair::new();
+  let payer: Pubkey = context.payer.pubkey();
+  process(context, &[system_instruction::transfer(&payer, &wallet.pubkey(), lamports)], &[]).await.unwrap();
+  wallet
+}
+
+#[tokio::test]
+async fn sell_player_escrows_nft() {
+  let mut context = start().await;
+  setup_marketplace(&mut context).await;
+  let seller: Keypair = create_user(&mut context).await;
+  let (player, nft, ata) = generate(&mut context, &seller).await;
+
+  list_for_sale(&mut context, &seller, &player, &nft, &ata, PRICE).await.unwrap();
+
+  let data: Player = get_player(&mut context, &player).await;
+  assert_eq!(data.status, PlayerStatus::ListedForSale);
+  assert_eq!(data.sale_required_price, PRICE);
+  assert_eq!(get_token_amount(&mut context, &ata).await, 0);
+  assert_eq!(get_token_amount(&mut context, &escrow_address(&nft)).await, 1);
+}
+
+#[tokio::test]
+async fn sell_player_rejects_non_owner() {
+  let mut context = start().await;
+  setup_marketplace(&mut context).await;
+  let seller: Keypair = create_user(&mut context).await;
+  let other: Keypair = create_user(&mut context).await;
+  let (player, nft, ata) = generate(&mut context, &seller).await;
+
+  let result = list_for_sale(&mut context, &other, &player, &nft, &ata, PRICE).await;
+
+  assert_game_error(result, GameError::NotPlayerOwner);
+}
+
+#[tokio::test]
+async fn abort_sell_returns_nft() {
+  let mut context = start().await;
+  setup_marketplace(&mut context).await;
+  let (seller, player, nft) = listed_player(&mut context, PRICE).await;
+  let ata: Pubkey = spl_associated_token_account::get_associated_token_address(&seller.pubkey(), &nft);
+  let vault: Pubkey = instruction::find_vault_address(&program_id());
+
+  process(
+    &mut context,
+    &[instruction::abort_sell(
+      &program_id(),
+      &seller.pubkey(),
+      &player,
+      &vault,
+      &escrow_address(&nft),
+      &ata,
+      &sale_book(PRICE),
+    )],
+    &[&seller],
+  )
+  .await
+  .unwrap();
+
+  let data: Player = get_player(&mut context, &player).await;
+  assert_eq!(data.status, PlayerStatus::Idle);
+  assert_eq!(get_token_amount(&mut context, &ata).await, 1);
+  assert!(!account_exists(&mut context, &escrow_address(&nft)).await);
+}
+
+#[tokio::test]
+async fn abort_sell_rejects_unlisted_player() {
+  let mut context = start().await;
+  setup_marketplace(&mut context).await;
+  let seller: Keypair = create_user(&mut context).await;
+  let (player, nft, ata) = generate(&mut context, &seller).await;
+  let vault: Pubkey = instruction::find_vault_address(&program_id());
+
+  let result = process(
+    &mut context,
+    &[instruction::abort_sell(
+      &program_id(),
+      &seller.pubkey(),
+      &player,
+      &vault,
+      &escrow_address(&nft),
+      &ata,
+      &sale_book(PRICE),
+    )],
+    &[&seller],
+  )
+  .await;
+
+  assert_game_error(result, GameError::PlayerNotForSale);
+}
+
+#[tokio::test]
+async fn buy_player_splits_price_with_treasury() {
+  let mut context = start().await;
+  let treasury: Pubkey = setup_marketplace(&mut context).await;
+  let (seller, player, nft) = listed_player(&mut context, PRICE).await;
+  let buyer: Keypair = create_user(&mut context).await;
+  let escrow_rent: u64 = Rent::default().minimum_balance(spl_token::state::Account::LEN);
+  let seller_before: u64 = get_lamports(&mut context, &seller.pubkey()).await;
+  let treasury_before: u64 = get_lamports(&mut context, &treasury).await;
+
+  buy(&mut context, &buyer, &player, &nft, &seller.pubkey(), &treasury).await.unwrap();
+
+  let fee: u64 = PRICE * FEE_BASIS_POINTS as u64 / 10_000;
+  assert_eq!(get_lamports(&mut context, &treasury).await - treasury_before, fee);
+  assert_eq!(get_lamports(&mut context, &seller.pubkey()).await - seller_before, PRICE - fee + escrow_rent);
+
+  let data: Player = get_player(&mut context, &player).await;
+  assert_eq!(data.owner, buyer.pubkey().to_bytes());
+  assert_eq!(data.status, PlayerStatus::Idle);
+  let buyer_ata: Pubkey = spl_associated_token_account::get_associated_token_address(&buyer.pubkey(), &nft);
+  assert_eq!(get_token_amount(&mut context, &buyer_ata).await, 1);
+  assert!(!account_exists(&mut context, &escrow_address(&nft)).await);
+}
+
+#[tokio::test]
+async fn buy_player_clears_renter_of_the_seller() {
+  let mut context = start().await;
+  let treasury: Pubkey = setup_marketplace(&mut context).await;
+  let (seller, player, nft) = listed_player(&mut context, PRICE).await;
+  let buyer: Keypair = create_user(&mut context).await;
+  //left behind by a rental the seller ran before listing
+  let mut data: Player = get_player(&mut context, &player).await;
+  data.who_rents = [9; 32];
+  set_player(&mut context, &player, &data).await;
+
+  buy(&mut context, &buyer, &player, &nft, &seller.pubkey(), &treasury).await.unwrap();
+
+  assert_eq!(get_player(&mut context, &player).await.who_rents, [0; 32]);
+}
+
+#[tokio::test]
+async fn buy_player_takes_exact_price_from_buyer_wallet() {
+  let mut context = start().await;
+  let treasury: Pubkey = setup_marketplace(&mut context).await;
+  let (seller, player, nft) = listed_player(&mut context, PRICE).await;
+  let buyer: Keypair = create_user(&mut context).await;
+  let buyer_before: u64 = get_lamports(&mut context, &buyer.pubkey()).await;
+
+  buy_at_most(&mut context, &buyer, &player, &nft, &seller.pubkey(), &treasury, Some(PRICE)).await.unwrap();
+
+  assert_eq!(buyer_before - get_lamports(&mut context, &buyer.pubkey()).await, PRICE);
+}
+
+#[tokio::test]
+async fn buy_player_rejects_price_above_maximum() {
+  let mut context = start().await;
+  let treasury: Pubkey = setup_marketplace(&mut context).await;
+  let (seller, player, nft) = listed_player(&mut context, PRICE).await;
+  let buyer: Keypair = create_user(&mut context).await;
+
+  let result = buy_at_most(&mut context, &buyer, &player, &nft, &seller.pubkey(), &treasury, Some(PRICE - 1)).await;
+
+  assert_game_error(result, GameError::PriceAboveMaximum);
+  assert_eq!(get_player(&mut context, &player).await.status, PlayerStatus::ListedForSale);
+}
+
+#[tokio::test]
+async fn buy_player_rejects_buyer_without_funds() {
+  let mut context = start().await;
+  let treasury: Pubkey = setup_marketplace(&mut context).await;
+  let (seller, player, nft) = listed_player(&mut context, PRICE).await;
+  let buyer: Keypair = wallet_with(&mut context, PRICE - 1).await;
+
+  let result = buy(&mut context, &buyer, &player, &nft, &seller.pubkey(), &treasury).await;
+
+  assert_game_error(result, GameError::InsufficientFunds);
+  assert_eq!(get_lamports(&mut context, &buyer.pubkey()).await, PRICE - 1);
+}
+
+#[tokio::test]
+async fn buy_player_rejects_unlisted_player() {
+  let mut context = start().await;
+  let treasury: Pubkey = setup_marketplace(&mut context).await;
+  let seller: Keypair = create_user(&mut context).await;
+  let (player, nft, _) = generate(&mut context, &seller).await;
+  let buyer: Keypair = create_user(&mut context).await;
+
+  let result = buy(&mut context, &buyer, &player, &nft, &seller.pubkey(), &treasury).await;
+
+  assert_game_error(result, GameError::PlayerNotForSale);
+}
+
+#[tokio::test]
+async fn buy_player_rejects_wrong_treasury() {
+  let mut context = start().await;
+  setup_marketplace(&mut context).await;
+  let (seller, player, nft) = listed_player(&mut context, PRICE).await;
+  let buyer: Keypair = create_user(&mut context).await;
+
+  let result = buy(&mut context, &buyer, &player, &nft, &seller.pubkey(), &buyer.pubkey()).await;
+
+  assert_game_error(result, GameError::TreasuryMismatch);
+}
+
+#[tokio::test]
+async fn buy_player_rejects_wrong_seller() {
+  let mut context = start().await;
+  let treasury: Pubkey = setup_marketplace(&mut context).await;
+  let (_, player, nft) = listed_player(&mut context, PRICE).await;
+  let buyer: Keypair = create_user(&mut context).await;
+
+  let result = buy(&mut context, &buyer, &player, &nft, &buyer.pubkey(), &treasury).await;
+
+  assert_game_error(result, GameError::NotPlayerOwner);
+}
+
+#[tokio::test]
+async fn buy_player_pays_in_spl_token() {
+  let mut context = start().await;
+  let treasury: Pubkey = setup_marketplace(&mut context).await;
+  let seller: Keypair = create_user(&mut context).await;
+  let buyer: Keypair = create_user(&mut context).await;
+  let (player, nft, ata) = generate(&mut context, &seller).await;
+  let mint: Pubkey = create_mint(&mut context, 6).await;
+  let buyer_payment: Pubkey = create_ata(&mut context, &buyer.pubkey(), &mint).await;
+  let seller_payment: Pubkey = create_ata(&mut context, &seller.pubkey(), &mint).await;
+  let treasury_payment: Pubkey = create_ata(&mut context, &treasury, &mint).await;
+  mint_to(&mut context, &mint, &buyer_payment, PRICE).await;
+  let vault: Pubkey = instruction::find_vault_address(&program_id());
+  process(
+    &mut context,
+    &[instruction::sell_player(&program_id(), &seller.pubkey(), &player, &nft, &ata, &vault, PRICE, Some(mint), 0)],
+    &[&seller],
+  )
+  .await
+  .unwrap();
+  let buyer_ata: Pubkey = create_ata(&mut context, &buyer.pubkey(), &nft).await;
+
+  process(
+    &mut context,
+    &[instruction::buy_player(
+      &program_id(),
+      &buyer.pubkey(),
+      &player,
+      &nft,
+      &seller.pubkey(),
+      &vault,
+      &escrow_address(&nft),
+      &buyer_ata,
+      &treasury,
+      &sale_book(PRICE),
+      None,
+      Some(TokenPayment {
+        payer_token_account: buyer_payment,
+        seller_token_account: seller_payment,
+        treasury_token_account: treasury_payment,
+      }),
+    )],
+    &[&buyer],
+  )
+  .await
+  .unwrap();
+
+  let fee: u64 = PRICE * FEE_BASIS_POINTS as u64 / 10_000;
+  assert_eq!(get_token_amount(&mut context, &buyer_payment).await, 0);
+  assert_eq!(get_token_amount(&mut context, &seller_payment).await, PRICE - fee);
+  assert_eq!(get_token_amount(&mut context, &treasury_payment).await, fee);
+  assert_eq!(get_token_amount(&mut context, &buyer_ata).await, 1);
+}
+
+#[tokio::test]
+async fn rent_another_player_marks_player_rented() {
+  let mut context = start().await;
+  let treasury: Pubkey = setup_marketplace(&mut context).await;
+  let (owner, player) = rent_listed_player(&mut context, PRICE, 3600).await;
+  let renter: Keypair = create_user(&mut context).await;
+  let owner_before: u64 = get_lamports(&mut context, &owner.pubkey()).await;
+
+  rent(&mut context, &renter, &player, &owner.pubkey(), &treasury).await.unwrap();
+
+  assert_eq!(get_lamports(&mut context, &owner.pubkey()).await, owner_before);
+  let data: Player = get_player(&mut context, &player).await;
+  assert_eq!(data.status, PlayerStatus::Rented);
+  assert_eq!(data.who_rents, renter.pubkey().to_bytes());
+  assert_eq!(data.rent_end_on, now(&mut context).await as u64 + 3600);
+  assert_eq!(data.owner, owner.pubkey().to_bytes());
+}
+
+#[tokio::test]
+async fn rent_another_player_takes_rent_price_from_renter_wallet() {
+  let mut context = start().await;
+  let treasury: Pubkey = setup_marketplace(&mut context).await;
+  let owner: Keypair = create_user(&mut context).await;
+  let (player, _, _) = generate(&mut context, &owner).await;
+  list_for_rent(&mut context, &owner, &player, PRICE, 3600, 1, None).await.unwrap();
+  let renter: Keypair = create_user(&mut context).await;
+  let renter_before: u64 = get_lamports(&mut context, &renter.pubkey()).await;
+  let treasury_before: u64 = get_lamports(&mut context, &treasury).await;
+
+  rent_player(&mut context, &renter, &player, &owner.pubkey(), &treasury, PRICE, 2).await.unwrap();
+
+  let escrow: Pubkey = instruction::find_rent_escrow_address(&program_id(), &player);
+  let escrow_rent: u64 = Rent::default().minimum_balance(get_account(&mut context, &escrow).await.data.len());
+  let fee: u64 = PRICE * FEE_BASIS_POINTS as u64 / 10_000;
+  assert_eq!(get_lamports(&mut context, &treasury).await - treasury_before, 2 * fee);
+  assert_eq!(get_lamports(&mut context, &escrow).await, escrow_rent + 2 * (PRICE - fee));
+  assert_eq!(renter_before - get_lamports(&mut context, &renter.pubkey()).await, escrow_rent + 2 * PRICE);
+}
+
+#[tokio::test]
+async fn rent_another_player_checks_funds_against_rent_price() {
+  let mut context = start().await;
+  let treasury: Pubkey = setup_marketplace(&mut context).await;
+  let (owner, player) = rent_listed_player(&mut context, PRICE, 3600).await;
+  let renter: Keypair = wallet_with(&mut context, PRICE).await;
+
+  let result = rent_player(&mut context, &renter, &player, &owner.pubkey(), &treasury, PRICE, 1).await;
+
+  assert_game_error(result, GameError::InsufficientFunds);
+  assert_eq!(get_player(&mut context, &player).await.status, PlayerStatus::ListedForRent);
+}
+
+#[tokio::test]
+async fn rent_another_player_rejects_price_above_maximum() {
+  let mut context = start().await;
+  let treasury: Pubkey = setup_marketplace(&mut context).await;
+  let owner: Keypair = create_user(&mut context).await;
+  let (player, _, _) = generate(&mut context, &owner).await;
+  list_for_rent(&mut context, &owner, &player, PRICE, 3600, 1, None).await.unwrap();
+  let renter: Keypair = create_user(&mut context).await;
+  let ix = instruction::rent_another_player(
+    &program_id(),
+    &renter.pubkey(),
+    &player,
+    &owner.pubkey(),
+    &treasury,
+    &rent_book(PRICE),
+    2,
+    Some(2 * PRICE - 1),
+    None,
+  );
+
+  let result = process(&mut context, &[ix], &[&renter]).await;
+
+  assert_game_error(result, GameError::PriceAboveMaximum);
+}
+
+#[tokio::test]
+async fn rent_another_player_rejects_unlisted_player() {
+  let mut context = start().await;
+  let treasury: Pubkey = setup_marketplace(&mut context).await;
+  let owner: Keypair = create_user(&mut context).await;
+  let (player, _, _) = generate(&mut context, &owner).await;
+  let renter: Keypair = create_user(&mut context).await;
+
+  let result = rent(&mut context, &renter, &player, &owner.pubkey(), &treasury).await;
+
+  assert_game_error(result, GameError::PlayerNotForRent);
+}
+
+#[tokio::test]
+async fn claim_rent_over_player_waits_for_rent_end() {
+  let mut context = start().await;
+  let treasury: Pubkey = setup_marketplace(&mut context).await;
+  let (owner, player) = rent_listed_player(&mut context, PRICE, 3600).await;
+  let renter: Keypair = create_user(&mut context).await;
+  rent(&mut context, &renter, &player, &owner.pubkey(), &treasury).await.unwrap();
+  let claim = instruction::claim_rent_over_player(&program_id(), &owner.pubkey(), &player, &renter.pubkey(), None);
+
+  let result = process(&mut context, std::slice::from_ref(&claim), &[&owner]).await;
+  assert_game_error(result, GameError::RentNotOver);
+
+  let timestamp: i64 = now(&mut context).await;
+  set_clock(&mut context, timestamp + 3600).await;
+  process(&mut context, &[claim], &[&owner]).await.unwrap();
+
+  let data: Player = get_player(&mut context, &player).await;
+  assert_eq!(data.status, PlayerStatus::Idle);
+  assert_eq!(data.who_rents, [0; 32]);
+}
+
+#[tokio::test]
+async fn abort_rent_removes_listing() {
+  let mut context = start().await;
+  setup_marketplace(&mut context).await;
+  let (owner, player) = rent_listed_player(&mut context, PRICE, 3600).await;
+
+  let abort = instruction::abort_rent(&program_id(), &owner.pubkey(), &player, &rent_book(PRICE));
+
+  process(&mut context, &[abort], &[&owner]).await.unwrap();
+
+  let data: Player = get_player(&mut context, &player).await;
+  assert_eq!(data.status, PlayerStatus::Idle);
+  assert_eq!(data.rent_required_price, 0);
+}
+
+#[tokio::test]
+async fn abort_rent_rejects_rented_player() {
+  let mut context = start().await;
+  let treasury: Pubkey = setup_marketplace(&mut context).await;
+  let (owner, player) = rent_listed_player(&mut context, PRICE, 3600).await;
+  let renter: Keypair = create_user(&mut context).await;
+  rent(&mut context, &renter, &player, &owner.pubkey(), &treasury).await.unwrap();
+  let abort = instruction::abort_rent(&program_id(), &owner.pubkey(), &player, &rent_book(PRICE));
+
+  let result = process(&mut context, &[abort], &[&owner]).await;
+
+  assert_game_error(result, GameError::PlayerRented);
+}
+
+#[tokio::test]
+async fn rent_your_player_rejects_non_owner() {
+  let mut context = start().await;
+  let owner: Keypair = create_user(&mut context).await;
+  let other: Keypair = create_user(&mut context).await;
+  let (player, _, _) = generate(&mut context, &owner).await;
+
+  let result = process(
+    &mut context,
+    &[instruction::rent_your_player(&program_id(), &other.pubkey(), &player, PRICE, 3600, 0, None, 0)],
+    &[&other],
+  )
+  .await;
+
+  assert_game_error(result, GameError::NotPlayerOwner);
+}
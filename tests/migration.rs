@@ -0,0 +1,331 @@
+mod common;
+
+use borsh::BorshSerialize;
+use common::*;
+use sol_battle_balls::{
+  error::GameError,
+  instruction,
+  state::{
+    AccountType, Formation, Player, PlayerStatus, PlayerV0, PlayerV1, PlayerV2, PlayerV3, ProgramAccount, TeamStatus,
+    UserAccount, UserAccountV0, UserAccountV1, UserAccountV2, UserAccountV3, ACCOUNT_HEADER_LEN, INITIAL_RATING, TEAM_SIZE,
+    UNINDEXED_LISTING,
+  },
+};
+use solana_program::{pubkey::Pubkey, rent::Rent};
+use solana_program_test::{tokio, BanksClientError, ProgramTestContext};
+use solana_sdk::signature::{Keypair, Signer};
+
+fn user_account_v0() -> UserAccountV0 {
+  UserAccountV0 {
+    user_address: [1; 32],
+    user_name: "strikerXXX".to_string(),
+    user_name_length: 7,
+    team_is_ready: 1,
+    team_power: 321,
+    team_class: "XX".to_string(),
+    defence_1: [2; 32],
+    defence_2: [3; 32],
+    middle: [4; 32],
+    offence_1: [5; 32],
+    offence_2: [6; 32],
+    experienced: 9,
+  }
+}
+
+fn player_v0() -> PlayerV0 {
+  PlayerV0 {
+    nft_adress: [1; 32],
+    owner: [2; 32],
+    for_sale: "aa".to_string(),
+    sale_required_price: 0,
+    for_rent: "XX".to_string(),
+    rent_required_price: 5,
+    who_rents: [0; 32],
+    rented: 0,
+    rented_for_time: 60,
+    rent_end_on: 0,
+    offence: 40,
+    defence: 50,
+  }
+}
+
+async fn migrate(
+  context: &mut ProgramTestContext,
+  account: &Pubkey,
+  account_type: AccountType,
+) -> Result<(), BanksClientError> {
+  let payer: Pubkey = context.payer.pubkey();
+  process(context, &[instruction::migrate_account(&program_id(), &payer, account, account_type)], &[]).await
+}
+
+#[tokio::test]
+async fn migrate_user_account_v0_adds_header_and_stats() {
+  let mut context = start().await;
+  let account: Pubkey = set_program_account(&mut context, user_account_v0().try_to_vec().unwrap()).await;
+
+  migrate(&mut context, &account, AccountType::UserAccount).await.unwrap();
+
+  let data: UserAccount = get_user(&mut context, &account).await;
+  assert_eq!(data.user_address, [1; 32]);
+  assert_eq!(data.user_name, "strikerXXX");
+  assert_eq!(data.team_status, TeamStatus::NoTeam);
+  assert_eq!(data.team_power, 0);
+  assert_eq!(data.formation, Formation::TwoOneTwo);
+  assert_eq!(data.team, [[0; 32]; TEAM_SIZE]);
+  assert_eq!(data.experienced, 9);
+  assert_eq!(data.wins, 0);
+  assert_eq!(data.rating, INITIAL_RATING);
+
+  let raw = get_account(&mut context, &account).await;
+  assert_eq!(raw.data.len(), data.packed_len().unwrap());
+  assert!(raw.lamports >= Rent::default().minimum_balance(raw.data.len()));
+}
+
+#[tokio::test]
+async fn migrate_user_account_v1_keeps_stats() {
+  let mut context = start().await;
+  let mut v1: UserAccountV1 = user_account_v0().into();
+  v1.wins = 4;
+  v1.losses = 2;
+  v1.streak = -1;
+  v1.rating = 1234;
+  let account: Pubkey = set_program_account(&mut context, v1.try_to_vec().unwrap()).await;
+
+  migrate(&mut context, &account, AccountType::UserAccount).await.unwrap();
+
+  let data: UserAccount = get_user(&mut context, &account).await;
+  assert_eq!(data.wins, 4);
+  assert_eq!(data.losses, 2);
+  assert_eq!(data.streak, -1);
+  assert_eq!(data.rating, 1234);
+}
+
+#[tokio::test]
+async fn migrate_player_v0_adds_lamport_payment_mint() {
+  let mut context = start().await;
+  let account: Pubkey = set_program_account(&mut context, player_v0().try_to_vec().unwrap()).await;
+
+  migrate(&mut context, &account, AccountType::Player).await.unwrap();
+
+  let data: Player = get_player(&mut context, &account).await;
+  assert_eq!(data.owner, [2; 32]);
+  assert_eq!(data.status, PlayerStatus::ListedForRent);
+  assert_eq!(data.rent_required_price, 5);
+  assert_eq!(data.offence, 40);
+  assert_eq!(data.defence, 50);
+  assert_eq!(data.payment_mint, [0; 32]);
+}
+
+#[tokio::test]
+async fn migrate_headerless_player_with_payment_mint() {
+  let mut context = start().await;
+  let mut player: PlayerV1 = player_v0().into();
+  player.payment_mint = [7; 32];
+  let account: Pubkey = set_program_account(&mut context, player.try_to_vec().unwrap()).await;
+
+  migrate(&mut context, &account, AccountType::Player).await.unwrap();
+
+  let data: Player = get_player(&mut context, &account).await;
+  assert_eq!(data.payment_mint, [7; 32]);
+  assert_eq!(data.offence, 40);
+}
+
+#[tokio::test]
+async fn migrate_player_v1_adds_max_renewals() {
+  let mut context = start().await;
+  let mut player: PlayerV1 = player_v0().into();
+  player.payment_mint = [7; 32];
+  let mut raw: Vec<u8> = Player::DISCRIMINATOR.to_vec();
+  raw.push(1);
+  raw.extend(player.try_to_vec().unwrap());
+  let account: Pubkey = set_program_account(&mut context, raw.clone()).await;
+
+  migrate(&mut context, &account, AccountType::Player).await.unwrap();
+
+  let after = get_account(&mut context, &account).await;
+  assert_eq!(after.data[ACCOUNT_HEADER_LEN - 1], Player::VERSION);
+  let data: Player = Player::unpack(&after.data).unwrap();
+  assert!(after.data.len() >= data.packed_len().unwrap());
+  assert_eq!(data.payment_mint, [7; 32]);
+  assert_eq!(data.rented_for_time, 60);
+  assert_eq!(data.max_renewals, 0);
+}
+
+fn with_header(discriminator: &[u8], version: u8, body: Vec<u8>) -> Vec<u8> {
+  let mut raw: Vec<u8> = discriminator.to_vec();
+  raw.push(version);
+  raw.extend(body);
+  raw
+}
+
+#[tokio::test]
+async fn migrate_player_v2_maps_flags_to_status() {
+  let cases: [(&str, &str, u8, PlayerStatus); 5] = [
+    ("aa", "aa", 0, PlayerStatus::Idle),
+    ("XX", "aa", 0, PlayerStatus::ListedForSale),
+    ("AU", "aa", 0, PlayerStatus::InAuction),
+    ("aa", "XX", 0, PlayerStatus::ListedForRent),
+    ("aa", "aa", 1, PlayerStatus::Rented),
+  ];
+  let mut context = start().await;
+  for (for_sale, for_rent, rented, status) in cases {
+    let mut player: PlayerV2 = PlayerV1::from(player_v0()).into();
+    player.for_sale = for_sale.to_string();
+    player.for_rent = for_rent.to_string();
+    player.rented = rented;
+    player.max_renewals = 3;
+    let raw: Vec<u8> = with_header(&Player::DISCRIMINATOR, 2, player.try_to_vec().unwrap());
+    let account: Pubkey = set_program_account(&mut context, raw).await;
+
+    migrate(&mut context, &account, AccountType::Player).await.unwrap();
+
+    let data: Player = get_player(&mut context, &account).await;
+    assert_eq!(data.status, status);
+    assert_eq!(data.max_renewals, 3);
+    assert_eq!(data.rent_required_price, 5);
+  }
+}
+
+#[tokio::test]
+async fn migrate_player_v3_marks_listing_unindexed() {
+  let mut context = start().await;
+  let mut player: PlayerV3 = PlayerV2::from(PlayerV1::from(player_v0())).into();
+  player.max_renewals = 2;
+  let raw: Vec<u8> = with_header(&Player::DISCRIMINATOR, 3, player.try_to_vec().unwrap());
+  let account: Pubkey = set_program_account(&mut context, raw).await;
+
+  migrate(&mut context, &account, AccountType::Player).await.unwrap();
+
+  let data: Player = get_player(&mut context, &account).await;
+  assert_eq!(data.status, PlayerStatus::ListedForRent);
+  assert_eq!(data.listing_page, UNINDEXED_LISTING);
+  assert_eq!(data.max_renewals, 2);
+  assert_eq!(data.rent_required_price, 5);
+}
+
+#[tokio::test]
+async fn migrated_listing_in_no_page_can_be_aborted() {
+  let mut context = start().await;
+  let owner: Keypair = create_user(&mut context).await;
+  let mut player: PlayerV2 = PlayerV1::from(player_v0()).into();
+  player.owner = owner.pubkey().to_bytes();
+  let raw: Vec<u8> = with_header(&Player::DISCRIMINATOR, 2, player.try_to_vec().unwrap());
+  let account: Pubkey = set_program_account(&mut context, raw).await;
+  migrate(&mut context, &account, AccountType::Player).await.unwrap();
+
+  process(
+    &mut context,
+    &[instruction::abort_rent(&program_id(), &owner.pubkey(), &account, &rent_book(5))],
+    &[&owner],
+  )
+  .await
+  .unwrap();
+
+  let data: Player = get_player(&mut context, &account).await;
+  assert_eq!(data.status, PlayerStatus::Idle);
+  assert!(!account_exists(&mut context, &rent_book(5)).await);
+}
+
+#[tokio::test]
+async fn migrate_user_account_v2_drops_unmarked_team() {
+  let mut context = start().await;
+  let mut user: UserAccountV2 = UserAccountV1::from(user_account_v0()).into();
+  user.team_is_ready = 1;
+  user.wins = 3;
+  let raw: Vec<u8> = with_header(&UserAccount::DISCRIMINATOR, 2, user.try_to_vec().unwrap());
+  let account: Pubkey = set_program_account(&mut context, raw).await;
+
+  migrate(&mut context, &account, AccountType::UserAccount).await.unwrap();
+
+  let data: UserAccount = get_user(&mut context, &account).await;
+  assert_eq!(data.team_status, TeamStatus::NoTeam);
+  assert_eq!(data.team_power, 0);
+  assert_eq!(data.team, [[0; 32]; TEAM_SIZE]);
+  assert_eq!(data.wins, 3);
+}
+
+#[tokio::test]
+async fn migrate_user_account_v3_keeps_slots_as_two_one_two() {
+  let mut context = start().await;
+  let mut user: UserAccountV3 = UserAccountV2::from(UserAccountV1::from(user_account_v0())).into();
+  user.team_status = TeamStatus::Ready;
+  user.team_power = 321;
+  user.defence_1 = [2; 32];
+  user.defence_2 = [3; 32];
+  user.middle = [7; 32];
+  user.offence_1 = [5; 32];
+  user.offence_2 = [6; 32];
+  user.rating = 1300;
+  let raw: Vec<u8> = with_header(&UserAccount::DISCRIMINATOR, 3, user.try_to_vec().unwrap());
+  let account: Pubkey = set_program_account(&mut context, raw).await;
+
+  migrate(&mut context, &account, AccountType::UserAccount).await.unwrap();
+
+  let data: UserAccount = get_user(&mut context, &account).await;
+  assert_eq!(data.team_status, TeamStatus::Ready);
+  assert_eq!(data.team_power, 321);
+  assert_eq!(data.formation, Formation::TwoOneTwo);
+  assert_eq!(data.team, [[2; 32], [3; 32], [7; 32], [5; 32], [6; 32]]);
+  assert_eq!(data.rating, 1300);
+}
+
+#[tokio::test]
+async fn migrate_current_layout_is_a_no_op() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+  let user_account: Pubkey = register(&mut context, &user, "alice").await;
+  let before = get_account(&mut context, &user_account).await;
+
+  migrate(&mut context, &user_account, AccountType::UserAccount).await.unwrap();
+
+  let after = get_account(&mut context, &user_account).await;
+  assert_eq!(after.data, before.data);
+  assert_eq!(after.lamports, before.lamports);
+}
+
+#[tokio::test]
+async fn migrate_rejects_wrong_account_type() {
+  let mut context = start().await;
+  let account: Pubkey = set_program_account(&mut context, player_v0().try_to_vec().unwrap()).await;
+
+  let result = migrate(&mut context, &account, AccountType::UserAccount).await;
+
+  assert_game_error(result, GameError::InvalidAccountType);
+}
+
+#[tokio::test]
+async fn migrate_rejects_account_of_other_program() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+
+  let result = migrate(&mut context, &user.pubkey(), AccountType::UserAccount).await;
+
+  assert_game_error(result, GameError::IncorrectProgramOwner);
+}
+
+#[tokio::test]
+async fn migrated_user_account_can_challenge() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+  let user_account: Pubkey = register(&mut context, &user, "alice").await;
+  let team: [Pubkey; TEAM_SIZE] = field_team(&mut context, &user, &user_account).await;
+  let opponent: Keypair = create_user(&mut context).await;
+  let mut legacy: UserAccountV0 = user_account_v0();
+  legacy.user_address = opponent.pubkey().to_bytes();
+  let opponent_account: Pubkey = set_program_account(&mut context, legacy.try_to_vec().unwrap()).await;
+
+  migrate(&mut context, &opponent_account, AccountType::UserAccount).await.unwrap();
+  let opponent_team: [Pubkey; TEAM_SIZE] = field_team(&mut context, &opponent, &opponent_account).await;
+  let secret: [u8; 32] = solana_program::hash::Hash::new_unique().to_bytes();
+  commit_challenge(&mut context, &user, &user_account, &opponent_account, secret).await;
+  process(
+    &mut context,
+    &[instruction::challenge(&program_id(), &user.pubkey(), &user_account, &opponent_account, &team, &opponent_team, 1, secret)],
+    &[&user],
+  )
+  .await
+  .unwrap();
+
+  let data: UserAccount = get_user(&mut context, &opponent_account).await;
+  assert_eq!(data.wins + data.losses + data.draws, 1);
+}
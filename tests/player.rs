@@ -0,0 +1,332 @@
+mod common;
+
+use common::*;
+use sol_battle_balls::{
+  error::GameError,
+  instruction,
+  state::{Player, PlayerStatus, ProgramAccount, RandomnessCommit, COMMIT_BOND, MAX_COMMIT_AGE},
+};
+use solana_program::{hash::Hash, pubkey::Pubkey, rent::Rent, slot_hashes::SlotHashes};
+use solana_program_test::tokio;
+use solana_sdk::signature::{Keypair, Signer};
+
+#[tokio::test]
+async fn generate_player_writes_stats_for_nft_holder() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+
+  let (player, nft, _) = generate(&mut context, &user).await;
+
+  let data: Player = get_player(&mut context, &player).await;
+  assert_eq!(data.owner, user.pubkey().to_bytes());
+  assert_eq!(data.nft_adress, nft.to_bytes());
+  assert_eq!(data.status, PlayerStatus::Idle);
+  assert!(data.offence > 0);
+  assert!(data.defence > 0);
+}
+
+#[tokio::test]
+async fn generate_player_creates_rent_exempt_pda() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+
+  let (player, nft, _) = generate(&mut context, &user).await;
+
+  assert_eq!(player, instruction::find_player_address(&program_id(), &nft));
+  let account = get_account(&mut context, &player).await;
+  let data: Player = Player::unpack(&account.data).unwrap();
+  assert_eq!(account.owner, program_id());
+  assert_eq!(account.data.len(), data.packed_len().unwrap());
+  assert_eq!(account.lamports, Rent::default().minimum_balance(account.data.len()));
+}
+
+#[tokio::test]
+async fn generate_player_allows_one_player_per_nft() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+  let (_, nft, ata) = generate(&mut context, &user).await;
+  let secret: [u8; 32] = Hash::new_unique().to_bytes();
+  commit(&mut context, &user, &nft, secret).await;
+
+  let result = process(
+    &mut context,
+    &[instruction::generate_player(&program_id(), &user.pubkey(), &ata, &nft, secret)],
+    &[&user],
+  )
+  .await;
+
+  assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn generate_player_rejects_address_not_derived_from_nft() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+  let (nft, ata) = mint_nft(&mut context, &user.pubkey()).await;
+  let secret: [u8; 32] = Hash::new_unique().to_bytes();
+  commit(&mut context, &user, &nft, secret).await;
+  let mut generate = instruction::generate_player(&program_id(), &user.pubkey(), &ata, &nft, secret);
+  generate.accounts[2].pubkey = Pubkey::new_unique();
+
+  let result = process(&mut context, &[generate], &[&user]).await;
+
+  assert_game_error(result, GameError::InvalidPlayerAccount);
+}
+
+#[tokio::test]
+async fn generate_player_closes_commit() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+
+  let (_, nft, _) = generate(&mut context, &user).await;
+
+  let commit_address: Pubkey = instruction::find_commit_address(&program_id(), &user.pubkey(), &nft);
+  assert!(!account_exists(&mut context, &commit_address).await);
+}
+
+#[tokio::test]
+async fn generate_player_requires_commit() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+  let (nft, ata) = mint_nft(&mut context, &user.pubkey()).await;
+
+  let result = process(
+    &mut context,
+    &[instruction::generate_player(&program_id(), &user.pubkey(), &ata, &nft, [1; 32])],
+    &[&user],
+  )
+  .await;
+
+  assert_game_error(result, GameError::InvalidCommit);
+}
+
+#[tokio::test]
+async fn generate_player_rejects_wrong_secret() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+  let (nft, ata) = mint_nft(&mut context, &user.pubkey()).await;
+  commit(&mut context, &user, &nft, [1; 32]).await;
+
+  let result = process(
+    &mut context,
+    &[instruction::generate_player(&program_id(), &user.pubkey(), &ata, &nft, [2; 32])],
+    &[&user],
+  )
+  .await;
+
+  assert_game_error(result, GameError::CommitmentMismatch);
+}
+
+#[tokio::test]
+async fn generate_player_rejects_reveal_in_commit_slot() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+  let (nft, ata) = mint_nft(&mut context, &user.pubkey()).await;
+  let secret: [u8; 32] = [1; 32];
+
+  let result = process(
+    &mut context,
+    &[
+      instruction::commit_randomness(
+        &program_id(),
+        &user.pubkey(),
+        &nft,
+        sol_battle_balls::randomness::commitment(&secret),
+      ),
+      instruction::generate_player(&program_id(), &user.pubkey(), &ata, &nft, secret),
+    ],
+    &[&user],
+  )
+  .await;
+
+  assert_game_error(result, GameError::RevealTooEarly);
+}
+
+#[tokio::test]
+async fn generate_player_rejects_expired_commit() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+  let (nft, ata) = mint_nft(&mut context, &user.pubkey()).await;
+  let secret: [u8; 32] = Hash::new_unique().to_bytes();
+  commit(&mut context, &user, &nft, secret).await;
+  let commit_address: Pubkey = instruction::find_commit_address(&program_id(), &user.pubkey(), &nft);
+  let commit_data: RandomnessCommit =
+    RandomnessCommit::unpack(&get_account(&mut context, &commit_address).await.data).unwrap();
+  let late_slot: u64 = commit_data.commit_slot + MAX_COMMIT_AGE + 2;
+  context.warp_to_slot(late_slot).unwrap();
+  context.set_sysvar(&SlotHashes::new(&[
+    (late_slot - 1, Hash::new_unique()),
+    (late_slot - 2, Hash::new_unique()),
+  ]));
+
+  let result = process(
+    &mut context,
+    &[instruction::generate_player(&program_id(), &user.pubkey(), &ata, &nft, secret)],
+    &[&user],
+  )
+  .await;
+
+  assert_game_error(result, GameError::CommitExpired);
+}
+
+#[tokio::test]
+async fn commit_randomness_holds_bond() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+  let (nft, _) = mint_nft(&mut context, &user.pubkey()).await;
+
+  commit(&mut context, &user, &nft, [1; 32]).await;
+
+  let commit_address: Pubkey = instruction::find_commit_address(&program_id(), &user.pubkey(), &nft);
+  let account = get_account(&mut context, &commit_address).await;
+  assert_eq!(account.lamports, Rent::default().minimum_balance(account.data.len()) + COMMIT_BOND);
+}
+
+#[tokio::test]
+async fn cancel_commit_forfeits_bond_and_allows_new_commit() {
+  let mut context = start().await;
+  let treasury: Pubkey = setup_marketplace(&mut context).await;
+  let user: Keypair = create_user(&mut context).await;
+  let (nft, ata) = mint_nft(&mut context, &user.pubkey()).await;
+  commit(&mut context, &user, &nft, [1; 32]).await;
+  let treasury_before: u64 = get_lamports(&mut context, &treasury).await;
+
+  process(
+    &mut context,
+    &[instruction::cancel_commit(&program_id(), &user.pubkey(), &nft, &treasury)],
+    &[&user],
+  )
+  .await
+  .unwrap();
+
+  let commit_address: Pubkey = instruction::find_commit_address(&program_id(), &user.pubkey(), &nft);
+  assert!(!account_exists(&mut context, &commit_address).await);
+  assert_eq!(get_lamports(&mut context, &treasury).await, treasury_before + COMMIT_BOND);
+
+  let secret: [u8; 32] = Hash::new_unique().to_bytes();
+  commit(&mut context, &user, &nft, secret).await;
+  process(
+    &mut context,
+    &[instruction::generate_player(&program_id(), &user.pubkey(), &ata, &nft, secret)],
+    &[&user],
+  )
+  .await
+  .unwrap();
+}
+
+#[tokio::test]
+async fn cancel_commit_rejects_other_user() {
+  let mut context = start().await;
+  let treasury: Pubkey = setup_marketplace(&mut context).await;
+  let user: Keypair = create_user(&mut context).await;
+  let other: Keypair = create_user(&mut context).await;
+  let (nft, _) = mint_nft(&mut context, &user.pubkey()).await;
+  commit(&mut context, &user, &nft, [1; 32]).await;
+
+  let mut ix = instruction::cancel_commit(&program_id(), &other.pubkey(), &nft, &treasury);
+  ix.accounts[1].pubkey = instruction::find_commit_address(&program_id(), &user.pubkey(), &nft);
+  let result = process(&mut context, &[ix], &[&other]).await;
+
+  assert_game_error(result, GameError::InvalidCommit);
+}
+
+#[tokio::test]
+async fn generate_player_rejects_user_without_nft() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+  let other: Keypair = create_user(&mut context).await;
+  let (nft, _) = mint_nft(&mut context, &other.pubkey()).await;
+  let ata: Pubkey = create_ata(&mut context, &user.pubkey(), &nft).await;
+  let secret: [u8; 32] = Hash::new_unique().to_bytes();
+  commit(&mut context, &user, &nft, secret).await;
+
+  let result = process(
+    &mut context,
+    &[instruction::generate_player(&program_id(), &user.pubkey(), &ata, &nft, secret)],
+    &[&user],
+  )
+  .await;
+
+  assert_game_error(result, GameError::NftNotHeld);
+}
+
+#[tokio::test]
+async fn generate_player_rejects_token_account_of_other_user() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+  let other: Keypair = create_user(&mut context).await;
+  let (nft, other_ata) = mint_nft(&mut context, &other.pubkey()).await;
+  let secret: [u8; 32] = Hash::new_unique().to_bytes();
+  commit(&mut context, &user, &nft, secret).await;
+
+  let result = process(
+    &mut context,
+    &[instruction::generate_player(&program_id(), &user.pubkey(), &other_ata, &nft, secret)],
+    &[&user],
+  )
+  .await;
+
+  assert_game_error(result, GameError::TokenAccountOwnerMismatch);
+}
+
+#[tokio::test]
+async fn claim_nft_player_moves_player_to_new_holder() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+  let holder: Keypair = create_user(&mut context).await;
+  let (player, nft, ata) = generate(&mut context, &user).await;
+  let holder_ata: Pubkey = create_ata(&mut context, &holder.pubkey(), &nft).await;
+  process(
+    &mut context,
+    &[spl_token::instruction::transfer(&spl_token::id(), &ata, &holder_ata, &user.pubkey(), &[], 1).unwrap()],
+    &[&user],
+  )
+  .await
+  .unwrap();
+
+  process(
+    &mut context,
+    &[instruction::claim_nft_player(&program_id(), &holder.pubkey(), &holder_ata, &player, &nft)],
+    &[&holder],
+  )
+  .await
+  .unwrap();
+
+  let data: Player = get_player(&mut context, &player).await;
+  assert_eq!(data.owner, holder.pubkey().to_bytes());
+}
+
+#[tokio::test]
+async fn claim_nft_player_rejects_other_mint() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+  let (player, _, _) = generate(&mut context, &user).await;
+  let (other_nft, other_ata) = mint_nft(&mut context, &user.pubkey()).await;
+
+  let result = process(
+    &mut context,
+    &[instruction::claim_nft_player(&program_id(), &user.pubkey(), &other_ata, &player, &other_nft)],
+    &[&user],
+  )
+  .await;
+
+  assert_game_error(result, GameError::NftMintMismatch);
+}
+
+#[tokio::test]
+async fn claim_nft_player_rejects_user_without_nft() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+  let other: Keypair = create_user(&mut context).await;
+  let (player, nft, _) = generate(&mut context, &user).await;
+  let other_ata: Pubkey = create_ata(&mut context, &other.pubkey(), &nft).await;
+
+  let result = process(
+    &mut context,
+    &[instruction::claim_nft_player(&program_id(), &other.pubkey(), &other_ata, &player, &nft)],
+    &[&other],
+  )
+  .await;
+
+  assert_game_error(result, GameError::NftNotHeld);
+}
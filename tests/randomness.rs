@@ -0,0 +1,70 @@
+use sol_battle_balls::randomness::{commitment, mix, slot_hash_after};
+use solana_program::pubkey::Pubkey;
+
+//raw SlotHashes sysvar data, entries newest first
+fn slot_hashes_data(slots: &[u64]) -> Vec<u8> {
+  let mut data: Vec<u8> = (slots.len() as u64).to_le_bytes().to_vec();
+  for slot in slots {
+    data.extend_from_slice(&slot.to_le_bytes());
+    data.extend_from_slice(&[*slot as u8; 32]);
+  }
+  data
+}
+
+#[test]
+fn commitment_only_matches_its_secret() {
+  let secret: [u8; 32] = [7; 32];
+
+  assert_eq!(commitment(&secret), commitment(&secret));
+  assert_ne!(commitment(&secret), commitment(&[8; 32]));
+  assert_ne!(commitment(&secret), secret);
+}
+
+#[test]
+fn no_slot_hash_exists_at_commit_time() {
+  //at commit time the sysvar only holds the commit slot and earlier ones
+  let data: Vec<u8> = slot_hashes_data(&[100, 99, 98]);
+
+  assert_eq!(slot_hash_after(&data, 100), None);
+}
+
+#[test]
+fn first_slot_after_commit_is_used() {
+  let data: Vec<u8> = slot_hashes_data(&[104, 103, 101, 100, 99]);
+
+  assert_eq!(slot_hash_after(&data, 100), Some((101, [101; 32])));
+  assert_eq!(slot_hash_after(&data, 101), Some((103, [103; 32])));
+}
+
+#[test]
+fn commit_older_than_history_is_rejected() {
+  //slot 101 may have been dropped, so no entry is trusted
+  let data: Vec<u8> = slot_hashes_data(&[110, 109, 108]);
+
+  assert_eq!(slot_hash_after(&data, 100), None);
+}
+
+#[test]
+fn outcome_depends_on_future_slot_hash() {
+  let secret: [u8; 32] = [7; 32];
+  let user: Pubkey = Pubkey::new_unique();
+  let target: Pubkey = Pubkey::new_unique();
+
+  //same commit, different slot hashes after it
+  let first = mix(&secret, &[1; 32], &user, &target);
+  let second = mix(&secret, &[2; 32], &user, &target);
+
+  assert_ne!(first, second);
+}
+
+#[test]
+fn outcome_is_bound_to_target() {
+  let secret: [u8; 32] = [7; 32];
+  let slot_hash: [u8; 32] = [1; 32];
+  let user: Pubkey = Pubkey::new_unique();
+
+  let first = mix(&secret, &slot_hash, &user, &Pubkey::new_unique());
+  let second = mix(&secret, &slot_hash, &user, &Pubkey::new_unique());
+
+  assert_ne!(first, second);
+}
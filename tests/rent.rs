@@ -0,0 +1,469 @@
+mod common;
+
+use common::*;
+use sol_battle_balls::{
+  error::GameError,
+  instruction::{self, RentPayout, RentTokenPayment},
+  state::{Player, PlayerStatus, ProgramAccount, RentEscrow},
+};
+use solana_program::{pubkey::Pubkey, rent::Rent};
+use solana_program_test::{tokio, BanksClientError, ProgramTestContext};
+use solana_sdk::signature::{Keypair, Signer};
+
+const PRICE: u64 = 1_000_000_000;
+const DURATION: u64 = 1_000;
+const START: i64 = 1_700_000_000;
+
+/// Owner's share of `PRICE` once the marketplace fee is taken.
+fn escrowed() -> u64 {
+  PRICE - PRICE * FEE_BASIS_POINTS as u64 / 10_000
+}
+
+struct Rental {
+  owner: Keypair,
+  renter: Keypair,
+  player: Pubkey,
+  escrow: Pubkey,
+}
+
+/// Lists a player for `DURATION` seconds and rents it out at `START`.
+async fn rented_player(context: &mut ProgramTestContext) -> Rental {
+  subscribed_player(context, 0, 1).await
+}
+
+/// Lists a player for periods of `DURATION` seconds with `max_renewals`
+/// and rents it at `START`, prepaying `periods` periods.
+async fn subscribed_player(context: &mut ProgramTestContext, max_renewals: u16, periods: u16) -> Rental {
+  let treasury: Pubkey = setup_marketplace(context).await;
+  let owner: Keypair = create_user(context).await;
+  let renter: Keypair = create_user(context).await;
+  let (player, _, _) = generate(context, &owner).await;
+  list_for_rent(context, &owner, &player, PRICE, DURATION, max_renewals, None).await.unwrap();
+  set_clock(context, START).await;
+  rent_player(context, &renter, &player, &owner.pubkey(), &treasury, PRICE, periods).await.unwrap();
+  let escrow: Pubkey = instruction::find_rent_escrow_address(&program_id(), &player);
+  Rental { owner, renter, player, escrow }
+}
+
+async fn get_escrow(context: &mut ProgramTestContext, key: &Pubkey) -> RentEscrow {
+  RentEscrow::unpack(&get_account(context, key).await.data).unwrap()
+}
+
+async fn release(context: &mut ProgramTestContext, rental: &Rental) {
+  process(
+    context,
+    &[instruction::release_rent(&program_id(), &rental.player, &rental.owner.pubkey(), None)],
+    &[],
+  )
+  .await
+  .unwrap();
+}
+
+async fn renew(context: &mut ProgramTestContext, rental: &Rental) -> Result<(), BanksClientError> {
+  process(
+    context,
+    &[instruction::renew_rent(
+      &program_id(),
+      &rental.player,
+      &rental.owner.pubkey(),
+      &rental.renter.pubkey(),
+      None,
+    )],
+    &[],
+  )
+  .await
+}
+
+async fn terminate(context: &mut ProgramTestContext, rental: &Rental, signer: &Keypair) -> Result<(), BanksClientError> {
+  process(
+    context,
+    &[instruction::terminate_rent(
+      &program_id(),
+      &signer.pubkey(),
+      &rental.player,
+      &rental.owner.pubkey(),
+      &rental.renter.pubkey(),
+      None,
+    )],
+    &[signer],
+  )
+  .await
+}
+
+#[tokio::test]
+async fn rent_another_player_escrows_owner_share() {
+  let mut context = start().await;
+
+  let rental: Rental = rented_player(&mut context).await;
+
+  let account = get_account(&mut context, &rental.escrow).await;
+  assert_eq!(account.lamports, Rent::default().minimum_balance(account.data.len()) + escrowed());
+  let escrow: RentEscrow = RentEscrow::unpack(&account.data).unwrap();
+  assert_eq!(escrow.owner, rental.owner.pubkey().to_bytes());
+  assert_eq!(escrow.renter, rental.renter.pubkey().to_bytes());
+  assert_eq!(escrow.amount, escrowed());
+  assert_eq!(escrow.released, 0);
+  assert_eq!(escrow.start_time, START as u64);
+  assert_eq!(escrow.end_time, START as u64 + DURATION);
+}
+
+#[tokio::test]
+async fn release_rent_pays_owner_linearly() {
+  let mut context = start().await;
+  let rental: Rental = rented_player(&mut context).await;
+  let owner_before: u64 = get_lamports(&mut context, &rental.owner.pubkey()).await;
+
+  set_clock(&mut context, START + 250).await;
+  release(&mut context, &rental).await;
+  assert_eq!(get_lamports(&mut context, &rental.owner.pubkey()).await - owner_before, escrowed() / 4);
+
+  set_clock(&mut context, START + 500).await;
+  release(&mut context, &rental).await;
+  assert_eq!(get_lamports(&mut context, &rental.owner.pubkey()).await - owner_before, escrowed() / 2);
+  assert_eq!(get_escrow(&mut context, &rental.escrow).await.released, escrowed() / 2);
+}
+
+#[tokio::test]
+async fn release_rent_rejects_wrong_owner() {
+  let mut context = start().await;
+  let rental: Rental = rented_player(&mut context).await;
+  set_clock(&mut context, START + 250).await;
+
+  let result = process(
+    &mut context,
+    &[instruction::release_rent(&program_id(), &rental.player, &rental.renter.pubkey(), None)],
+    &[],
+  )
+  .await;
+
+  assert_game_error(result, GameError::NotPlayerOwner);
+}
+
+#[tokio::test]
+async fn terminate_rent_by_renter_refunds_unused_time() {
+  let mut context = start().await;
+  let rental: Rental = rented_player(&mut context).await;
+  let owner_before: u64 = get_lamports(&mut context, &rental.owner.pubkey()).await;
+  let renter_before: u64 = get_lamports(&mut context, &rental.renter.pubkey()).await;
+  let escrow_rent: u64 = get_lamports(&mut context, &rental.escrow).await - escrowed();
+
+  set_clock(&mut context, START + 400).await;
+  terminate(&mut context, &rental, &rental.renter).await.unwrap();
+
+  let earned: u64 = escrowed() * 400 / DURATION;
+  assert_eq!(get_lamports(&mut context, &rental.owner.pubkey()).await - owner_before, earned);
+  assert_eq!(
+    get_lamports(&mut context, &rental.renter.pubkey()).await - renter_before,
+    escrowed() - earned + escrow_rent,
+  );
+  assert!(!account_exists(&mut context, &rental.escrow).await);
+  let data: Player = get_player(&mut context, &rental.player).await;
+  assert_eq!(data.status, PlayerStatus::Idle);
+  assert_eq!(data.who_rents, [0; 32]);
+}
+
+#[tokio::test]
+async fn terminate_rent_by_owner_rejects_running_period() {
+  let mut context = start().await;
+  let rental: Rental = rented_player(&mut context).await;
+  set_clock(&mut context, START + 500).await;
+
+  let result = terminate(&mut context, &rental, &rental.owner).await;
+
+  assert_game_error(result, GameError::RentNotOver);
+  assert!(account_exists(&mut context, &rental.escrow).await);
+  assert_eq!(get_player(&mut context, &rental.player).await.who_rents, rental.renter.pubkey().to_bytes());
+}
+
+#[tokio::test]
+async fn terminate_rent_by_owner_after_period_refunds_prepaid() {
+  let mut context = start().await;
+  let rental: Rental = subscribed_player(&mut context, 2, 3).await;
+  let owner_before: u64 = get_lamports(&mut context, &rental.owner.pubkey()).await;
+  let renter_before: u64 = get_lamports(&mut context, &rental.renter.pubkey()).await;
+  let escrow_rent: u64 = get_lamports(&mut context, &rental.escrow).await - 3 * escrowed();
+  set_clock(&mut context, START + 250).await;
+  release(&mut context, &rental).await;
+
+  set_clock(&mut context, START + DURATION as i64).await;
+  terminate(&mut context, &rental, &rental.owner).await.unwrap();
+
+  assert_eq!(get_lamports(&mut context, &rental.owner.pubkey()).await - owner_before, escrowed());
+  assert_eq!(
+    get_lamports(&mut context, &rental.renter.pubkey()).await - renter_before,
+    2 * escrowed() + escrow_rent,
+  );
+  assert_eq!(get_player(&mut context, &rental.player).await.status, PlayerStatus::Idle);
+}
+
+#[tokio::test]
+async fn terminate_rent_rejects_third_party() {
+  let mut context = start().await;
+  let rental: Rental = rented_player(&mut context).await;
+  let stranger: Keypair = create_user(&mut context).await;
+
+  let result = terminate(&mut context, &rental, &stranger).await;
+
+  assert_game_error(result, GameError::NotRentParty);
+  assert!(account_exists(&mut context, &rental.escrow).await);
+}
+
+#[tokio::test]
+async fn terminate_rent_rejects_player_not_rented() {
+  let mut context = start().await;
+  let owner: Keypair = create_user(&mut context).await;
+  let renter: Keypair = create_user(&mut context).await;
+  let (player, _, _) = generate(&mut context, &owner).await;
+
+  let result = process(
+    &mut context,
+    &[instruction::terminate_rent(&program_id(), &renter.pubkey(), &player, &owner.pubkey(), &renter.pubkey(), None)],
+    &[&renter],
+  )
+  .await;
+
+  assert_game_error(result, GameError::PlayerNotRented);
+}
+
+#[tokio::test]
+async fn claim_rent_over_player_pays_remaining_rent() {
+  let mut context = start().await;
+  let rental: Rental = rented_player(&mut context).await;
+  let owner_before: u64 = get_lamports(&mut context, &rental.owner.pubkey()).await;
+  set_clock(&mut context, START + 250).await;
+  release(&mut context, &rental).await;
+
+  set_clock(&mut context, START + DURATION as i64).await;
+  process(
+    &mut context,
+    &[instruction::claim_rent_over_player(
+      &program_id(),
+      &rental.owner.pubkey(),
+      &rental.player,
+      &rental.renter.pubkey(),
+      None,
+    )],
+    &[&rental.owner],
+  )
+  .await
+  .unwrap();
+
+  assert_eq!(get_lamports(&mut context, &rental.owner.pubkey()).await - owner_before, escrowed());
+  assert!(!account_exists(&mut context, &rental.escrow).await);
+}
+
+#[tokio::test]
+async fn claim_rent_over_player_rejects_wrong_renter() {
+  let mut context = start().await;
+  let rental: Rental = rented_player(&mut context).await;
+  let stranger: Keypair = create_user(&mut context).await;
+  set_clock(&mut context, START + DURATION as i64).await;
+
+  let result = process(
+    &mut context,
+    &[instruction::claim_rent_over_player(
+      &program_id(),
+      &rental.owner.pubkey(),
+      &rental.player,
+      &stranger.pubkey(),
+      None,
+    )],
+    &[&rental.owner],
+  )
+  .await;
+
+  assert_game_error(result, GameError::InvalidRentEscrow);
+}
+
+#[tokio::test]
+async fn claim_rent_over_player_ends_rental_without_escrow() {
+  let mut context = start().await;
+  let owner: Keypair = create_user(&mut context).await;
+  let renter: Keypair = create_user(&mut context).await;
+  let (player, _, _) = generate(&mut context, &owner).await;
+  let mut data: Player = get_player(&mut context, &player).await;
+  data.status = PlayerStatus::Rented;
+  data.who_rents = renter.pubkey().to_bytes();
+  set_player(&mut context, &player, &data).await;
+
+  process(
+    &mut context,
+    &[instruction::claim_rent_over_player(&program_id(), &owner.pubkey(), &player, &renter.pubkey(), None)],
+    &[&owner],
+  )
+  .await
+  .unwrap();
+
+  let data: Player = get_player(&mut context, &player).await;
+  assert_eq!(data.status, PlayerStatus::Idle);
+}
+
+#[tokio::test]
+async fn token_rent_is_escrowed_and_split_on_termination() {
+  let mut context = start().await;
+  let treasury: Pubkey = setup_marketplace(&mut context).await;
+  let owner: Keypair = create_user(&mut context).await;
+  let renter: Keypair = create_user(&mut context).await;
+  let (player, _, _) = generate(&mut context, &owner).await;
+  let mint: Pubkey = create_mint(&mut context, 6).await;
+  let owner_payment: Pubkey = create_ata(&mut context, &owner.pubkey(), &mint).await;
+  let renter_payment: Pubkey = create_ata(&mut context, &renter.pubkey(), &mint).await;
+  let treasury_payment: Pubkey = create_ata(&mut context, &treasury, &mint).await;
+  mint_to(&mut context, &mint, &renter_payment, PRICE).await;
+  list_for_rent(&mut context, &owner, &player, PRICE, DURATION, 0, Some(mint)).await.unwrap();
+  set_clock(&mut context, START).await;
+
+  process(
+    &mut context,
+    &[instruction::rent_another_player(
+      &program_id(),
+      &renter.pubkey(),
+      &player,
+      &owner.pubkey(),
+      &treasury,
+      &rent_book(PRICE),
+      1,
+      None,
+      Some(RentTokenPayment {
+        payment_mint: mint,
+        payer_token_account: renter_payment,
+        treasury_token_account: treasury_payment,
+      }),
+    )],
+    &[&renter],
+  )
+  .await
+  .unwrap();
+
+  let escrow: Pubkey = instruction::find_rent_escrow_address(&program_id(), &player);
+  let escrow_payment: Pubkey = spl_associated_token_account::get_associated_token_address(&escrow, &mint);
+  assert_eq!(get_token_amount(&mut context, &escrow_payment).await, escrowed());
+  assert_eq!(get_token_amount(&mut context, &treasury_payment).await, PRICE - escrowed());
+
+  set_clock(&mut context, START + 500).await;
+  process(
+    &mut context,
+    &[instruction::terminate_rent(
+      &program_id(),
+      &renter.pubkey(),
+      &player,
+      &owner.pubkey(),
+      &renter.pubkey(),
+      Some(RentPayout {
+        payment_mint: mint,
+        owner_token_account: owner_payment,
+        renter_token_account: renter_payment,
+      }),
+    )],
+    &[&renter],
+  )
+  .await
+  .unwrap();
+
+  assert_eq!(get_token_amount(&mut context, &owner_payment).await, escrowed() / 2);
+  assert_eq!(get_token_amount(&mut context, &renter_payment).await, escrowed() - escrowed() / 2);
+  assert!(!account_exists(&mut context, &escrow_payment).await);
+  assert!(!account_exists(&mut context, &escrow).await);
+}
+
+#[tokio::test]
+async fn rent_another_player_prepays_periods() {
+  let mut context = start().await;
+
+  let rental: Rental = subscribed_player(&mut context, 2, 3).await;
+
+  let account = get_account(&mut context, &rental.escrow).await;
+  assert_eq!(account.lamports, Rent::default().minimum_balance(account.data.len()) + 3 * escrowed());
+  let escrow: RentEscrow = RentEscrow::unpack(&account.data).unwrap();
+  assert_eq!(escrow.amount, escrowed());
+  assert_eq!(escrow.prepaid, 2 * escrowed());
+  assert_eq!(escrow.renewals_left, 2);
+  assert_eq!(escrow.period, DURATION);
+  let data: Player = get_player(&mut context, &rental.player).await;
+  assert_eq!(data.rent_end_on, START as u64 + DURATION);
+}
+
+#[tokio::test]
+async fn rent_another_player_rejects_periods_outside_offer() {
+  let mut context = start().await;
+  let treasury: Pubkey = setup_marketplace(&mut context).await;
+  let owner: Keypair = create_user(&mut context).await;
+  let renter: Keypair = create_user(&mut context).await;
+  let (player, _, _) = generate(&mut context, &owner).await;
+  list_for_rent(&mut context, &owner, &player, PRICE, DURATION, 1, None).await.unwrap();
+
+  for periods in [0, 3] {
+    let result = rent_player(&mut context, &renter, &player, &owner.pubkey(), &treasury, PRICE, periods).await;
+    assert_game_error(result, GameError::InvalidRentPeriods);
+  }
+}
+
+#[tokio::test]
+async fn renew_rent_waits_for_period_end() {
+  let mut context = start().await;
+  let rental: Rental = subscribed_player(&mut context, 1, 2).await;
+  set_clock(&mut context, START + DURATION as i64 - 1).await;
+
+  let result = renew(&mut context, &rental).await;
+
+  assert_game_error(result, GameError::RentNotOver);
+}
+
+#[tokio::test]
+async fn renew_rent_pays_period_and_extends_rental() {
+  let mut context = start().await;
+  let rental: Rental = subscribed_player(&mut context, 2, 3).await;
+  let owner_before: u64 = get_lamports(&mut context, &rental.owner.pubkey()).await;
+  set_clock(&mut context, START + 250).await;
+  release(&mut context, &rental).await;
+
+  set_clock(&mut context, START + DURATION as i64 + 10).await;
+  renew(&mut context, &rental).await.unwrap();
+
+  assert_eq!(get_lamports(&mut context, &rental.owner.pubkey()).await - owner_before, escrowed());
+  let escrow: RentEscrow = get_escrow(&mut context, &rental.escrow).await;
+  assert_eq!(escrow.start_time, START as u64 + DURATION);
+  assert_eq!(escrow.end_time, START as u64 + 2 * DURATION);
+  assert_eq!(escrow.released, 0);
+  assert_eq!(escrow.renewals_left, 1);
+  assert_eq!(escrow.prepaid, escrowed());
+  let data: Player = get_player(&mut context, &rental.player).await;
+  assert_eq!(data.status, PlayerStatus::Rented);
+  assert_eq!(data.rent_end_on, START as u64 + 2 * DURATION);
+}
+
+#[tokio::test]
+async fn renew_rent_ends_rental_when_prepaid_periods_run_out() {
+  let mut context = start().await;
+  let rental: Rental = subscribed_player(&mut context, 1, 2).await;
+  let owner_before: u64 = get_lamports(&mut context, &rental.owner.pubkey()).await;
+  set_clock(&mut context, START + DURATION as i64).await;
+  renew(&mut context, &rental).await.unwrap();
+
+  set_clock(&mut context, START + 2 * DURATION as i64).await;
+  renew(&mut context, &rental).await.unwrap();
+
+  assert_eq!(get_lamports(&mut context, &rental.owner.pubkey()).await - owner_before, 2 * escrowed());
+  assert!(!account_exists(&mut context, &rental.escrow).await);
+  let data: Player = get_player(&mut context, &rental.player).await;
+  assert_eq!(data.status, PlayerStatus::Idle);
+  assert_eq!(data.who_rents, [0; 32]);
+}
+
+#[tokio::test]
+async fn terminate_rent_refunds_prepaid_periods() {
+  let mut context = start().await;
+  let rental: Rental = subscribed_player(&mut context, 2, 3).await;
+  let owner_before: u64 = get_lamports(&mut context, &rental.owner.pubkey()).await;
+  let renter_before: u64 = get_lamports(&mut context, &rental.renter.pubkey()).await;
+  let escrow_rent: u64 = get_lamports(&mut context, &rental.escrow).await - 3 * escrowed();
+
+  set_clock(&mut context, START + 500).await;
+  terminate(&mut context, &rental, &rental.renter).await.unwrap();
+
+  assert_eq!(get_lamports(&mut context, &rental.owner.pubkey()).await - owner_before, escrowed() / 2);
+  assert_eq!(
+    get_lamports(&mut context, &rental.renter.pubkey()).await - renter_before,
+    escrowed() - escrowed() / 2 + 2 * escrowed() + escrow_rent,
+  );
+}
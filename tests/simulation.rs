@@ -0,0 +1,174 @@
+use sol_battle_balls::{
+  simulation::{possession_rolls, simulate, simulate_rolls, Goal, Lineup, MatchResult, Rolls, Side, POSSESSIONS},
+  state::{Formation, Player, PlayerStatus, TEAM_SIZE},
+};
+use solana_program::keccak;
+
+fn player_with(offence: u64, defence: u64) -> Player {
+  Player {
+    nft_adress: [0; 32],
+    owner: [0; 32],
+    status: PlayerStatus::InTeam,
+    sale_required_price: 0,
+    rent_required_price: 0,
+    who_rents: [0; 32],
+    rented_for_time: 0,
+    rent_end_on: 0,
+    offence,
+    defence,
+    payment_mint: [0; 32],
+    max_renewals: 0,
+    listing_page: 0,
+  }
+}
+
+fn team_with(offence: u64, defence: u64) -> [Player; TEAM_SIZE] {
+  std::array::from_fn(|_| player_with(offence, defence))
+}
+
+//rolls of a challenge whose every possession uses the same attacker, defender and shot rolls
+fn rolls_with(attacker: u8, defender: u8, shot: u8) -> Vec<Rolls> {
+  vec![[attacker, defender, shot]; POSSESSIONS as usize]
+}
+
+fn play(user: &[Player; TEAM_SIZE], opponent: &[Player; TEAM_SIZE], rolls: Vec<Rolls>) -> MatchResult {
+  simulate_rolls(
+    &Lineup { formation: Formation::TwoOneTwo, players: user },
+    &Lineup { formation: Formation::TwoOneTwo, players: opponent },
+    rolls,
+  )
+}
+
+fn play_seed(user: &[Player; TEAM_SIZE], opponent: &[Player; TEAM_SIZE], seed: &[u8; 32], possessions: u8) -> MatchResult {
+  simulate(
+    &Lineup { formation: Formation::TwoOneTwo, players: user },
+    &Lineup { formation: Formation::TwoOneTwo, players: opponent },
+    seed,
+    possessions,
+  )
+}
+
+#[test]
+fn same_seed_plays_same_match() {
+  let user: [Player; TEAM_SIZE] = team_with(30, 20);
+  let opponent: [Player; TEAM_SIZE] = team_with(25, 25);
+  let seed: [u8; 32] = keccak::hash(b"match").to_bytes();
+
+  assert_eq!(play_seed(&user, &opponent, &seed, POSSESSIONS), play_seed(&user, &opponent, &seed, POSSESSIONS));
+}
+
+#[test]
+fn low_shot_rolls_score_every_possession() {
+  let team: [Player; TEAM_SIZE] = team_with(10, 10);
+
+  let result: MatchResult = play(&team, &team, rolls_with(0, 0, 0));
+
+  assert_eq!(result.user_score, POSSESSIONS / 2);
+  assert_eq!(result.opponent_score, POSSESSIONS / 2);
+  assert_eq!(result.goals.len(), POSSESSIONS as usize);
+  for (possession, goal) in result.goals.iter().enumerate() {
+    let side: Side = if possession % 2 == 0 { Side::User } else { Side::Opponent };
+    assert_eq!(goal.possession as usize, possession);
+    assert_eq!(goal.side, side);
+  }
+}
+
+#[test]
+fn high_shot_rolls_never_score() {
+  let team: [Player; TEAM_SIZE] = team_with(10, 10);
+
+  let result: MatchResult = play(&team, &team, rolls_with(0, 0, 255));
+
+  assert_eq!(result, MatchResult { user_score: 0, opponent_score: 0, goals: Vec::new() });
+}
+
+#[test]
+fn shot_scores_with_offence_share_of_the_duel() {
+  //30 offence against 10 defence scores 3/4 of the shot chance, rolls below 96
+  let team: [Player; TEAM_SIZE] = team_with(30, 10);
+
+  assert_eq!(play(&team, &team, rolls_with(0, 0, 95)).goals.len(), POSSESSIONS as usize);
+  assert_eq!(play(&team, &team, rolls_with(0, 0, 96)).goals.len(), 0);
+}
+
+#[test]
+fn players_without_offence_never_score() {
+  let user: [Player; TEAM_SIZE] = team_with(0, 10);
+  let opponent: [Player; TEAM_SIZE] = team_with(10, 0);
+
+  let result: MatchResult = play(&user, &opponent, rolls_with(0, 0, 0));
+
+  assert_eq!(result.user_score, 0);
+  assert_eq!(result.opponent_score, POSSESSIONS / 2);
+}
+
+#[test]
+fn offence_slots_duel_defence_slots() {
+  let team: [Player; TEAM_SIZE] = team_with(10, 10);
+
+  let result: MatchResult = simulate_rolls(
+    &Lineup { formation: Formation::TwoOneTwo, players: &team },
+    &Lineup { formation: Formation::ThreeOneOne, players: &team },
+    rolls_with(1, 2, 0),
+  );
+
+  //2-1-2 attacks with slots 2 to 4 and defends with 0 to 2, 3-1-1 attacks with 3 and 4 and defends with 0 to 3
+  assert_eq!(result.goals[0], Goal { possession: 0, side: Side::User, scorer_slot: 3, defender_slot: 2 });
+  assert_eq!(result.goals[1], Goal { possession: 1, side: Side::Opponent, scorer_slot: 4, defender_slot: 2 });
+}
+
+#[test]
+fn duel_uses_stats_of_the_picked_players() {
+  //only the second offence slot can score, and only against the first defence slot
+  let mut user: [Player; TEAM_SIZE] = team_with(0, 0);
+  user[4].offence = 10;
+  let mut opponent: [Player; TEAM_SIZE] = team_with(0, 100);
+  opponent[0].defence = 0;
+
+  assert_eq!(play(&user, &opponent, rolls_with(2, 0, 100)).user_score, POSSESSIONS / 2);
+  assert_eq!(play(&user, &opponent, rolls_with(1, 0, 100)).user_score, 0);
+  assert_eq!(play(&user, &opponent, rolls_with(2, 1, 100)).user_score, 0);
+}
+
+#[test]
+fn middle_slots_attack_and_defend() {
+  let team: [Player; TEAM_SIZE] = team_with(10, 10);
+
+  let result: MatchResult = play(&team, &team, rolls_with(0, 2, 0));
+
+  //the middle of a 2-1-2 is slot 2, the first attacker and the last defender
+  assert_eq!(result.goals[0], Goal { possession: 0, side: Side::User, scorer_slot: 2, defender_slot: 2 });
+}
+
+#[test]
+fn stronger_team_scores_more_over_many_matches() {
+  let strong: [Player; TEAM_SIZE] = team_with(60, 60);
+  let weak: [Player; TEAM_SIZE] = team_with(20, 20);
+  let mut strong_goals: u32 = 0;
+  let mut weak_goals: u32 = 0;
+
+  for i in 0..200u32 {
+    let result: MatchResult = play_seed(&strong, &weak, &keccak::hash(&i.to_le_bytes()).to_bytes(), POSSESSIONS);
+    strong_goals += result.user_score as u32;
+    weak_goals += result.opponent_score as u32;
+  }
+
+  assert!(strong_goals > 2 * weak_goals, "{} against {}", strong_goals, weak_goals);
+}
+
+#[test]
+fn seed_stretches_past_ten_possessions() {
+  let team: [Player; TEAM_SIZE] = team_with(30, 10);
+  let seed: [u8; 32] = keccak::hash(b"long match").to_bytes();
+
+  let short: MatchResult = play_seed(&team, &team, &seed, 10);
+  let long: MatchResult = play_seed(&team, &team, &seed, 40);
+
+  //the first possessions do not depend on how many follow
+  let first_ten: Vec<Goal> = long.goals.iter().filter(|goal| goal.possession < 10).copied().collect();
+  assert_eq!(first_ten, short.goals);
+  assert!(long.goals.iter().any(|goal| goal.possession >= 10));
+  assert!(long.goals.iter().all(|goal| goal.possession < 40));
+  let rolls: Vec<Rolls> = (0..40).map(|possession| possession_rolls(&seed, possession)).collect();
+  assert_eq!(play(&team, &team, rolls), long);
+}
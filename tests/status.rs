@@ -0,0 +1,327 @@
+mod common;
+
+use borsh::BorshSerialize;
+use common::*;
+use sol_battle_balls::{
+  instruction,
+  state::{Formation, ListingBook, Player, PlayerAction, PlayerStatus, ProgramAccount, TEAM_SIZE},
+};
+use solana_program::pubkey::Pubkey;
+use solana_program_test::{tokio, BanksClientError, ProgramTestContext};
+use solana_sdk::signature::{Keypair, Signer};
+
+const PRICE: u64 = 1_000_000;
+const STEPS: usize = 60;
+const SEEDS: [u64; 6] = [1, 7, 42, 1337, 0xdead_beef, 0x1234_5678_9abc];
+
+const STATUSES: [PlayerStatus; 6] = [
+  PlayerStatus::Idle,
+  PlayerStatus::ListedForSale,
+  PlayerStatus::ListedForRent,
+  PlayerStatus::Rented,
+  PlayerStatus::InAuction,
+  PlayerStatus::InTeam,
+];
+
+const ACTIONS: [PlayerAction; 14] = [
+  PlayerAction::ListForSale,
+  PlayerAction::AbortSale,
+  PlayerAction::Buy,
+  PlayerAction::ListForRent,
+  PlayerAction::AbortRent,
+  PlayerAction::Rent,
+  PlayerAction::RenewRent,
+  PlayerAction::EndRent,
+  PlayerAction::StartAuction,
+  PlayerAction::EndAuction,
+  PlayerAction::JoinTeam,
+  PlayerAction::LeaveTeam,
+  PlayerAction::ClaimNft,
+  PlayerAction::Close,
+];
+
+/// Every legal move, written out independently of `PlayerStatus::transition`.
+const LEGAL: [(PlayerStatus, PlayerAction, PlayerStatus); 17] = [
+  (PlayerStatus::Idle, PlayerAction::ListForSale, PlayerStatus::ListedForSale),
+  (PlayerStatus::Idle, PlayerAction::ListForRent, PlayerStatus::ListedForRent),
+  (PlayerStatus::Idle, PlayerAction::StartAuction, PlayerStatus::InAuction),
+  (PlayerStatus::Idle, PlayerAction::JoinTeam, PlayerStatus::InTeam),
+  (PlayerStatus::Idle, PlayerAction::ClaimNft, PlayerStatus::Idle),
+  (PlayerStatus::Idle, PlayerAction::Close, PlayerStatus::Idle),
+  (PlayerStatus::ListedForSale, PlayerAction::AbortSale, PlayerStatus::Idle),
+  (PlayerStatus::ListedForSale, PlayerAction::Buy, PlayerStatus::Idle),
+  (PlayerStatus::ListedForRent, PlayerAction::AbortRent, PlayerStatus::Idle),
+  (PlayerStatus::ListedForRent, PlayerAction::Rent, PlayerStatus::Rented),
+  (PlayerStatus::Rented, PlayerAction::RenewRent, PlayerStatus::Rented),
+  (PlayerStatus::Rented, PlayerAction::EndRent, PlayerStatus::Idle),
+  (PlayerStatus::Rented, PlayerAction::JoinTeam, PlayerStatus::Rented),
+  (PlayerStatus::InAuction, PlayerAction::EndAuction, PlayerStatus::Idle),
+  (PlayerStatus::InTeam, PlayerAction::JoinTeam, PlayerStatus::InTeam),
+  (PlayerStatus::InTeam, PlayerAction::LeaveTeam, PlayerStatus::Idle),
+  (PlayerStatus::InTeam, PlayerAction::ClaimNft, PlayerStatus::Idle),
+];
+
+/// xorshift64, enough to drive reproducible random sequences.
+struct Rng(u64);
+
+impl Rng {
+  fn next(&mut self) -> u64 {
+    self.0 ^= self.0 << 13;
+    self.0 ^= self.0 >> 7;
+    self.0 ^= self.0 << 17;
+    self.0
+  }
+
+  fn below(&mut self, n: usize) -> usize {
+    (self.next() % n as u64) as usize
+  }
+}
+
+fn legal_target(status: PlayerStatus, action: PlayerAction) -> Option<PlayerStatus> {
+  LEGAL.iter().find(|(from, by, _)| *from == status && *by == action).map(|(_, _, to)| *to)
+}
+
+#[test]
+fn transition_allows_exactly_the_legal_moves() {
+  for status in STATUSES {
+    for action in ACTIONS {
+      assert_eq!(status.transition(action).ok(), legal_target(status, action), "{:?} by {:?}", status, action);
+    }
+  }
+}
+
+#[test]
+fn random_action_sequences_only_reach_legal_states() {
+  for seed in SEEDS {
+    let mut rng: Rng = Rng(seed);
+    let mut status: PlayerStatus = PlayerStatus::Idle;
+    for _ in 0..1_000 {
+      let action: PlayerAction = ACTIONS[rng.below(ACTIONS.len())];
+      match status.transition(action) {
+        Ok(next) => {
+          assert_eq!(Some(next), legal_target(status, action), "{:?} by {:?}", status, action);
+          status = next;
+        }
+        Err(_) => assert_eq!(legal_target(status, action), None, "{:?} by {:?}", status, action),
+      }
+    }
+  }
+}
+
+/// Instructions the on-chain sequences are built from, each sent by a random actor.
+#[derive(Clone, Copy, Debug)]
+enum Step {
+  Sell,
+  AbortSale,
+  Buy,
+  ListForRent,
+  AbortRent,
+  Rent,
+  TerminateRent,
+  StartAuction,
+  CancelAuction,
+  JoinTeam,
+  FieldBench,
+}
+
+const STEP_KINDS: [Step; 11] = [
+  Step::Sell,
+  Step::AbortSale,
+  Step::Buy,
+  Step::ListForRent,
+  Step::AbortRent,
+  Step::Rent,
+  Step::TerminateRent,
+  Step::StartAuction,
+  Step::CancelAuction,
+  Step::JoinTeam,
+  Step::FieldBench,
+];
+
+struct Actor {
+  wallet: Keypair,
+  user_account: Pubkey,
+  ata: Pubkey,
+  bench: [Pubkey; TEAM_SIZE],
+  bench_nfts: [Pubkey; TEAM_SIZE],
+}
+
+impl Actor {
+  /// Every player a team of this actor may hold: the contested one and the bench.
+  fn old_team(&self, world_nft: Pubkey) -> Vec<Pubkey> {
+    let mut team: Vec<Pubkey> = vec![world_nft];
+    team.extend_from_slice(&self.bench_nfts);
+    team
+  }
+}
+
+struct World {
+  treasury: Pubkey,
+  player: Pubkey,
+  nft: Pubkey,
+  actors: [Actor; 2],
+}
+
+async fn actor(context: &mut ProgramTestContext, name: &str) -> Actor {
+  let wallet: Keypair = create_user(context).await;
+  let user_account: Pubkey = register(context, &wallet, name).await;
+  let (bench, bench_nfts) = generate_team(context, &wallet).await;
+  Actor { wallet, user_account, ata: Pubkey::default(), bench, bench_nfts }
+}
+
+async fn world(context: &mut ProgramTestContext) -> World {
+  let treasury: Pubkey = setup_marketplace(context).await;
+  let mut alice: Actor = actor(context, "alice").await;
+  let mut bob: Actor = actor(context, "bob").await;
+  let (player, nft, ata) = generate(context, &alice.wallet).await;
+  alice.ata = ata;
+  bob.ata = create_ata(context, &bob.wallet.pubkey(), &nft).await;
+  World { treasury, player, nft, actors: [alice, bob] }
+}
+
+/// The status change `step` asks for, if it touches the player at all.
+fn step_action(step: Step, before: &Player, actor: &Actor) -> Option<PlayerAction> {
+  match step {
+    Step::Sell => Some(PlayerAction::ListForSale),
+    Step::AbortSale => Some(PlayerAction::AbortSale),
+    Step::Buy => Some(PlayerAction::Buy),
+    Step::ListForRent => Some(PlayerAction::ListForRent),
+    Step::AbortRent => Some(PlayerAction::AbortRent),
+    Step::Rent => Some(PlayerAction::Rent),
+    Step::TerminateRent => Some(PlayerAction::EndRent),
+    Step::StartAuction => Some(PlayerAction::StartAuction),
+    Step::CancelAuction => Some(PlayerAction::EndAuction),
+    Step::JoinTeam => Some(PlayerAction::JoinTeam),
+    Step::FieldBench => {
+      let owned: bool = before.owner == actor.wallet.pubkey().to_bytes();
+      (before.status == PlayerStatus::InTeam && owned).then_some(PlayerAction::LeaveTeam)
+    }
+  }
+}
+
+async fn run_step(context: &mut ProgramTestContext, world: &World, step: Step, actor: &Actor, other: &Actor) -> Result<(), BanksClientError> {
+  let before: Player = get_player(context, &world.player).await;
+  let owner: Pubkey = Pubkey::new_from_array(before.owner);
+  let vault: Pubkey = instruction::find_vault_address(&program_id());
+  let wallet: Pubkey = actor.wallet.pubkey();
+  let ix = match step {
+    Step::Sell => instruction::sell_player(&program_id(), &wallet, &world.player, &world.nft, &actor.ata, &vault, PRICE, None, 0),
+    Step::AbortSale => instruction::abort_sell(
+      &program_id(),
+      &wallet,
+      &world.player,
+      &vault,
+      &escrow_address(&world.nft),
+      &actor.ata,
+      &sale_book(PRICE),
+    ),
+    Step::Buy => instruction::buy_player(
+      &program_id(),
+      &wallet,
+      &world.player,
+      &world.nft,
+      &owner,
+      &vault,
+      &escrow_address(&world.nft),
+      &actor.ata,
+      &world.treasury,
+      &sale_book(PRICE),
+      None,
+      None,
+    ),
+    Step::ListForRent => instruction::rent_your_player(&program_id(), &wallet, &world.player, PRICE, 3600, 0, None, 0),
+    Step::AbortRent => instruction::abort_rent(&program_id(), &wallet, &world.player, &rent_book(PRICE)),
+    Step::Rent => return rent_player(context, &actor.wallet, &world.player, &owner, &world.treasury, PRICE, 1).await,
+    Step::TerminateRent => {
+      let renter: Pubkey = if owner == wallet { other.wallet.pubkey() } else { wallet };
+      instruction::terminate_rent(&program_id(), &wallet, &world.player, &owner, &renter, None)
+    }
+    Step::StartAuction => instruction::create_auction(&program_id(), &wallet, &world.player, &world.nft, &actor.ata, PRICE, 3600),
+    Step::CancelAuction => instruction::cancel_auction(&program_id(), &wallet, &world.player, &world.nft, &actor.ata),
+    Step::JoinTeam => {
+      let mut team: [Pubkey; TEAM_SIZE] = actor.bench;
+      team[0] = world.player;
+      instruction::set_team(&program_id(), &wallet, &actor.user_account, Formation::TwoOneTwo, &team, &actor.old_team(world.nft))
+    }
+    Step::FieldBench => {
+      let old_team: Vec<Pubkey> = actor.old_team(world.nft);
+      instruction::set_team(&program_id(), &wallet, &actor.user_account, Formation::TwoOneTwo, &actor.bench, &old_team)
+    }
+  };
+  process(context, &[ix], &[&actor.wallet]).await
+}
+
+async fn listed_in(context: &mut ProgramTestContext, book: &Pubkey, player: &Pubkey) -> bool {
+  match context.banks_client.get_account(*book).await.unwrap() {
+    Some(account) => {
+      ListingBook::unpack(&account.data).unwrap().entries.iter().any(|entry| entry.player == player.to_bytes())
+    }
+    None => false,
+  }
+}
+
+async fn nft_amount(context: &mut ProgramTestContext, ata: &Pubkey) -> u64 {
+  match account_exists(context, ata).await {
+    true => get_token_amount(context, ata).await,
+    false => 0,
+  }
+}
+
+/// Checks that the player's status agrees with where its NFT, listings,
+/// rent escrow and auction are.
+async fn assert_consistent(context: &mut ProgramTestContext, world: &World, data: &Player) {
+  let status: PlayerStatus = data.status;
+  let escrowed: bool = matches!(status, PlayerStatus::ListedForSale | PlayerStatus::InAuction);
+  let owner_ata: Pubkey = spl_associated_token_account::get_associated_token_address(
+    &Pubkey::new_from_array(data.owner),
+    &world.nft,
+  );
+  assert_eq!(nft_amount(context, &escrow_address(&world.nft)).await, escrowed as u64, "{:?}", status);
+  assert_eq!(nft_amount(context, &owner_ata).await, !escrowed as u64, "{:?}", status);
+  assert_eq!(
+    listed_in(context, &sale_book(PRICE), &world.player).await,
+    status == PlayerStatus::ListedForSale
+  );
+  assert_eq!(
+    listed_in(context, &rent_book(PRICE), &world.player).await,
+    status == PlayerStatus::ListedForRent
+  );
+  let rent_escrow: Pubkey = instruction::find_rent_escrow_address(&program_id(), &world.player);
+  assert_eq!(account_exists(context, &rent_escrow).await, status == PlayerStatus::Rented);
+  assert_eq!(data.who_rents != [0; 32], status == PlayerStatus::Rented);
+  let auction: Pubkey = instruction::find_auction_address(&program_id(), &world.player);
+  assert_eq!(account_exists(context, &auction).await, status == PlayerStatus::InAuction);
+}
+
+#[tokio::test]
+async fn random_instruction_sequences_keep_status_consistent() {
+  for seed in SEEDS {
+    let mut context = start().await;
+    let world: World = world(&mut context).await;
+    let mut rng: Rng = Rng(seed);
+
+    for _ in 0..STEPS {
+      //a fresh slot keeps repeated instructions from being deduplicated
+      let slot: u64 = context.banks_client.get_root_slot().await.unwrap();
+      context.warp_to_slot(slot + 1).unwrap();
+
+      let step: Step = STEP_KINDS[rng.below(STEP_KINDS.len())];
+      let before: Player = get_player(&mut context, &world.player).await;
+      //mostly let the side that may legally act send the step, sometimes the other one
+      let owner: usize = (before.owner != world.actors[0].wallet.pubkey().to_bytes()) as usize;
+      let counterparty: bool = matches!(step, Step::Buy | Step::Rent);
+      let index: usize = if rng.below(4) == 0 { rng.below(2) } else if counterparty { 1 - owner } else { owner };
+      let (actor, other) = (&world.actors[index], &world.actors[1 - index]);
+
+      let result = run_step(&mut context, &world, step, actor, other).await;
+
+      let after: Player = get_player(&mut context, &world.player).await;
+      match (result, step_action(step, &before, actor)) {
+        (Ok(()), Some(action)) => assert_eq!(Ok(after.status), before.status.transition(action), "{:?}", step),
+        (Ok(()), None) => assert_eq!(after.status, before.status, "{:?}", step),
+        (Err(_), _) => assert_eq!(after.try_to_vec().unwrap(), before.try_to_vec().unwrap(), "{:?} changed the player", step),
+      }
+      assert_consistent(&mut context, &world, &after).await;
+    }
+  }
+}
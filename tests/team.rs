@@ -0,0 +1,783 @@
+mod common;
+
+use common::*;
+use sol_battle_balls::{
+  error::GameError,
+  instruction,
+  simulation::{simulate, Lineup, MatchResult},
+  state::{
+    compute_team_power, Formation, MatchRecord, Player, PlayerStatus, ProgramAccount, Role, TeamStatus, UserAccount,
+    DISCRIMINATOR_LEN, TEAM_SIZE,
+  },
+};
+use solana_program::{hash::Hash, pubkey::Pubkey};
+use solana_program_test::{tokio, BanksClientError, ProgramTestContext};
+use solana_sdk::signature::{Keypair, Signer};
+
+const PRICE: u64 = 1_000_000;
+
+/// Registers a user and marks its team ready with `team_power`, without
+/// players. Enough for challenges rejected before the teams are checked.
+async fn user_with_team(context: &mut ProgramTestContext, name: &str, team_power: u64) -> (Keypair, Pubkey) {
+  let user: Keypair = create_user(context).await;
+  let user_account: Pubkey = register(context, &user, name).await;
+  let mut data: UserAccount = get_user(context, &user_account).await;
+  data.team_status = TeamStatus::Ready;
+  data.team_power = team_power;
+  set_user(context, &user_account, &data).await;
+  (user, user_account)
+}
+
+/// Registers a user and fields a generated team. Returns (user, user account, team nfts).
+async fn challenger(context: &mut ProgramTestContext, name: &str) -> (Keypair, Pubkey, [Pubkey; TEAM_SIZE]) {
+  let user: Keypair = create_user(context).await;
+  let user_account: Pubkey = register(context, &user, name).await;
+  let team: [Pubkey; TEAM_SIZE] = field_team(context, &user, &user_account).await;
+  (user, user_account, team)
+}
+
+/// Fields a team for `user` whose last slot is rented from another owner for an hour.
+/// Returns (team nfts, owner of the rented player, treasury).
+async fn team_with_rented_player(
+  context: &mut ProgramTestContext,
+  user: &Keypair,
+  user_account: &Pubkey,
+) -> ([Pubkey; TEAM_SIZE], Keypair, Pubkey) {
+  let treasury: Pubkey = setup_marketplace(context).await;
+  let owner: Keypair = create_user(context).await;
+  let (rented, rented_nft, _) = generate(context, &owner).await;
+  list_for_rent(context, &owner, &rented, PRICE, 3600, 0, None).await.unwrap();
+  rent_player(context, user, &rented, &owner.pubkey(), &treasury, PRICE, 1).await.unwrap();
+  let (mut team, mut nfts) = generate_team(context, user).await;
+  team[4] = rented;
+  nfts[4] = rented_nft;
+  set_team(context, user, user_account, Formation::TwoOneTwo, &team, &[]).await.unwrap();
+  (nfts, owner, treasury)
+}
+
+/// Ends the rental of the player of `nft` once it is over.
+async fn end_rental(context: &mut ProgramTestContext, owner: &Keypair, nft: &Pubkey, renter: &Pubkey) {
+  let timestamp: i64 = now(context).await;
+  set_clock(context, timestamp + 3600).await;
+  let player: Pubkey = instruction::find_player_address(&program_id(), nft);
+  process(
+    context,
+    &[instruction::claim_rent_over_player(&program_id(), &owner.pubkey(), &player, renter, None)],
+    &[owner],
+  )
+  .await
+  .unwrap();
+}
+
+async fn challenge(
+  context: &mut ProgramTestContext,
+  user: &Keypair,
+  user_account: &Pubkey,
+  opponent_account: &Pubkey,
+  team: &[Pubkey; TEAM_SIZE],
+  opponent_team: &[Pubkey; TEAM_SIZE],
+  match_id: u64,
+) -> Result<(), BanksClientError> {
+  let secret: [u8; 32] = Hash::new_unique().to_bytes();
+  commit_challenge(context, user, user_account, opponent_account, secret).await;
+  send_challenge(context, user, user_account, opponent_account, team, opponent_team, match_id, secret).await
+}
+
+/// Sends a challenge without committing first, enough for challenges
+/// rejected before the reveal.
+#[allow(clippy::too_many_arguments)]
+async fn send_challenge(
+  context: &mut ProgramTestContext,
+  user: &Keypair,
+  user_account: &Pubkey,
+  opponent_account: &Pubkey,
+  team: &[Pubkey; TEAM_SIZE],
+  opponent_team: &[Pubkey; TEAM_SIZE],
+  match_id: u64,
+  secret: [u8; 32],
+) -> Result<(), BanksClientError> {
+  process(
+    context,
+    &[instruction::challenge(
+      &program_id(),
+      &user.pubkey(),
+      user_account,
+      opponent_account,
+      team,
+      opponent_team,
+      match_id,
+      secret,
+    )],
+    &[user],
+  )
+  .await
+}
+
+async fn refresh_team(
+  context: &mut ProgramTestContext,
+  user_account: &Pubkey,
+  team: &[Pubkey; TEAM_SIZE],
+) -> Result<(), BanksClientError> {
+  process(context, &[instruction::refresh_team(&program_id(), user_account, team)], &[]).await
+}
+
+async fn set_team(
+  context: &mut ProgramTestContext,
+  user: &Keypair,
+  user_account: &Pubkey,
+  formation: Formation,
+  team: &[Pubkey; TEAM_SIZE],
+  old_team: &[Pubkey],
+) -> Result<(), BanksClientError> {
+  process(
+    context,
+    &[instruction::set_team(&program_id(), &user.pubkey(), user_account, formation, team, old_team)],
+    &[user],
+  )
+  .await
+}
+
+async fn upgrade(
+  context: &mut ProgramTestContext,
+  user: &Keypair,
+  team: &[Pubkey; TEAM_SIZE],
+  slot: u8,
+  exp: u64,
+) -> Result<(), BanksClientError> {
+  process(context, &[instruction::upgrade(&program_id(), &user.pubkey(), team, slot, exp)], &[user]).await
+}
+
+async fn get_team(context: &mut ProgramTestContext, team: &[Pubkey; TEAM_SIZE]) -> [Player; TEAM_SIZE] {
+  [
+    get_player(context, &team[0]).await,
+    get_player(context, &team[1]).await,
+    get_player(context, &team[2]).await,
+    get_player(context, &team[3]).await,
+    get_player(context, &team[4]).await,
+  ]
+}
+
+fn player_with(offence: u64, defence: u64) -> Player {
+  Player {
+    nft_adress: [0; 32],
+    owner: [0; 32],
+    status: PlayerStatus::Idle,
+    sale_required_price: 0,
+    rent_required_price: 0,
+    who_rents: [0; 32],
+    rented_for_time: 0,
+    rent_end_on: 0,
+    offence,
+    defence,
+    payment_mint: [0; 32],
+    max_renewals: 0,
+    listing_page: 0,
+  }
+}
+
+#[test]
+fn compute_team_power_weights_slots_by_role() {
+  let players = [player_with(1, 10), player_with(2, 20), player_with(3, 30), player_with(4, 40), player_with(5, 50)];
+
+  //defence slots weigh (1, 2), middle slots (3, 3) and offence slots (2, 1)
+  assert_eq!(compute_team_power(Formation::TwoOneTwo, &players), 21 + 42 + 99 + 48 + 60);
+  assert_eq!(compute_team_power(Formation::OneTwoTwo, &players), 21 + 66 + 99 + 48 + 60);
+  assert_eq!(compute_team_power(Formation::ThreeOneOne, &players), 21 + 42 + 63 + 132 + 60);
+}
+
+#[test]
+fn formations_have_five_slots_defence_first() {
+  for formation in [Formation::TwoOneTwo, Formation::OneTwoTwo, Formation::ThreeOneOne] {
+    let roles: [Role; TEAM_SIZE] = formation.roles();
+    assert!(roles.windows(2).all(|pair| pair[0] as u8 <= pair[1] as u8), "{:?}", formation);
+  }
+}
+
+#[tokio::test]
+async fn set_team_puts_players_in_team() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+  let user_account: Pubkey = register(&mut context, &user, "alice").await;
+  let (team, nfts) = generate_team(&mut context, &user).await;
+
+  set_team(&mut context, &user, &user_account, Formation::OneTwoTwo, &team, &[]).await.unwrap();
+
+  let players: [Player; TEAM_SIZE] = get_team(&mut context, &team).await;
+  assert!(players.iter().all(|player| player.status == PlayerStatus::InTeam));
+  let data: UserAccount = get_user(&mut context, &user_account).await;
+  assert_eq!(data.team_status, TeamStatus::Ready);
+  assert_eq!(data.formation, Formation::OneTwoTwo);
+  assert_eq!(data.team, nfts.map(|nft| nft.to_bytes()));
+  assert_eq!(data.team_power, compute_team_power(Formation::OneTwoTwo, &players));
+}
+
+#[tokio::test]
+async fn set_team_keeps_earned_experience() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+  let user_account: Pubkey = register(&mut context, &user, "alice").await;
+  let (team, nfts) = generate_team(&mut context, &user).await;
+  set_team(&mut context, &user, &user_account, Formation::TwoOneTwo, &team, &[]).await.unwrap();
+  let mut data: UserAccount = get_user(&mut context, &user_account).await;
+  data.experienced = 7;
+  set_user(&mut context, &user_account, &data).await;
+
+  set_team(&mut context, &user, &user_account, Formation::OneTwoTwo, &team, &nfts).await.unwrap();
+
+  assert_eq!(get_user(&mut context, &user_account).await.experienced, 7);
+}
+
+#[tokio::test]
+async fn set_team_weights_players_by_formation() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+  let user_account: Pubkey = register(&mut context, &user, "alice").await;
+  let (team, nfts) = generate_team(&mut context, &user).await;
+  let players: [Player; TEAM_SIZE] = get_team(&mut context, &team).await;
+
+  for formation in [Formation::TwoOneTwo, Formation::OneTwoTwo, Formation::ThreeOneOne] {
+    set_team(&mut context, &user, &user_account, formation, &team, &nfts).await.unwrap();
+
+    let data: UserAccount = get_user(&mut context, &user_account).await;
+    assert_eq!(data.formation, formation);
+    assert_eq!(data.team_power, compute_team_power(formation, &players), "{:?}", formation);
+  }
+}
+
+#[tokio::test]
+async fn set_team_releases_replaced_players() {
+  let mut context = start().await;
+  setup_marketplace(&mut context).await;
+  let user: Keypair = create_user(&mut context).await;
+  let user_account: Pubkey = register(&mut context, &user, "alice").await;
+  let (first, first_nfts) = generate_team(&mut context, &user).await;
+  let (substitute, _, _) = generate(&mut context, &user).await;
+  let first_ata: Pubkey = spl_associated_token_account::get_associated_token_address(&user.pubkey(), &first_nfts[0]);
+  set_team(&mut context, &user, &user_account, Formation::TwoOneTwo, &first, &[]).await.unwrap();
+
+  let result = list_for_sale(&mut context, &user, &first[0], &first_nfts[0], &first_ata, 1_000_000).await;
+  assert_game_error(result, GameError::PlayerInTeam);
+
+  let mut second: [Pubkey; TEAM_SIZE] = first;
+  second[0] = substitute;
+  let result = set_team(&mut context, &user, &user_account, Formation::TwoOneTwo, &second, &[]).await;
+  assert_game_error(result, GameError::TeamPlayerMissing);
+
+  set_team(&mut context, &user, &user_account, Formation::TwoOneTwo, &second, &first_nfts).await.unwrap();
+
+  assert_eq!(get_player(&mut context, &first[0]).await.status, PlayerStatus::Idle);
+  for player in second {
+    assert_eq!(get_player(&mut context, &player).await.status, PlayerStatus::InTeam);
+  }
+  list_for_sale(&mut context, &user, &first[0], &first_nfts[0], &first_ata, 1_000_000).await.unwrap();
+}
+
+#[tokio::test]
+async fn set_team_rejects_duplicate_players() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+  let user_account: Pubkey = register(&mut context, &user, "alice").await;
+  let (mut team, _) = generate_team(&mut context, &user).await;
+  team[4] = team[1];
+
+  let result = set_team(&mut context, &user, &user_account, Formation::TwoOneTwo, &team, &[]).await;
+
+  assert_game_error(result, GameError::DuplicatePlayer);
+  assert_eq!(get_player(&mut context, &team[1]).await.status, PlayerStatus::Idle);
+}
+
+#[tokio::test]
+async fn set_team_rejects_listed_players() {
+  let mut context = start().await;
+  setup_marketplace(&mut context).await;
+  let user: Keypair = create_user(&mut context).await;
+  let user_account: Pubkey = register(&mut context, &user, "alice").await;
+  let (team, nfts) = generate_team(&mut context, &user).await;
+  let ata: Pubkey = spl_associated_token_account::get_associated_token_address(&user.pubkey(), &nfts[2]);
+  list_for_sale(&mut context, &user, &team[2], &nfts[2], &ata, 1_000_000).await.unwrap();
+
+  let result = set_team(&mut context, &user, &user_account, Formation::TwoOneTwo, &team, &[]).await;
+
+  assert_game_error(result, GameError::PlayerListed);
+}
+
+#[tokio::test]
+async fn set_team_rejects_players_of_other_user() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+  let other: Keypair = create_user(&mut context).await;
+  let user_account: Pubkey = register(&mut context, &user, "alice").await;
+  let (mut team, _) = generate_team(&mut context, &user).await;
+  (team[3], _, _) = generate(&mut context, &other).await;
+
+  let result = set_team(&mut context, &user, &user_account, Formation::TwoOneTwo, &team, &[]).await;
+
+  assert_game_error(result, GameError::PlayerNotControlled);
+}
+
+#[tokio::test]
+async fn set_team_rejects_other_signer() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+  let other: Keypair = create_user(&mut context).await;
+  let user_account: Pubkey = register(&mut context, &user, "alice").await;
+  let (player, _, _) = generate(&mut context, &other).await;
+
+  let result = set_team(&mut context, &other, &user_account, Formation::TwoOneTwo, &[player; TEAM_SIZE], &[]).await;
+
+  assert_game_error(result, GameError::NotAccountOwner);
+}
+
+#[tokio::test]
+async fn challenge_records_match_and_updates_stats() {
+  let mut context = start().await;
+  let (user, user_account, team) = challenger(&mut context, "alice").await;
+  let (_, opponent_account, opponent_team) = challenger(&mut context, "bob").await;
+  let team_power: u64 = get_user(&mut context, &user_account).await.team_power;
+  let opponent_team_power: u64 = get_user(&mut context, &opponent_account).await.team_power;
+
+  challenge(&mut context, &user, &user_account, &opponent_account, &team, &opponent_team, 1).await.unwrap();
+
+  let match_address: Pubkey = instruction::find_match_address(&program_id(), &user.pubkey(), 1);
+  let record: MatchRecord = MatchRecord::unpack(&get_account(&mut context, &match_address).await.data).unwrap();
+  assert_eq!(record.match_id, 1);
+  assert_eq!(record.user, user.pubkey().to_bytes());
+  assert_eq!(record.user_team_power, team_power);
+  assert_eq!(record.opponent_team_power, opponent_team_power);
+  assert_eq!(record.goals.len(), (record.user_score + record.opponent_score) as usize);
+
+  let user_data: UserAccount = get_user(&mut context, &user_account).await;
+  let opponent_data: UserAccount = get_user(&mut context, &opponent_account).await;
+  assert_eq!(user_data.wins + user_data.losses + user_data.draws, 1);
+  assert_eq!(user_data.wins, opponent_data.losses);
+  assert_eq!(user_data.losses, opponent_data.wins);
+  assert_eq!(user_data.rating + opponent_data.rating, 2 * 1200);
+
+  let commit_address: Pubkey = instruction::find_commit_address(&program_id(), &user.pubkey(), &opponent_account);
+  assert!(!account_exists(&mut context, &commit_address).await);
+}
+
+#[tokio::test]
+async fn challenge_plays_with_current_team_power() {
+  let mut context = start().await;
+  let (user, user_account, team) = challenger(&mut context, "alice").await;
+  let (_, opponent_account, opponent_team) = challenger(&mut context, "bob").await;
+  let players: [Pubkey; TEAM_SIZE] = team.map(|nft| instruction::find_player_address(&program_id(), &nft));
+  let mut data: Player = get_player(&mut context, &players[0]).await;
+  data.defence += 10;
+  set_player(&mut context, &players[0], &data).await;
+  let team_power: u64 = compute_team_power(Formation::TwoOneTwo, &get_team(&mut context, &players).await);
+
+  challenge(&mut context, &user, &user_account, &opponent_account, &team, &opponent_team, 1).await.unwrap();
+
+  let match_address: Pubkey = instruction::find_match_address(&program_id(), &user.pubkey(), 1);
+  let record: MatchRecord = MatchRecord::unpack(&get_account(&mut context, &match_address).await.data).unwrap();
+  assert_eq!(record.user_team_power, team_power);
+  assert_eq!(get_user(&mut context, &user_account).await.team_power, team_power);
+}
+
+#[tokio::test]
+async fn challenge_record_replays_off_chain() {
+  let mut context = start().await;
+  let (user, user_account, team) = challenger(&mut context, "alice").await;
+  let (_, opponent_account, opponent_team) = challenger(&mut context, "bob").await;
+  let players: [Pubkey; TEAM_SIZE] = team.map(|nft| instruction::find_player_address(&program_id(), &nft));
+  let opponent_players: [Pubkey; TEAM_SIZE] = opponent_team.map(|nft| instruction::find_player_address(&program_id(), &nft));
+
+  challenge(&mut context, &user, &user_account, &opponent_account, &team, &opponent_team, 1).await.unwrap();
+
+  let match_address: Pubkey = instruction::find_match_address(&program_id(), &user.pubkey(), 1);
+  let record: MatchRecord = MatchRecord::unpack(&get_account(&mut context, &match_address).await.data).unwrap();
+  let user_players: [Player; TEAM_SIZE] = get_team(&mut context, &players).await;
+  let opponent_players: [Player; TEAM_SIZE] = get_team(&mut context, &opponent_players).await;
+  let result: MatchResult = simulate(
+    &Lineup { formation: Formation::TwoOneTwo, players: &user_players },
+    &Lineup { formation: Formation::TwoOneTwo, players: &opponent_players },
+    &record.seed,
+    record.possessions,
+  );
+  assert_eq!(result.user_score, record.user_score);
+  assert_eq!(result.opponent_score, record.opponent_score);
+  assert_eq!(result.goals, record.goals);
+}
+
+#[tokio::test]
+async fn challenge_rejects_user_without_team() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+  let user_account: Pubkey = register(&mut context, &user, "alice").await;
+  let (_, opponent_account, opponent_team) = challenger(&mut context, "bob").await;
+  let team: [Pubkey; TEAM_SIZE] = [Pubkey::default(); TEAM_SIZE];
+
+  let result = send_challenge(&mut context, &user, &user_account, &opponent_account, &team, &opponent_team, 1, [0; 32]).await;
+
+  assert_game_error(result, GameError::TeamNotReady);
+}
+
+#[tokio::test]
+async fn challenge_rejects_team_with_expired_rental() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+  let user_account: Pubkey = register(&mut context, &user, "alice").await;
+  let (team, _, _) = team_with_rented_player(&mut context, &user, &user_account).await;
+  let (_, opponent_account, opponent_team) = challenger(&mut context, "bob").await;
+  challenge(&mut context, &user, &user_account, &opponent_account, &team, &opponent_team, 1).await.unwrap();
+
+  let timestamp: i64 = now(&mut context).await;
+  set_clock(&mut context, timestamp + 3600).await;
+  let result = challenge(&mut context, &user, &user_account, &opponent_account, &team, &opponent_team, 2).await;
+
+  assert_game_error(result, GameError::TeamNotEligible);
+}
+
+#[tokio::test]
+async fn challenge_rejects_own_user_account() {
+  let mut context = start().await;
+  let (user, user_account, team) = challenger(&mut context, "alice").await;
+
+  let result = challenge(&mut context, &user, &user_account, &user_account, &team, &team, 1).await;
+
+  assert_game_error(result, GameError::SelfChallenge);
+  assert_eq!(get_user(&mut context, &user_account).await.wins, 0);
+}
+
+#[tokio::test]
+async fn challenge_rejects_opponent_without_team() {
+  let mut context = start().await;
+  let (user, user_account, team) = challenger(&mut context, "alice").await;
+  let opponent: Keypair = create_user(&mut context).await;
+  let opponent_account: Pubkey = register(&mut context, &opponent, "bob").await;
+
+  let result = challenge(&mut context, &user, &user_account, &opponent_account, &team, &[Pubkey::default(); TEAM_SIZE], 1).await;
+
+  assert_game_error(result, GameError::TeamNotReady);
+}
+
+#[tokio::test]
+async fn challenge_rejects_opponent_team_with_expired_rental() {
+  let mut context = start().await;
+  let (user, user_account, team) = challenger(&mut context, "alice").await;
+  let opponent: Keypair = create_user(&mut context).await;
+  let opponent_account: Pubkey = register(&mut context, &opponent, "bob").await;
+  let (opponent_team, _, _) = team_with_rented_player(&mut context, &opponent, &opponent_account).await;
+  let timestamp: i64 = now(&mut context).await;
+  set_clock(&mut context, timestamp + 3600).await;
+
+  let result = challenge(&mut context, &user, &user_account, &opponent_account, &team, &opponent_team, 1).await;
+
+  assert_game_error(result, GameError::TeamNotEligible);
+}
+
+#[tokio::test]
+async fn challenge_rejects_players_of_other_slots() {
+  let mut context = start().await;
+  let (user, user_account, mut team) = challenger(&mut context, "alice").await;
+  let (_, opponent_account, opponent_team) = challenger(&mut context, "bob").await;
+  team.swap(1, 2);
+
+  let result = challenge(&mut context, &user, &user_account, &opponent_account, &team, &opponent_team, 1).await;
+
+  assert_game_error(result, GameError::PlayerNotInTeam);
+}
+
+#[tokio::test]
+async fn challenge_rejects_reused_match_id() {
+  let mut context = start().await;
+  let (user, user_account, team) = challenger(&mut context, "alice").await;
+  let (_, opponent_account, opponent_team) = challenger(&mut context, "bob").await;
+  challenge(&mut context, &user, &user_account, &opponent_account, &team, &opponent_team, 1).await.unwrap();
+
+  let result = challenge(&mut context, &user, &user_account, &opponent_account, &team, &opponent_team, 1).await;
+
+  assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn challenge_rejects_other_signer() {
+  let mut context = start().await;
+  let (_, user_account) = user_with_team(&mut context, "alice", 500).await;
+  let (opponent, opponent_account) = user_with_team(&mut context, "bob", 500).await;
+  let team: [Pubkey; TEAM_SIZE] = [Pubkey::default(); TEAM_SIZE];
+
+  let result = send_challenge(&mut context, &opponent, &user_account, &opponent_account, &team, &team, 1, [0; 32]).await;
+
+  assert_game_error(result, GameError::NotAccountOwner);
+}
+
+#[tokio::test]
+async fn challenge_requires_commit_for_opponent() {
+  let mut context = start().await;
+  let (user, user_account, team) = challenger(&mut context, "alice").await;
+  let (_, opponent_account, opponent_team) = challenger(&mut context, "bob").await;
+  let (_, other_account) = user_with_team(&mut context, "carol", 500).await;
+  let secret: [u8; 32] = Hash::new_unique().to_bytes();
+  commit(&mut context, &user, &other_account, secret).await;
+
+  let result = process(
+    &mut context,
+    &[instruction::challenge(&program_id(), &user.pubkey(), &user_account, &opponent_account, &team, &opponent_team, 1, secret)],
+    &[&user],
+  )
+  .await;
+
+  assert_game_error(result, GameError::InvalidCommit);
+}
+
+#[tokio::test]
+async fn challenge_rejects_lineup_changed_after_commit() {
+  let mut context = start().await;
+  let (user, user_account, team) = challenger(&mut context, "alice").await;
+  let (_, opponent_account, opponent_team) = challenger(&mut context, "bob").await;
+  let secret: [u8; 32] = Hash::new_unique().to_bytes();
+  commit_challenge(&mut context, &user, &user_account, &opponent_account, secret).await;
+  let players: [Pubkey; TEAM_SIZE] = team.map(|nft| instruction::find_player_address(&program_id(), &nft));
+  set_team(&mut context, &user, &user_account, Formation::ThreeOneOne, &players, &[]).await.unwrap();
+
+  let result = send_challenge(&mut context, &user, &user_account, &opponent_account, &team, &opponent_team, 1, secret).await;
+
+  assert_game_error(result, GameError::CommitmentMismatch);
+  assert_eq!(get_user(&mut context, &user_account).await.wins, 0);
+}
+
+#[tokio::test]
+async fn challenge_rejects_player_account_as_opponent() {
+  let mut context = start().await;
+  let (user, user_account) = user_with_team(&mut context, "alice", 500).await;
+  let (player, _, _) = generate(&mut context, &user).await;
+  let team: [Pubkey; TEAM_SIZE] = [Pubkey::default(); TEAM_SIZE];
+
+  let result = send_challenge(&mut context, &user, &user_account, &player, &team, &team, 1, [0; 32]).await;
+
+  assert_game_error(result, GameError::InvalidAccountType);
+}
+
+#[tokio::test]
+async fn refresh_team_recomputes_team_power() {
+  let mut context = start().await;
+  let (_, user_account, team) = challenger(&mut context, "alice").await;
+  let players: [Pubkey; TEAM_SIZE] = team.map(|nft| instruction::find_player_address(&program_id(), &nft));
+  let mut data: Player = get_player(&mut context, &players[2]).await;
+  data.offence += 5;
+  set_player(&mut context, &players[2], &data).await;
+
+  refresh_team(&mut context, &user_account, &team).await.unwrap();
+
+  let data: UserAccount = get_user(&mut context, &user_account).await;
+  assert_eq!(data.team_status, TeamStatus::Ready);
+  assert_eq!(data.team_power, compute_team_power(Formation::TwoOneTwo, &get_team(&mut context, &players).await));
+}
+
+#[tokio::test]
+async fn refresh_team_drops_team_with_sold_player() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+  let user_account: Pubkey = register(&mut context, &user, "alice").await;
+  let (team, owner, treasury) = team_with_rented_player(&mut context, &user, &user_account).await;
+  let (_, opponent_account, opponent_team) = challenger(&mut context, "bob").await;
+  end_rental(&mut context, &owner, &team[4], &user.pubkey()).await;
+  let sold: Pubkey = instruction::find_player_address(&program_id(), &team[4]);
+  let owner_ata: Pubkey = spl_associated_token_account::get_associated_token_address(&owner.pubkey(), &team[4]);
+  list_for_sale(&mut context, &owner, &sold, &team[4], &owner_ata, PRICE).await.unwrap();
+  let buyer: Keypair = create_user(&mut context).await;
+  let buyer_ata: Pubkey = create_ata(&mut context, &buyer.pubkey(), &team[4]).await;
+  process(
+    &mut context,
+    &[instruction::buy_player(
+      &program_id(),
+      &buyer.pubkey(),
+      &sold,
+      &team[4],
+      &owner.pubkey(),
+      &instruction::find_vault_address(&program_id()),
+      &escrow_address(&team[4]),
+      &buyer_ata,
+      &treasury,
+      &sale_book(PRICE),
+      None,
+      None,
+    )],
+    &[&buyer],
+  )
+  .await
+  .unwrap();
+
+  refresh_team(&mut context, &user_account, &team).await.unwrap();
+
+  let data: UserAccount = get_user(&mut context, &user_account).await;
+  assert_eq!(data.team_status, TeamStatus::NoTeam);
+  assert_eq!(data.team_power, 0);
+  assert_eq!(data.team, team.map(|nft| nft.to_bytes()));
+  let result = challenge(&mut context, &user, &user_account, &opponent_account, &team, &opponent_team, 1).await;
+  assert_game_error(result, GameError::TeamNotReady);
+}
+
+#[tokio::test]
+async fn refresh_team_drops_team_with_closed_player() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+  let user_account: Pubkey = register(&mut context, &user, "alice").await;
+  let (team, owner, _) = team_with_rented_player(&mut context, &user, &user_account).await;
+  end_rental(&mut context, &owner, &team[4], &user.pubkey()).await;
+  process(
+    &mut context,
+    &[instruction::close_player(&program_id(), &owner.pubkey(), &team[4], &owner.pubkey())],
+    &[&owner],
+  )
+  .await
+  .unwrap();
+
+  refresh_team(&mut context, &user_account, &team).await.unwrap();
+
+  assert_eq!(get_user(&mut context, &user_account).await.team_status, TeamStatus::NoTeam);
+}
+
+#[tokio::test]
+async fn refresh_team_rejects_players_of_other_slots() {
+  let mut context = start().await;
+  let (_, user_account, mut team) = challenger(&mut context, "alice").await;
+  team.swap(0, 4);
+
+  let result = refresh_team(&mut context, &user_account, &team).await;
+
+  assert_game_error(result, GameError::PlayerNotInTeam);
+  assert_eq!(get_user(&mut context, &user_account).await.team_status, TeamStatus::Ready);
+}
+
+#[tokio::test]
+async fn upgrade_rejects_unknown_layout_version() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+  let user_account: Pubkey = register(&mut context, &user, "alice").await;
+  let (player, _, _) = generate(&mut context, &user).await;
+  let mut account = get_account(&mut context, &user_account).await;
+  account.data[DISCRIMINATOR_LEN] = UserAccount::VERSION + 1;
+  context.set_account(&user_account, &account.into());
+
+  let result = upgrade(&mut context, &user, &[player; TEAM_SIZE], 0, 4).await;
+
+  assert_game_error(result, GameError::UnsupportedAccountVersion);
+}
+
+#[tokio::test]
+async fn upgrade_spends_experience_on_team_player() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+  let user_account: Pubkey = register(&mut context, &user, "alice").await;
+  let (team, nfts) = generate_team(&mut context, &user).await;
+  set_team(&mut context, &user, &user_account, Formation::ThreeOneOne, &team, &[]).await.unwrap();
+  let before: Player = get_player(&mut context, &team[3]).await;
+  let mut data: UserAccount = get_user(&mut context, &user_account).await;
+  let power_before: u64 = data.team_power;
+  data.experienced = 10;
+  set_user(&mut context, &user_account, &data).await;
+
+  upgrade(&mut context, &user, &team, 3, 4).await.unwrap();
+
+  let after: Player = get_player(&mut context, &team[3]).await;
+  assert_eq!(after.offence, before.offence + 4);
+  assert_eq!(after.defence, before.defence + 4);
+  let data: UserAccount = get_user(&mut context, &user_account).await;
+  assert_eq!(data.experienced, 6);
+  //slot 3 is the middle of a 3-1-1, weighed 3 for offence and defence
+  assert_eq!(data.team_power, power_before + 4 * 3 + 4 * 3);
+
+  //setting the same team again computes the same power
+  set_team(&mut context, &user, &user_account, Formation::ThreeOneOne, &team, &nfts).await.unwrap();
+  assert_eq!(get_user(&mut context, &user_account).await.team_power, data.team_power);
+}
+
+#[tokio::test]
+async fn upgrade_rejects_missing_experience() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+  register(&mut context, &user, "alice").await;
+  let (player, _, _) = generate(&mut context, &user).await;
+
+  let result = upgrade(&mut context, &user, &[player; TEAM_SIZE], 0, 4).await;
+
+  assert_game_error(result, GameError::NotEnoughExperience);
+}
+
+#[tokio::test]
+async fn upgrade_rejects_player_outside_team() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+  let user_account: Pubkey = register(&mut context, &user, "alice").await;
+  let (mut team, _) = generate_team(&mut context, &user).await;
+  set_team(&mut context, &user, &user_account, Formation::TwoOneTwo, &team, &[]).await.unwrap();
+  let mut data: UserAccount = get_user(&mut context, &user_account).await;
+  data.experienced = 10;
+  set_user(&mut context, &user_account, &data).await;
+  (team[0], _, _) = generate(&mut context, &user).await;
+
+  let result = upgrade(&mut context, &user, &team, 0, 4).await;
+
+  assert_game_error(result, GameError::PlayerNotInTeam);
+}
+
+#[tokio::test]
+async fn upgrade_rejects_user_without_team() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+  let user_account: Pubkey = register(&mut context, &user, "alice").await;
+  let (player, _, _) = generate(&mut context, &user).await;
+  let mut data: UserAccount = get_user(&mut context, &user_account).await;
+  data.experienced = 10;
+  set_user(&mut context, &user_account, &data).await;
+
+  let result = upgrade(&mut context, &user, &[player; TEAM_SIZE], 0, 4).await;
+
+  assert_game_error(result, GameError::TeamNotReady);
+}
+
+#[tokio::test]
+async fn upgrade_rejects_player_whose_rental_expired() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+  let user_account: Pubkey = register(&mut context, &user, "alice").await;
+  let (team, _, _) = team_with_rented_player(&mut context, &user, &user_account).await;
+  let players: [Pubkey; TEAM_SIZE] = team.map(|nft| instruction::find_player_address(&program_id(), &nft));
+  let before: Player = get_player(&mut context, &players[4]).await;
+  let mut data: UserAccount = get_user(&mut context, &user_account).await;
+  data.experienced = 10;
+  set_user(&mut context, &user_account, &data).await;
+  let timestamp: i64 = now(&mut context).await;
+  set_clock(&mut context, timestamp + 3600).await;
+
+  let result = upgrade(&mut context, &user, &players, 4, 4).await;
+
+  assert_game_error(result, GameError::TeamNotEligible);
+  assert_eq!(get_player(&mut context, &players[4]).await.offence, before.offence);
+}
+
+#[tokio::test]
+async fn upgrade_rejects_players_in_other_slots() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+  let user_account: Pubkey = register(&mut context, &user, "alice").await;
+  let (mut team, _) = generate_team(&mut context, &user).await;
+  set_team(&mut context, &user, &user_account, Formation::TwoOneTwo, &team, &[]).await.unwrap();
+  let mut data: UserAccount = get_user(&mut context, &user_account).await;
+  data.experienced = 10;
+  set_user(&mut context, &user_account, &data).await;
+  team.swap(0, 3);
+
+  let result = upgrade(&mut context, &user, &team, 0, 4).await;
+
+  assert_game_error(result, GameError::PlayerNotInTeam);
+}
+
+#[tokio::test]
+async fn upgrade_rejects_slot_outside_formation() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+  let user_account: Pubkey = register(&mut context, &user, "alice").await;
+  let (player, _, _) = generate(&mut context, &user).await;
+  let mut data: UserAccount = get_user(&mut context, &user_account).await;
+  data.experienced = 10;
+  set_user(&mut context, &user_account, &data).await;
+
+  let result = upgrade(&mut context, &user, &[player; TEAM_SIZE], TEAM_SIZE as u8, 4).await;
+
+  assert_game_error(result, GameError::InvalidTeamSlot);
+}
@@ -0,0 +1,105 @@
+mod common;
+
+use common::*;
+use sol_battle_balls::{
+  error::GameError,
+  instruction,
+  state::{ProgramAccount, TeamStatus, UserAccount, DISCRIMINATOR_LEN, INITIAL_RATING},
+};
+use solana_program::{pubkey::Pubkey, rent::Rent};
+use solana_program_test::tokio;
+use solana_sdk::signature::{Keypair, Signer};
+
+#[tokio::test]
+async fn register_user_pads_name_and_starts_rating() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+
+  let user_account: Pubkey = register(&mut context, &user, "striker").await;
+
+  let data: UserAccount = get_user(&mut context, &user_account).await;
+  assert_eq!(data.user_address, user.pubkey().to_bytes());
+  assert_eq!(data.user_name, "strikerXXX");
+  assert_eq!(data.user_name_length, 7);
+  assert_eq!(data.team_status, TeamStatus::NoTeam);
+  assert_eq!(data.rating, INITIAL_RATING);
+
+  let raw: Vec<u8> = get_account(&mut context, &user_account).await.data;
+  assert_eq!(raw[..DISCRIMINATOR_LEN], UserAccount::DISCRIMINATOR);
+  assert_eq!(raw[DISCRIMINATOR_LEN], UserAccount::VERSION);
+}
+
+#[tokio::test]
+async fn register_user_creates_rent_exempt_pda() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+
+  let user_account: Pubkey = register(&mut context, &user, "keeper").await;
+
+  let account = get_account(&mut context, &user_account).await;
+  assert_eq!(account.owner, program_id());
+  assert_eq!(account.lamports, Rent::default().minimum_balance(account.data.len()));
+  let data: UserAccount = UserAccount::unpack(&account.data).unwrap();
+  assert_eq!(account.data.len(), data.packed_len().unwrap());
+}
+
+#[tokio::test]
+async fn register_user_succeeds_when_pda_was_prefunded() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+  let user_account: Pubkey = instruction::find_user_address(&program_id(), &user.pubkey());
+  prefund(&mut context, &user_account).await;
+
+  register(&mut context, &user, "keeper").await;
+
+  let account = get_account(&mut context, &user_account).await;
+  assert_eq!(account.owner, program_id());
+  assert_eq!(account.lamports, Rent::default().minimum_balance(account.data.len()));
+  let data: UserAccount = UserAccount::unpack(&account.data).unwrap();
+  assert_eq!(data.user_address, user.pubkey().to_bytes());
+}
+
+#[tokio::test]
+async fn register_user_rejects_long_name() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+
+  let result = process(
+    &mut context,
+    &[instruction::register_user(&program_id(), &user.pubkey(), "elevenchars".to_string())],
+    &[&user],
+  )
+  .await;
+
+  assert_game_error(result, GameError::UserNameTooLong);
+}
+
+#[tokio::test]
+async fn register_user_rejects_account_not_derived_from_user() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+  let mut register_user = instruction::register_user(&program_id(), &user.pubkey(), "keeper".to_string());
+  register_user.accounts[1].pubkey = Pubkey::new_unique();
+
+  let result = process(&mut context, &[register_user], &[&user]).await;
+
+  assert_game_error(result, GameError::InvalidUserAccount);
+}
+
+#[tokio::test]
+async fn register_user_rejects_second_registration() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+  register(&mut context, &user, "keeper").await;
+
+  let result = process(
+    &mut context,
+    &[instruction::register_user(&program_id(), &user.pubkey(), "striker".to_string())],
+    &[&user],
+  )
+  .await;
+
+  assert!(result.is_err());
+  let data: UserAccount = get_user(&mut context, &instruction::find_user_address(&program_id(), &user.pubkey())).await;
+  assert_eq!(data.user_name, "keeperXXXX");
+}
@@ -0,0 +1,218 @@
+mod common;
+
+use common::*;
+use sol_battle_balls::{
+  error::GameError,
+  instruction,
+  state::{ProgramAccount, UserAccount, UsernameRecord},
+};
+use solana_program::pubkey::Pubkey;
+use solana_program_test::{tokio, BanksClientError, ProgramTestContext};
+use solana_sdk::signature::{Keypair, Signer};
+
+async fn get_record(context: &mut ProgramTestContext, user_name: &str) -> UsernameRecord {
+  let record_address: Pubkey = instruction::find_username_address(&program_id(), user_name);
+  UsernameRecord::unpack(&get_account(context, &record_address).await.data).unwrap()
+}
+
+async fn try_register(context: &mut ProgramTestContext, user: &Keypair, name: &str) -> Result<(), BanksClientError> {
+  process(
+    context,
+    &[instruction::register_user(&program_id(), &user.pubkey(), name.to_string())],
+    &[user],
+  )
+  .await
+}
+
+async fn rename(
+  context: &mut ProgramTestContext,
+  user: &Keypair,
+  old_name: &str,
+  name: &str,
+) -> Result<(), BanksClientError> {
+  process(
+    context,
+    &[instruction::rename_user(&program_id(), &user.pubkey(), old_name, name.to_string())],
+    &[user],
+  )
+  .await
+}
+
+#[tokio::test]
+async fn register_user_creates_username_record() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+
+  register(&mut context, &user, "Keeper").await;
+
+  let record: UsernameRecord = get_record(&mut context, "keeper").await;
+  assert_eq!(record.owner, user.pubkey().to_bytes());
+}
+
+#[tokio::test]
+async fn register_user_rejects_taken_name_in_any_case() {
+  let mut context = start().await;
+  let first: Keypair = create_user(&mut context).await;
+  let second: Keypair = create_user(&mut context).await;
+  register(&mut context, &first, "keeper").await;
+
+  let result = try_register(&mut context, &second, "KEEPER").await;
+
+  assert_game_error(result, GameError::UserNameTaken);
+  let second_account: Pubkey = instruction::find_user_address(&program_id(), &second.pubkey());
+  assert!(!account_exists(&mut context, &second_account).await);
+}
+
+#[tokio::test]
+async fn register_user_claims_name_squatted_with_lamports() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+  let record_address: Pubkey = instruction::find_username_address(&program_id(), "keeper");
+  prefund(&mut context, &record_address).await;
+
+  register(&mut context, &user, "keeper").await;
+
+  let record: UsernameRecord = get_record(&mut context, "keeper").await;
+  assert_eq!(record.owner, user.pubkey().to_bytes());
+  assert_eq!(get_account(&mut context, &record_address).await.owner, program_id());
+}
+
+#[tokio::test]
+async fn register_user_rejects_short_name() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+
+  let result = try_register(&mut context, &user, "ab").await;
+
+  assert_game_error(result, GameError::UserNameTooShort);
+}
+
+#[tokio::test]
+async fn register_user_rejects_invalid_characters() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+
+  for name in ["kee per", "keeper!", "kéeper"] {
+    let result = try_register(&mut context, &user, name).await;
+    assert_game_error(result, GameError::InvalidUserNameCharacter);
+  }
+}
+
+#[tokio::test]
+async fn register_user_rejects_record_of_another_name() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+  let mut register_user = instruction::register_user(&program_id(), &user.pubkey(), "keeper".to_string());
+  register_user.accounts[3].pubkey = instruction::find_username_address(&program_id(), "striker");
+
+  let result = process(&mut context, &[register_user], &[&user]).await;
+
+  assert_game_error(result, GameError::InvalidUsernameRecord);
+}
+
+#[tokio::test]
+async fn rename_user_moves_record_and_refunds_old_one() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+  let user_account: Pubkey = register(&mut context, &user, "keeper").await;
+  let old_record: Pubkey = instruction::find_username_address(&program_id(), "keeper");
+
+  rename(&mut context, &user, "keeper", "striker_9").await.unwrap();
+
+  let data: UserAccount = get_user(&mut context, &user_account).await;
+  assert_eq!(data.user_name, "striker_9X");
+  assert_eq!(data.user_name_length, 9);
+  assert!(!account_exists(&mut context, &old_record).await);
+  let record: UsernameRecord = get_record(&mut context, "striker_9").await;
+  assert_eq!(record.owner, user.pubkey().to_bytes());
+}
+
+#[tokio::test]
+async fn rename_user_without_old_record_claims_new_name() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+  let user_account: Pubkey = register(&mut context, &user, "keeper").await;
+  remove_account(&mut context, &instruction::find_username_address(&program_id(), "keeper"));
+
+  rename(&mut context, &user, "keeper", "striker").await.unwrap();
+
+  let data: UserAccount = get_user(&mut context, &user_account).await;
+  assert_eq!(data.user_name, "strikerXXX");
+  let record: UsernameRecord = get_record(&mut context, "striker").await;
+  assert_eq!(record.owner, user.pubkey().to_bytes());
+}
+
+#[tokio::test]
+async fn rename_user_releases_old_name_for_others() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+  let other: Keypair = create_user(&mut context).await;
+  register(&mut context, &user, "keeper").await;
+  rename(&mut context, &user, "keeper", "striker").await.unwrap();
+
+  register(&mut context, &other, "keeper").await;
+
+  let record: UsernameRecord = get_record(&mut context, "keeper").await;
+  assert_eq!(record.owner, other.pubkey().to_bytes());
+}
+
+#[tokio::test]
+async fn rename_user_changes_case_without_new_record() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+  let user_account: Pubkey = register(&mut context, &user, "keeper").await;
+
+  rename(&mut context, &user, "keeper", "KeePer").await.unwrap();
+
+  let data: UserAccount = get_user(&mut context, &user_account).await;
+  assert_eq!(data.user_name, "KeePerXXXX");
+  let record: UsernameRecord = get_record(&mut context, "keeper").await;
+  assert_eq!(record.owner, user.pubkey().to_bytes());
+}
+
+#[tokio::test]
+async fn rename_user_rejects_taken_name() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+  let other: Keypair = create_user(&mut context).await;
+  register(&mut context, &user, "keeper").await;
+  register(&mut context, &other, "striker").await;
+
+  let result = rename(&mut context, &user, "keeper", "Striker").await;
+
+  assert_game_error(result, GameError::UserNameTaken);
+  let record: UsernameRecord = get_record(&mut context, "keeper").await;
+  assert_eq!(record.owner, user.pubkey().to_bytes());
+}
+
+#[tokio::test]
+async fn rename_user_rejects_wrong_old_record() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+  let other: Keypair = create_user(&mut context).await;
+  register(&mut context, &user, "keeper").await;
+  register(&mut context, &other, "striker").await;
+
+  let result = rename(&mut context, &user, "striker", "goalie").await;
+
+  assert_game_error(result, GameError::InvalidUsernameRecord);
+  let record: UsernameRecord = get_record(&mut context, "striker").await;
+  assert_eq!(record.owner, other.pubkey().to_bytes());
+}
+
+#[tokio::test]
+async fn rename_user_validates_new_name() {
+  let mut context = start().await;
+  let user: Keypair = create_user(&mut context).await;
+  register(&mut context, &user, "keeper").await;
+
+  assert_game_error(rename(&mut context, &user, "keeper", "no").await, GameError::UserNameTooShort);
+  assert_game_error(
+    rename(&mut context, &user, "keeper", "elevenchars").await,
+    GameError::UserNameTooLong,
+  );
+  assert_game_error(
+    rename(&mut context, &user, "keeper", "no-dash").await,
+    GameError::InvalidUserNameCharacter,
+  );
+}